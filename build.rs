@@ -456,6 +456,15 @@ fn build_grpc_stubs() {
                 .codec_path(json_codec)
                 .build(),
         )
+        .method(
+            tonic_build::manual::Method::builder()
+                .name("place_options_strategy_order")
+                .route_name("PlaceOptionsStrategyOrder")
+                .input_type("crate::oms::PlaceOptionsStrategyOrderRequest")
+                .output_type("crate::orderflow::Order")
+                .codec_path(json_codec)
+                .build(),
+        )
         .method(
             tonic_build::manual::Method::builder()
                 .name("cancel_order")
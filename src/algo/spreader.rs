@@ -1,5 +1,6 @@
 use super::*;
 use crate::{
+    marketdata::spread_pricing::{SpreadPricer, SpreadPricingLeg},
     symbology::{ExecutionVenue, MarketdataVenue},
     AccountIdOrName, Dir, HumanDuration,
 };
@@ -39,6 +40,19 @@ pub struct SpreaderParams {
     pub leg2_quantity_ratio: Decimal,
 }
 
+impl SpreaderParams {
+    /// Pricer for the implied spread, without tick sizes; attach them with
+    /// [`SpreadPricer::with_leg_tick_sizes`].
+    pub fn spread_pricer(&self) -> Result<SpreadPricer> {
+        SpreadPricer::new(vec![
+            SpreadPricingLeg::new(self.leg1_price_ratio, self.leg1_quantity_ratio.abs())
+                .with_price_offset(self.leg1_price_offset),
+            SpreadPricingLeg::new(self.leg2_price_ratio, self.leg2_quantity_ratio.abs())
+                .with_price_offset(self.leg2_price_offset),
+        ])
+    }
+}
+
 impl DisplaySymbols for SpreaderParams {
     fn display_symbols(&self) -> Option<Vec<String>> {
         Some(vec![self.leg1_symbol.clone(), self.leg2_symbol.clone()])
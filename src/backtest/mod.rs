@@ -0,0 +1,886 @@
+//! Historical backtest replay.
+//!
+//! A [`Replay`] merges recorded marketdata sources into one stream ordered
+//! by timestamp.  A [`Backtest`] drives that stream through a
+//! [`BacktestStrategy`], simulates fills for the strategy's orders against
+//! the replayed market, and reports the resulting [`Fill`]s and
+//! [`AccountSummary`].
+//!
+//! Fill model:
+//!
+//! - Orders that cross the book on arrival take displayed L2 liquidity, or
+//!   fill at the reference price if no book is being replayed.  Liquidity
+//!   taken by the strategy is not removed from the replayed book.
+//! - Resting limit orders fill passively at their limit price when a
+//!   trade prints through the price (up to the trade size), when a candle
+//!   trades through it, or when the opposite side of the book reaches it.
+//! - Only market and limit orders are supported.
+
+use crate::{
+    folio::{AccountSummary, CostBasisMethod, PositionKeeper},
+    marketdata::{Candle, L2Book, L2BookUpdate, Ticker, Trade},
+    oms::PlaceOrderRequest,
+    orderflow::{
+        Fill, FillKind, Modify, ModifyStatus, Order, OrderAck, OrderCanceled,
+        OrderModified, OrderOut, OrderReject, OrderRejectReason, OrderSource,
+        OrderStatus, OrderTracker, OrderType, Orderflow, OrderflowRequest, TimeInForce,
+    },
+    symbology::{ExecutionVenue, TradableProduct},
+    AccountId, Dir, OrderId, UserId,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, VecDeque},
+    time::Duration,
+};
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum MarketdataEvent {
+    /// L2 updates don't carry their symbol, so it's attached here.
+    L2BookUpdate {
+        symbol: String,
+        update: L2BookUpdate,
+    },
+    Trade(Trade),
+    Candle(Candle),
+    Ticker(Ticker),
+}
+
+impl MarketdataEvent {
+    pub fn symbol(&self) -> &str {
+        match self {
+            Self::L2BookUpdate { symbol, .. } => symbol,
+            Self::Trade(trade) => &trade.symbol,
+            Self::Candle(candle) => &candle.symbol,
+            Self::Ticker(ticker) => &ticker.symbol,
+        }
+    }
+
+    /// (timestamp, timestamp_ns), the replay sort key
+    pub fn timestamp_key(&self) -> (i64, u32) {
+        match self {
+            Self::L2BookUpdate { update: L2BookUpdate::Snapshot(s), .. } => {
+                (s.timestamp, s.timestamp_ns)
+            }
+            Self::L2BookUpdate { update: L2BookUpdate::Diff(d), .. } => {
+                (d.timestamp, d.timestamp_ns)
+            }
+            Self::Trade(trade) => (trade.timestamp, trade.timestamp_ns),
+            Self::Candle(candle) => (candle.timestamp, candle.timestamp_ns),
+            Self::Ticker(ticker) => (ticker.timestamp, ticker.timestamp_ns),
+        }
+    }
+
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        let (ts, tn) = self.timestamp_key();
+        DateTime::from_timestamp(ts, tn)
+    }
+}
+
+impl From<Trade> for MarketdataEvent {
+    fn from(trade: Trade) -> Self {
+        Self::Trade(trade)
+    }
+}
+
+impl From<Candle> for MarketdataEvent {
+    fn from(candle: Candle) -> Self {
+        Self::Candle(candle)
+    }
+}
+
+impl From<Ticker> for MarketdataEvent {
+    fn from(ticker: Ticker) -> Self {
+        Self::Ticker(ticker)
+    }
+}
+
+/// Merge of several recorded sources, each already in timestamp order.
+///
+/// Events with equal timestamps are yielded in the order their sources
+/// were added.
+#[derive(Default)]
+pub struct Replay {
+    sources: Vec<Box<dyn Iterator<Item = MarketdataEvent>>>,
+    heads: BinaryHeap<Reverse<((i64, u32), usize)>>,
+    peeked: Vec<Option<MarketdataEvent>>,
+}
+
+impl Replay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_source<I>(&mut self, source: I)
+    where
+        I: IntoIterator<Item = MarketdataEvent>,
+        I::IntoIter: 'static,
+    {
+        let index = self.sources.len();
+        self.sources.push(Box::new(source.into_iter()));
+        self.peeked.push(None);
+        self.advance(index);
+    }
+
+    pub fn with_source<I>(mut self, source: I) -> Self
+    where
+        I: IntoIterator<Item = MarketdataEvent>,
+        I::IntoIter: 'static,
+    {
+        self.add_source(source);
+        self
+    }
+
+    /// Convenience for a recorded L2 stream for one symbol.
+    pub fn add_l2_source<I>(&mut self, symbol: impl Into<String>, updates: I)
+    where
+        I: IntoIterator<Item = L2BookUpdate>,
+        I::IntoIter: 'static,
+    {
+        let symbol = symbol.into();
+        self.add_source(updates.into_iter().map(move |update| {
+            MarketdataEvent::L2BookUpdate { symbol: symbol.clone(), update }
+        }));
+    }
+
+    /// Timestamp of the next event, without consuming it.
+    pub fn peek_timestamp(&self) -> Option<(i64, u32)> {
+        self.heads.peek().map(|Reverse((key, _))| *key)
+    }
+
+    fn advance(&mut self, index: usize) {
+        if let Some(event) = self.sources[index].next() {
+            self.heads.push(Reverse((event.timestamp_key(), index)));
+            self.peeked[index] = Some(event);
+        }
+    }
+}
+
+impl Iterator for Replay {
+    type Item = MarketdataEvent;
+
+    fn next(&mut self) -> Option<MarketdataEvent> {
+        let Reverse((_, index)) = self.heads.pop()?;
+        let event = self.peeked[index].take();
+        self.advance(index);
+        event
+    }
+}
+
+/// Latest replayed market state per symbol.
+#[derive(Debug, Default, Clone)]
+pub struct MarketState {
+    pub books: BTreeMap<String, L2Book>,
+    pub last_trades: BTreeMap<String, Trade>,
+    pub last_candles: BTreeMap<String, Candle>,
+    pub tickers: BTreeMap<String, Ticker>,
+    /// Most recent trade, candle close or ticker price
+    pub last_prices: BTreeMap<String, Decimal>,
+}
+
+impl MarketState {
+    pub fn apply(&mut self, event: &MarketdataEvent) {
+        match event {
+            MarketdataEvent::L2BookUpdate { symbol, update } => {
+                let book = self.books.entry(symbol.clone()).or_default();
+                if book.apply(update).is_err() {
+                    // out of sequence; wait for the next snapshot
+                    book.clear();
+                }
+            }
+            MarketdataEvent::Trade(trade) => {
+                self.last_prices.insert(trade.symbol.clone(), trade.price);
+                self.last_trades.insert(trade.symbol.clone(), trade.clone());
+            }
+            MarketdataEvent::Candle(candle) => {
+                if let Some(close) = candle.close {
+                    self.last_prices.insert(candle.symbol.clone(), close);
+                }
+                self.last_candles.insert(candle.symbol.clone(), candle.clone());
+            }
+            MarketdataEvent::Ticker(ticker) => {
+                if let Some(price) = ticker.last_or_mid_price() {
+                    self.last_prices.insert(ticker.symbol.clone(), price);
+                }
+                self.tickers.insert(ticker.symbol.clone(), ticker.clone());
+            }
+        }
+    }
+
+    pub fn book(&self, symbol: &str) -> Option<&L2Book> {
+        self.books.get(symbol).filter(|b| b.is_initialized())
+    }
+
+    /// Book mid if a book is being replayed, else the last price.
+    pub fn reference_price(&self, symbol: &str) -> Option<Decimal> {
+        self.book(symbol)
+            .and_then(|b| b.mid_price())
+            .or_else(|| self.last_prices.get(symbol).copied())
+    }
+}
+
+pub trait BacktestStrategy {
+    fn on_marketdata(&mut self, ctx: &mut BacktestContext, event: &MarketdataEvent);
+
+    fn on_orderflow(&mut self, _ctx: &mut BacktestContext, _event: &Orderflow) {}
+}
+
+/// What a strategy can see and do during a callback.  Requests are
+/// processed after the callback returns.
+pub struct BacktestContext<'a> {
+    now: DateTime<Utc>,
+    market: &'a MarketState,
+    orders: &'a OrderTracker,
+    requests: &'a mut Vec<OrderflowRequest>,
+}
+
+impl BacktestContext<'_> {
+    pub fn now(&self) -> DateTime<Utc> {
+        self.now
+    }
+
+    pub fn market(&self) -> &MarketState {
+        self.market
+    }
+
+    pub fn orders(&self) -> &OrderTracker {
+        self.orders
+    }
+
+    pub fn send(&mut self, request: OrderflowRequest) {
+        self.requests.push(request);
+    }
+
+    pub fn place_order(&mut self, request: PlaceOrderRequest) {
+        self.send(OrderflowRequest::PlaceOrder(request));
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BacktestResult {
+    pub fills: Vec<Fill>,
+    pub account_summary: AccountSummary,
+}
+
+pub struct Backtest {
+    replay: Replay,
+    market: MarketState,
+    orders: OrderTracker,
+    positions: PositionKeeper,
+    fills: Vec<Fill>,
+    /// Resting orders in time priority
+    resting: Vec<OrderId>,
+    requests: Vec<OrderflowRequest>,
+    orderflow: VecDeque<Orderflow>,
+    now: DateTime<Utc>,
+    last_event_time: Option<DateTime<Utc>>,
+    seqno: u64,
+    pub account: AccountId,
+    pub trader: UserId,
+    pub execution_venue: ExecutionVenue,
+    pub maker_fee_rate: Decimal,
+    pub taker_fee_rate: Decimal,
+    /// Replay speed relative to recorded time; None for as fast as possible
+    pub speed: Option<f64>,
+}
+
+impl Backtest {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            market: MarketState::default(),
+            orders: OrderTracker::new(),
+            positions: PositionKeeper::new(CostBasisMethod::default()),
+            fills: vec![],
+            resting: vec![],
+            requests: vec![],
+            orderflow: VecDeque::new(),
+            now: DateTime::<Utc>::MIN_UTC,
+            last_event_time: None,
+            seqno: 0,
+            account: AccountId::nil(),
+            trader: UserId::anonymous(),
+            execution_venue: ExecutionVenue::from("BACKTEST"),
+            maker_fee_rate: Decimal::ZERO,
+            taker_fee_rate: Decimal::ZERO,
+            speed: None,
+        }
+    }
+
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        self.speed = Some(speed);
+        self
+    }
+
+    pub fn with_fees(mut self, maker_fee_rate: Decimal, taker_fee_rate: Decimal) -> Self {
+        self.maker_fee_rate = maker_fee_rate;
+        self.taker_fee_rate = taker_fee_rate;
+        self
+    }
+
+    /// Simulated time: the timestamp of the last replayed event
+    pub fn now(&self) -> DateTime<Utc> {
+        self.now
+    }
+
+    pub fn market(&self) -> &MarketState {
+        &self.market
+    }
+
+    pub fn orders(&self) -> &OrderTracker {
+        &self.orders
+    }
+
+    pub fn positions(&self) -> &PositionKeeper {
+        &self.positions
+    }
+
+    pub fn fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
+    /// Replay a single event, returning it, or None at the end of the data.
+    pub fn step(
+        &mut self,
+        strategy: &mut impl BacktestStrategy,
+    ) -> Option<MarketdataEvent> {
+        let event = self.replay.next()?;
+        if let Some(t) = event.timestamp() {
+            self.pace(t);
+            self.now = self.now.max(t);
+        }
+        self.market.apply(&event);
+        if let Some(price) = self.market.reference_price(event.symbol()) {
+            self.positions.set_mark(TradableProduct(event.symbol().to_string()), price);
+        }
+        self.fill_resting(&event);
+        self.drain(strategy);
+        let mut ctx = BacktestContext {
+            now: self.now,
+            market: &self.market,
+            orders: &self.orders,
+            requests: &mut self.requests,
+        };
+        strategy.on_marketdata(&mut ctx, &event);
+        self.drain(strategy);
+        Some(event)
+    }
+
+    /// Replay all remaining events and produce the result.
+    pub fn run(mut self, strategy: &mut impl BacktestStrategy) -> BacktestResult {
+        while self.step(strategy).is_some() {}
+        self.finish()
+    }
+
+    pub fn finish(self) -> BacktestResult {
+        BacktestResult {
+            account_summary: self.positions.account_summary(self.account, self.now),
+            fills: self.fills,
+        }
+    }
+
+    fn pace(&mut self, t: DateTime<Utc>) {
+        if let (Some(speed), Some(last)) = (self.speed, self.last_event_time) {
+            if let Ok(dt) = (t - last).to_std() {
+                if speed > 0. {
+                    std::thread::sleep(Duration::from_secs_f64(dt.as_secs_f64() / speed));
+                }
+            }
+        }
+        self.last_event_time = Some(t);
+    }
+
+    /// Process requests and orderflow until the strategy goes quiet.
+    fn drain(&mut self, strategy: &mut impl BacktestStrategy) {
+        loop {
+            for request in std::mem::take(&mut self.requests) {
+                self.handle_request(request);
+            }
+            let Some(event) = self.orderflow.pop_front() else { break };
+            let mut ctx = BacktestContext {
+                now: self.now,
+                market: &self.market,
+                orders: &self.orders,
+                requests: &mut self.requests,
+            };
+            strategy.on_orderflow(&mut ctx, &event);
+        }
+    }
+
+    fn next_seqno(&mut self) -> u64 {
+        self.seqno += 1;
+        self.seqno
+    }
+
+    /// Apply orderflow to internal state and queue it for the strategy.
+    fn emit(&mut self, event: Orderflow) {
+        // events are generated consistently with the tracker's state; an
+        // error here is a bug in the simulated venue
+        if let Err(e) = self.orders.apply(&event) {
+            panic!("backtest emitted orderflow the order tracker rejects: {e}");
+        }
+        if let Orderflow::Fill(fill) = &event {
+            self.positions.apply_fill(fill);
+            self.fills.push(fill.clone());
+        }
+        if let Some(order_id) = event.order_id() {
+            if self.orders.get(&order_id).is_some_and(|o| o.status.is_dead()) {
+                self.resting.retain(|id| *id != order_id);
+            }
+        }
+        self.orderflow.push_back(event);
+    }
+
+    fn handle_request(&mut self, request: OrderflowRequest) {
+        match request {
+            OrderflowRequest::PlaceOrder(request) => self.place_order(request),
+            OrderflowRequest::PlaceBatchOrder(batch) => {
+                for request in batch.place_orders {
+                    self.place_order(request);
+                }
+            }
+            OrderflowRequest::CancelOrder(cancel) => {
+                self.cancel_order(cancel.order_id, cancel.cancel_id)
+            }
+            OrderflowRequest::BatchCancelOrders(batch) => {
+                for cancel in batch.cancel_orders {
+                    self.cancel_order(cancel.order_id, cancel.cancel_id);
+                }
+            }
+            OrderflowRequest::CancelAllOrders(cancel) => {
+                for order_id in self.resting.clone() {
+                    self.cancel_order(order_id, Some(cancel.id));
+                }
+            }
+            OrderflowRequest::ModifyOrder(modify) => {
+                let new_order_id = OrderId::nil(self.next_seqno());
+                let modify = Modify {
+                    modify_id: modify.modify_id.unwrap_or_else(Uuid::new_v4),
+                    order_id: modify.order_id,
+                    new_order_id,
+                    new_price: modify.new_price,
+                    new_quantity: modify.new_quantity,
+                    recv_time: self.now.timestamp(),
+                    recv_time_ns: self.now.timestamp_subsec_nanos(),
+                    status: ModifyStatus::Pending,
+                    reject_reason: None,
+                };
+                self.modify_order(modify);
+            }
+        }
+    }
+
+    fn place_order(&mut self, request: PlaceOrderRequest) {
+        let order_id = request.id.unwrap_or_else(|| OrderId::nil(self.next_seqno()));
+        let symbol = match request.symbol.parse() {
+            Ok(symbol) => symbol,
+            Err(_) => return,
+        };
+        let order = Order {
+            id: order_id,
+            parent_id: request.parent_id,
+            exchange_order_id: None,
+            recv_time: self.now.timestamp(),
+            recv_time_ns: self.now.timestamp_subsec_nanos(),
+            status: OrderStatus::Pending,
+            reject_reason: None,
+            reject_message: None,
+            symbol,
+            trader: self.trader,
+            account: self.account,
+            dir: request.dir,
+            quantity: request.quantity,
+            filled_quantity: Decimal::ZERO,
+            average_fill_price: None,
+            order_type: request.order_type,
+            time_in_force: request.time_in_force,
+            source: request.source.unwrap_or(OrderSource::Algo),
+            execution_venue: self.execution_venue.clone(),
+            is_short_sale: None,
+        };
+        if self.orders.get(&order_id).is_some() {
+            // not applied to the tracker, which holds the original order
+            self.orderflow.push_back(Orderflow::OrderReject(OrderReject {
+                order_id,
+                reason: OrderRejectReason::DuplicateOrderId,
+                message: None,
+            }));
+            return;
+        }
+        self.emit(Orderflow::OrderPending(order));
+        self.accept(order_id);
+    }
+
+    /// Validate, ack and execute a pending order.
+    fn accept(&mut self, order_id: OrderId) {
+        let Some(order) = self.orders.get(&order_id) else { return };
+        let reject = match order.order_type {
+            OrderType::Market | OrderType::Limit(_)
+                if order.quantity <= Decimal::ZERO =>
+            {
+                Some((OrderRejectReason::InvalidOrder, "quantity must be positive"))
+            }
+            OrderType::Market | OrderType::Limit(_) => None,
+            _ => {
+                Some((OrderRejectReason::UnsupportedOrderType, "unsupported in backtest"))
+            }
+        };
+        let would_cross = !self.liquidity(order).is_empty();
+        let reject = reject.or((order.order_type.post_only() == Some(true)
+            && would_cross)
+            .then_some((OrderRejectReason::InvalidOrder, "post-only order would cross")));
+        if let Some((reason, message)) = reject {
+            self.emit(Orderflow::OrderReject(OrderReject {
+                order_id,
+                reason,
+                message: Some(message.to_string()),
+            }));
+            return;
+        }
+        let exchange_order_id = Some(self.next_seqno().to_string());
+        self.emit(Orderflow::OrderAck(OrderAck { order_id, exchange_order_id }));
+        self.execute(order_id);
+    }
+
+    /// Liquidity an order would take on arrival, best price first.
+    fn liquidity(&self, order: &Order) -> Vec<(Decimal, Decimal)> {
+        let limit_price = order.order_type.limit_price();
+        let crosses = |price: Decimal| match (order.dir, limit_price) {
+            (_, None) => true,
+            (Dir::Buy, Some(limit)) => price <= limit,
+            (Dir::Sell, Some(limit)) => price >= limit,
+        };
+        let symbol = order.symbol.as_str();
+        if let Some(book) = self.market.book(symbol) {
+            book.levels(order.dir.flip()).take_while(|(px, _)| crosses(*px)).collect()
+        } else if let Some(price) = self.market.reference_price(symbol) {
+            if crosses(price) {
+                vec![(price, Decimal::MAX)]
+            } else {
+                vec![]
+            }
+        } else {
+            vec![]
+        }
+    }
+
+    fn execute(&mut self, order_id: OrderId) {
+        let Some(order) = self.orders.get(&order_id) else { return };
+        let liquidity = self.liquidity(order);
+        let mut remaining = order.quantity - order.filled_quantity;
+        let time_in_force = order.time_in_force;
+        let is_market = order.order_type == OrderType::Market;
+        let available: Decimal =
+            liquidity.iter().fold(Decimal::ZERO, |acc, (_, sz)| acc.saturating_add(*sz));
+        if time_in_force == TimeInForce::FillOrKill && available < remaining {
+            self.emit(Orderflow::OrderOut(OrderOut { order_id }));
+            return;
+        }
+        for (price, size) in liquidity {
+            if remaining.is_zero() {
+                break;
+            }
+            let quantity = remaining.min(size);
+            self.fill(order_id, quantity, price, true);
+            remaining -= quantity;
+        }
+        if remaining.is_zero() {
+            return;
+        }
+        if is_market
+            || matches!(
+                time_in_force,
+                TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill
+            )
+        {
+            self.emit(Orderflow::OrderOut(OrderOut { order_id }));
+        } else {
+            self.resting.push(order_id);
+        }
+    }
+
+    fn fill(
+        &mut self,
+        order_id: OrderId,
+        quantity: Decimal,
+        price: Decimal,
+        is_taker: bool,
+    ) {
+        let seqno = self.next_seqno();
+        let fee_rate = if is_taker { self.taker_fee_rate } else { self.maker_fee_rate };
+        let Some(order) = self.orders.get(&order_id) else { return };
+        let is_done = order.filled_quantity + quantity >= order.quantity;
+        let fill = Fill {
+            fill_id: Uuid::new_v4(),
+            fill_kind: FillKind::Normal,
+            execution_venue: self.execution_venue.clone(),
+            exchange_fill_id: Some(seqno.to_string()),
+            order_id: Some(order_id),
+            trader: Some(order.trader),
+            account: Some(order.account),
+            symbol: order.symbol.clone(),
+            dir: order.dir,
+            quantity,
+            price,
+            is_taker: Some(is_taker),
+            fee: Some(fee_rate * quantity * price),
+            fee_currency: None,
+            recv_time: Some(self.now.timestamp()),
+            recv_time_ns: Some(self.now.timestamp_subsec_nanos()),
+            trade_time: self.now.timestamp(),
+            trade_time_ns: self.now.timestamp_subsec_nanos(),
+        };
+        self.emit(Orderflow::Fill(fill));
+        if is_done {
+            self.emit(Orderflow::OrderOut(OrderOut { order_id }));
+        }
+    }
+
+    /// Passive fills for resting orders that the market traded through.
+    fn fill_resting(&mut self, event: &MarketdataEvent) {
+        let symbol = event.symbol();
+        let mut trade_size = match event {
+            MarketdataEvent::Trade(trade) => trade.size,
+            _ => Decimal::MAX,
+        };
+        for order_id in self.resting.clone() {
+            let Some(order) = self.orders.get(&order_id) else { continue };
+            let Some(limit) = order.order_type.limit_price() else { continue };
+            if order.symbol.as_str() != symbol || trade_size.is_zero() {
+                continue;
+            }
+            let traded_through = match (event, order.dir) {
+                (MarketdataEvent::Trade(t), Dir::Buy) => t.price < limit,
+                (MarketdataEvent::Trade(t), Dir::Sell) => t.price > limit,
+                (MarketdataEvent::Candle(c), Dir::Buy) => {
+                    c.low.is_some_and(|l| l < limit)
+                }
+                (MarketdataEvent::Candle(c), Dir::Sell) => {
+                    c.high.is_some_and(|h| h > limit)
+                }
+                (MarketdataEvent::L2BookUpdate { .. }, Dir::Buy) => self
+                    .market
+                    .book(symbol)
+                    .and_then(|b| b.best_ask())
+                    .is_some_and(|(px, _)| px <= limit),
+                (MarketdataEvent::L2BookUpdate { .. }, Dir::Sell) => self
+                    .market
+                    .book(symbol)
+                    .and_then(|b| b.best_bid())
+                    .is_some_and(|(px, _)| px >= limit),
+                (MarketdataEvent::Ticker(_), _) => false,
+            };
+            if !traded_through {
+                continue;
+            }
+            let quantity = (order.quantity - order.filled_quantity).min(trade_size);
+            trade_size -= quantity;
+            self.fill(order_id, quantity, limit, false);
+        }
+    }
+
+    fn cancel_order(&mut self, order_id: OrderId, cancel_id: Option<Uuid>) {
+        if self.resting.contains(&order_id) {
+            self.emit(Orderflow::OrderCanceled(OrderCanceled { order_id, cancel_id }));
+        } else if let Some(cancel_id) = cancel_id {
+            let reject = Orderflow::CancelReject(crate::orderflow::CancelReject {
+                cancel_id,
+                order_id,
+                message: Some("order not open".to_string()),
+            });
+            if self.orders.get(&order_id).is_some() {
+                self.emit(reject);
+            } else {
+                // not applied to the tracker, which doesn't know the order
+                self.orderflow.push_back(reject);
+            }
+        }
+    }
+
+    /// Modifies are cancel-replace: the new order loses time priority.
+    fn modify_order(&mut self, modify: Modify) {
+        let order = match self.orders.get(&modify.order_id) {
+            Some(order) if self.resting.contains(&order.id) => order.clone(),
+            _ => {
+                let reject = modify.reject(Some("order not open".to_string()));
+                self.emit(Orderflow::ModifyReject(reject));
+                return;
+            }
+        };
+        if let Err(e) = modify.modify(order) {
+            let reject = modify.reject(Some(e.to_string()));
+            self.emit(Orderflow::ModifyReject(reject));
+            return;
+        }
+        if self.orders.get(&modify.new_order_id).is_some() {
+            let reject = modify.reject(Some("new order id already in use".to_string()));
+            self.emit(Orderflow::ModifyReject(reject));
+            return;
+        }
+        let modified = OrderModified {
+            order_id: modify.order_id,
+            new_order_id: modify.new_order_id,
+            modify_id: modify.modify_id,
+        };
+        self.emit(Orderflow::ModifyPending(modify));
+        self.emit(Orderflow::OrderModified(modified.clone()));
+        self.execute(modified.new_order_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        marketdata::CandleWidth,
+        oms::{CancelOrderRequest, PlaceOrderRequestBuilder},
+        orderflow::LimitOrderType,
+    };
+    use rust_decimal_macros::dec;
+
+    const SYMBOL: &str = "BTC Crypto/USD";
+
+    fn trade(ts: i64, price: Decimal, size: Decimal) -> MarketdataEvent {
+        MarketdataEvent::Trade(Trade {
+            symbol: SYMBOL.to_string(),
+            timestamp: ts,
+            timestamp_ns: 0,
+            direction: None,
+            price,
+            size,
+        })
+    }
+
+    fn candle(ts: i64, low: Decimal, high: Decimal, close: Decimal) -> MarketdataEvent {
+        let mut candle = Candle::default(
+            DateTime::from_timestamp(ts, 0).unwrap(),
+            CandleWidth::OneSecond,
+            SYMBOL.to_string(),
+        );
+        candle.low = Some(low);
+        candle.high = Some(high);
+        candle.close = Some(close);
+        MarketdataEvent::Candle(candle)
+    }
+
+    /// Buys 1 at the market on the first event, then bids 2 below it.
+    #[derive(Default)]
+    struct Strategy {
+        seen: Vec<(i64, u32)>,
+        orderflow: usize,
+    }
+
+    impl BacktestStrategy for Strategy {
+        fn on_marketdata(&mut self, ctx: &mut BacktestContext, event: &MarketdataEvent) {
+            self.seen.push(event.timestamp_key());
+            if self.seen.len() == 1 {
+                let order = |quantity, order_type| {
+                    PlaceOrderRequestBuilder::default()
+                        .id(None)
+                        .parent_id(None)
+                        .symbol(SYMBOL.to_string())
+                        .dir(Dir::Buy)
+                        .quantity(quantity)
+                        .order_type(order_type)
+                        .build()
+                        .unwrap()
+                };
+                ctx.place_order(order(dec!(1), OrderType::Market));
+                ctx.place_order(order(
+                    dec!(2),
+                    OrderType::Limit(LimitOrderType {
+                        limit_price: dec!(95),
+                        post_only: true,
+                    }),
+                ));
+            }
+        }
+
+        fn on_orderflow(&mut self, _ctx: &mut BacktestContext, _event: &Orderflow) {
+            self.orderflow += 1;
+        }
+    }
+
+    #[test]
+    fn test_replay_merges_sources() {
+        let replay = Replay::new()
+            .with_source(vec![trade(1, dec!(100), dec!(1)), trade(3, dec!(100), dec!(1))])
+            .with_source(vec![
+                candle(2, dec!(99), dec!(101), dec!(100)),
+                candle(3, dec!(99), dec!(101), dec!(100)),
+            ]);
+        let keys: Vec<_> = replay
+            .map(|e| (e.timestamp_key().0, matches!(e, MarketdataEvent::Trade(_))))
+            .collect();
+        assert_eq!(keys, vec![(1, true), (2, false), (3, true), (3, false)]);
+    }
+
+    #[test]
+    fn test_backtest() {
+        let replay = Replay::new()
+            .with_source(vec![
+                trade(1, dec!(100), dec!(1)),
+                trade(3, dec!(96), dec!(5)),
+                trade(5, dec!(94), dec!(1)),
+            ])
+            .with_source(vec![candle(6, dec!(90), dec!(97), dec!(92))]);
+        let mut strategy = Strategy::default();
+        let mut backtest = Backtest::new(replay).with_fees(dec!(0), dec!(0.01));
+        assert!(backtest.step(&mut strategy).is_some());
+        assert_eq!(backtest.fills().len(), 1);
+        assert_eq!(backtest.orders().open_orders().count(), 1);
+        let result = backtest.run(&mut strategy);
+        assert_eq!(strategy.seen.len(), 4);
+        let fills: Vec<_> =
+            result.fills.iter().map(|f| (f.quantity, f.price, f.is_taker)).collect();
+        assert_eq!(
+            fills,
+            vec![
+                (dec!(1), dec!(100), Some(true)),
+                // the trade at 94 only had size 1; the candle fills the rest
+                (dec!(1), dec!(95), Some(false)),
+                (dec!(1), dec!(95), Some(false)),
+            ]
+        );
+        // pending, ack, fills and out for each order
+        assert_eq!(strategy.orderflow, 9);
+        let summary = result.account_summary;
+        let position = &summary.positions[&TradableProduct(SYMBOL.to_string())][0];
+        assert_eq!(position.quantity, dec!(3));
+        // 3 @ 96.67 marked at the candle close
+        assert_eq!(summary.unrealized_pnl, Some(dec!(-14)));
+    }
+
+    /// Cancels an order that was never placed.
+    #[derive(Default)]
+    struct CancelUnknown {
+        rejects: usize,
+    }
+
+    impl BacktestStrategy for CancelUnknown {
+        fn on_marketdata(&mut self, ctx: &mut BacktestContext, _event: &MarketdataEvent) {
+            ctx.send(OrderflowRequest::CancelOrder(CancelOrderRequest {
+                cancel_id: Some(Uuid::new_v4()),
+                order_id: OrderId::nil(42),
+            }));
+        }
+
+        fn on_orderflow(&mut self, _ctx: &mut BacktestContext, event: &Orderflow) {
+            if matches!(event, Orderflow::CancelReject(_)) {
+                self.rejects += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_cancel_unknown_order() {
+        let replay = Replay::new().with_source(vec![trade(1, dec!(100), dec!(1))]);
+        let mut strategy = CancelUnknown::default();
+        Backtest::new(replay).run(&mut strategy);
+        assert_eq!(strategy.rejects, 1);
+    }
+}
@@ -11,6 +11,7 @@ use std::collections::BTreeMap;
 use uuid::Uuid;
 
 pub mod cpty_id;
+pub mod paper;
 
 pub use cpty_id::CptyId;
 
@@ -0,0 +1,793 @@
+//! In-process paper-trading counterparty.
+//!
+//! [`PaperCpty`] accepts [`CptyRequest`]s and matches orders against an
+//! internal limit order book with price-time priority.  Order lifecycle
+//! events are emitted as [`Orderflow`] (acks, rejects, fills, outs and
+//! cancels), while position changes are emitted as
+//! [`CptyResponse::UpdateAccountSummary`].
+//!
+//! The engine has no clock of its own; every call takes the current time,
+//! which keeps simulations deterministic.
+
+use super::{CptyRequest, CptyResponse};
+use crate::{
+    folio::{CostBasisMethod, PositionKeeper},
+    orderflow::{
+        Cancel, Fill, FillKind, Order, OrderAck, OrderCanceled, OrderOut, OrderReject,
+        OrderRejectReason, OrderStatus, OrderType, Orderflow, TimeInForce,
+    },
+    symbology::{ExecutionVenue, TradableProduct},
+    AccountId, AccountIdOrName, Dir, OrderId, UserId,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub enum PaperCptyEvent {
+    Orderflow(Orderflow),
+    Cpty(CptyResponse),
+}
+
+#[derive(Debug, Default, Clone)]
+struct Book {
+    /// price -> resting orders, in time priority
+    bids: BTreeMap<Decimal, VecDeque<OrderId>>,
+    asks: BTreeMap<Decimal, VecDeque<OrderId>>,
+}
+
+impl Book {
+    fn side_mut(&mut self, dir: Dir) -> &mut BTreeMap<Decimal, VecDeque<OrderId>> {
+        match dir {
+            Dir::Buy => &mut self.bids,
+            Dir::Sell => &mut self.asks,
+        }
+    }
+
+    /// Best resting price that an aggressor in `dir` would trade against.
+    fn best_contra(&self, dir: Dir) -> Option<Decimal> {
+        match dir {
+            Dir::Buy => self.asks.keys().next().copied(),
+            Dir::Sell => self.bids.keys().next_back().copied(),
+        }
+    }
+
+    fn remove(&mut self, dir: Dir, price: Decimal, order_id: OrderId) {
+        let side = self.side_mut(dir);
+        if let Some(queue) = side.get_mut(&price) {
+            queue.retain(|id| *id != order_id);
+            if queue.is_empty() {
+                side.remove(&price);
+            }
+        }
+    }
+}
+
+fn crosses(dir: Dir, limit_price: Option<Decimal>, contra_price: Decimal) -> bool {
+    match (dir, limit_price) {
+        (_, None) => true,
+        (Dir::Buy, Some(limit)) => contra_price <= limit,
+        (Dir::Sell, Some(limit)) => contra_price >= limit,
+    }
+}
+
+fn remaining(order: &Order) -> Decimal {
+    order.quantity - order.filled_quantity
+}
+
+#[derive(Debug)]
+pub struct PaperCpty {
+    pub execution_venue: ExecutionVenue,
+    /// Fees are charged as a fraction of fill notional.
+    pub taker_fee_rate: Decimal,
+    pub maker_fee_rate: Decimal,
+    /// Live orders, resting or waiting for their trigger
+    orders: BTreeMap<OrderId, Order>,
+    seen_order_ids: BTreeSet<OrderId>,
+    books: BTreeMap<TradableProduct, Book>,
+    /// Stop-loss and take-profit orders not yet triggered
+    untriggered: BTreeMap<TradableProduct, Vec<OrderId>>,
+    last_prices: BTreeMap<TradableProduct, Decimal>,
+    accounts: BTreeMap<AccountId, PositionKeeper>,
+    /// Accounts with position changes not yet reported
+    dirty_accounts: BTreeSet<AccountId>,
+    seqno: u64,
+}
+
+impl PaperCpty {
+    pub fn new(execution_venue: ExecutionVenue) -> Self {
+        Self {
+            execution_venue,
+            taker_fee_rate: Decimal::ZERO,
+            maker_fee_rate: Decimal::ZERO,
+            orders: BTreeMap::new(),
+            seen_order_ids: BTreeSet::new(),
+            books: BTreeMap::new(),
+            untriggered: BTreeMap::new(),
+            last_prices: BTreeMap::new(),
+            accounts: BTreeMap::new(),
+            dirty_accounts: BTreeSet::new(),
+            seqno: 0,
+        }
+    }
+
+    pub fn with_fees(mut self, maker_fee_rate: Decimal, taker_fee_rate: Decimal) -> Self {
+        self.maker_fee_rate = maker_fee_rate;
+        self.taker_fee_rate = taker_fee_rate;
+        self
+    }
+
+    pub fn get_order(&self, order_id: OrderId) -> Option<&Order> {
+        self.orders.get(&order_id)
+    }
+
+    pub fn open_orders(&self) -> impl Iterator<Item = &Order> {
+        self.orders.values()
+    }
+
+    pub fn last_price(&self, symbol: &TradableProduct) -> Option<Decimal> {
+        self.last_prices.get(symbol).copied()
+    }
+
+    pub fn account(&self, account: &AccountId) -> Option<&PositionKeeper> {
+        self.accounts.get(account)
+    }
+
+    pub fn reconcile_open_orders(&self) -> CptyResponse {
+        CptyResponse::ReconcileOpenOrders {
+            orders: self.orders.values().cloned().collect(),
+            snapshot_for_account: None,
+        }
+    }
+
+    pub fn handle(
+        &mut self,
+        request: CptyRequest,
+        now: DateTime<Utc>,
+    ) -> Vec<PaperCptyEvent> {
+        let mut events = vec![];
+        match request {
+            CptyRequest::Login(_) | CptyRequest::Logout(_) => {}
+            CptyRequest::PlaceOrder(order) => self.place_order(order, now, &mut events),
+            CptyRequest::PlaceBatchOrder { orders } => {
+                for order in orders {
+                    self.place_order(order, now, &mut events);
+                }
+            }
+            CptyRequest::CancelOrder { cancel, .. } => {
+                self.cancel_order(&cancel, &mut events)
+            }
+            CptyRequest::CancelAllOrders { cancel_id, trader, account } => {
+                self.cancel_all_orders(cancel_id, trader, account, &mut events)
+            }
+            CptyRequest::BatchCancelOrders { cancels, .. } => {
+                for cancel in &cancels {
+                    self.cancel_order(cancel, &mut events);
+                }
+            }
+        }
+        self.flush_account_updates(now, &mut events);
+        events
+    }
+
+    /// Feed an external trade or reference price, e.g. from market data,
+    /// which can fire stop-loss and take-profit triggers.
+    pub fn update_last_price(
+        &mut self,
+        symbol: &TradableProduct,
+        price: Decimal,
+        now: DateTime<Utc>,
+    ) -> Vec<PaperCptyEvent> {
+        let mut events = vec![];
+        self.set_last_price(symbol, price);
+        self.run_triggers(symbol, now, &mut events);
+        self.flush_account_updates(now, &mut events);
+        events
+    }
+
+    /// Cancel good-til-date orders that have expired as of `now`.
+    pub fn expire_orders(&mut self, now: DateTime<Utc>) -> Vec<PaperCptyEvent> {
+        let expired: Vec<OrderId> = self
+            .orders
+            .values()
+            .filter(|o| o.time_in_force.good_til_date().is_some_and(|t| t <= now))
+            .map(|o| o.id)
+            .collect();
+        let mut events = vec![];
+        for order_id in expired {
+            self.remove_live_order(order_id, OrderStatus::Canceled);
+            events.push(PaperCptyEvent::Orderflow(Orderflow::OrderCanceled(
+                OrderCanceled { order_id, cancel_id: None },
+            )));
+        }
+        events
+    }
+
+    fn next_seqno(&mut self) -> u64 {
+        self.seqno += 1;
+        self.seqno
+    }
+
+    fn reject(
+        &self,
+        order_id: OrderId,
+        reason: OrderRejectReason,
+        message: &str,
+        events: &mut Vec<PaperCptyEvent>,
+    ) {
+        events.push(PaperCptyEvent::Orderflow(Orderflow::OrderReject(OrderReject {
+            order_id,
+            reason,
+            message: Some(message.to_string()),
+        })));
+    }
+
+    fn place_order(
+        &mut self,
+        mut order: Order,
+        now: DateTime<Utc>,
+        events: &mut Vec<PaperCptyEvent>,
+    ) {
+        use OrderRejectReason::*;
+        if !self.seen_order_ids.insert(order.id) {
+            return self.reject(order.id, DuplicateOrderId, "duplicate order id", events);
+        }
+        if order.execution_venue != self.execution_venue {
+            return self.reject(
+                order.id,
+                UnsupportedExecutionVenue,
+                "wrong execution venue",
+                events,
+            );
+        }
+        if order.quantity <= Decimal::ZERO {
+            return self.reject(
+                order.id,
+                InvalidOrder,
+                "quantity must be positive",
+                events,
+            );
+        }
+        if matches!(order.order_type, OrderType::Bracket(_)) {
+            return self.reject(
+                order.id,
+                UnsupportedOrderType,
+                "bracket orders are not supported",
+                events,
+            );
+        }
+        if let Some(expiry) = order.time_in_force.good_til_date() {
+            if expiry <= now {
+                return self.reject(
+                    order.id,
+                    InvalidOrder,
+                    "order already expired",
+                    events,
+                );
+            }
+        }
+        if order.order_type.post_only() == Some(true) {
+            let best_contra =
+                self.books.get(&order.symbol).and_then(|b| b.best_contra(order.dir));
+            if best_contra
+                .is_some_and(|px| crosses(order.dir, order.order_type.limit_price(), px))
+            {
+                return self.reject(
+                    order.id,
+                    InvalidOrder,
+                    "post-only order would cross",
+                    events,
+                );
+            }
+        }
+        let exchange_order_id = self.next_seqno().to_string();
+        order.exchange_order_id = Some(exchange_order_id.clone());
+        order.status = OrderStatus::Open;
+        order.filled_quantity = Decimal::ZERO;
+        order.average_fill_price = None;
+        events.push(PaperCptyEvent::Orderflow(Orderflow::OrderAck(OrderAck {
+            order_id: order.id,
+            exchange_order_id: Some(exchange_order_id),
+        })));
+        let order_id = order.id;
+        let symbol = order.symbol.clone();
+        let is_triggered = matches!(
+            order.order_type,
+            OrderType::StopLossLimit(_) | OrderType::TakeProfitLimit(_)
+        );
+        self.orders.insert(order_id, order);
+        if is_triggered {
+            self.untriggered.entry(symbol.clone()).or_default().push(order_id);
+            self.run_triggers(&symbol, now, events);
+        } else {
+            self.execute(order_id, now, events);
+            self.run_triggers(&symbol, now, events);
+        }
+    }
+
+    /// Match a live order against the book, then rest or cancel the
+    /// remainder according to its order type and time in force.
+    fn execute(
+        &mut self,
+        order_id: OrderId,
+        now: DateTime<Utc>,
+        events: &mut Vec<PaperCptyEvent>,
+    ) {
+        let Some(order) = self.orders.get(&order_id) else { return };
+        let dir = order.dir;
+        let symbol = order.symbol.clone();
+        let limit_price = match order.order_type {
+            OrderType::Market => None,
+            _ => order.order_type.limit_price(),
+        };
+        let time_in_force = order.time_in_force;
+        if time_in_force == TimeInForce::FillOrKill
+            && self.available_quantity(&symbol, dir, limit_price) < remaining(order)
+        {
+            self.remove_live_order(order_id, OrderStatus::Out);
+            events.push(PaperCptyEvent::Orderflow(Orderflow::OrderOut(OrderOut {
+                order_id,
+            })));
+            return;
+        }
+        while let Some(taker) = self.orders.get(&order_id) {
+            let taker_remaining = remaining(taker);
+            if taker_remaining.is_zero() {
+                break;
+            }
+            let book = self.books.entry(symbol.clone()).or_default();
+            let Some(price) = book.best_contra(dir) else { break };
+            if !crosses(dir, limit_price, price) {
+                break;
+            }
+            let maker_id = book.side_mut(dir.flip())[&price][0];
+            let maker_remaining = remaining(&self.orders[&maker_id]);
+            let quantity = taker_remaining.min(maker_remaining);
+            self.fill(order_id, quantity, price, true, now, events);
+            self.fill(maker_id, quantity, price, false, now, events);
+            self.set_last_price(&symbol, price);
+        }
+        let Some(order) = self.orders.get(&order_id) else { return };
+        if remaining(order).is_zero() {
+            return;
+        }
+        let rests = limit_price.is_some()
+            && !matches!(
+                time_in_force,
+                TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill
+            );
+        match limit_price {
+            Some(price) if rests => {
+                let book = self.books.entry(symbol).or_default();
+                book.side_mut(dir).entry(price).or_default().push_back(order_id);
+            }
+            _ => {
+                self.remove_live_order(order_id, OrderStatus::Out);
+                events.push(PaperCptyEvent::Orderflow(Orderflow::OrderOut(OrderOut {
+                    order_id,
+                })));
+            }
+        }
+    }
+
+    fn available_quantity(
+        &self,
+        symbol: &TradableProduct,
+        dir: Dir,
+        limit_price: Option<Decimal>,
+    ) -> Decimal {
+        let Some(book) = self.books.get(symbol) else { return Decimal::ZERO };
+        let levels: Box<dyn Iterator<Item = (&Decimal, &VecDeque<OrderId>)>> = match dir {
+            Dir::Buy => Box::new(book.asks.iter()),
+            Dir::Sell => Box::new(book.bids.iter().rev()),
+        };
+        levels
+            .take_while(|(price, _)| crosses(dir, limit_price, **price))
+            .flat_map(|(_, queue)| queue.iter())
+            .map(|id| remaining(&self.orders[id]))
+            .sum()
+    }
+
+    fn fill(
+        &mut self,
+        order_id: OrderId,
+        quantity: Decimal,
+        price: Decimal,
+        is_taker: bool,
+        now: DateTime<Utc>,
+        events: &mut Vec<PaperCptyEvent>,
+    ) {
+        let seqno = self.next_seqno();
+        let fee_rate = if is_taker { self.taker_fee_rate } else { self.maker_fee_rate };
+        let Some(order) = self.orders.get_mut(&order_id) else { return };
+        let notional = order.average_fill_price.unwrap_or_default()
+            * order.filled_quantity
+            + price * quantity;
+        order.filled_quantity += quantity;
+        order.average_fill_price = Some(notional / order.filled_quantity);
+        let fill = Fill {
+            fill_id: Uuid::new_v4(),
+            fill_kind: FillKind::Normal,
+            execution_venue: self.execution_venue.clone(),
+            exchange_fill_id: Some(seqno.to_string()),
+            order_id: Some(order_id),
+            trader: Some(order.trader),
+            account: Some(order.account),
+            symbol: order.symbol.clone(),
+            dir: order.dir,
+            quantity,
+            price,
+            is_taker: Some(is_taker),
+            fee: Some(fee_rate * quantity * price),
+            fee_currency: None,
+            recv_time: Some(now.timestamp()),
+            recv_time_ns: Some(now.timestamp_subsec_nanos()),
+            trade_time: now.timestamp(),
+            trade_time_ns: now.timestamp_subsec_nanos(),
+        };
+        let account = order.account;
+        let is_done = remaining(order).is_zero();
+        self.accounts
+            .entry(account)
+            .or_insert_with(|| PositionKeeper::new(CostBasisMethod::default()))
+            .apply_fill(&fill);
+        self.dirty_accounts.insert(account);
+        events.push(PaperCptyEvent::Orderflow(Orderflow::Fill(fill)));
+        if is_done {
+            self.remove_live_order(order_id, OrderStatus::Out);
+            events.push(PaperCptyEvent::Orderflow(Orderflow::OrderOut(OrderOut {
+                order_id,
+            })));
+        }
+    }
+
+    fn set_last_price(&mut self, symbol: &TradableProduct, price: Decimal) {
+        self.last_prices.insert(symbol.clone(), price);
+        for keeper in self.accounts.values_mut() {
+            if keeper.position(symbol).is_some() {
+                keeper.set_mark(symbol.clone(), price);
+            }
+        }
+    }
+
+    /// Fire triggers until none are left to fire; triggered orders can
+    /// trade and move the last price, which can fire further triggers.
+    fn run_triggers(
+        &mut self,
+        symbol: &TradableProduct,
+        now: DateTime<Utc>,
+        events: &mut Vec<PaperCptyEvent>,
+    ) {
+        loop {
+            let Some(last_price) = self.last_prices.get(symbol).copied() else { return };
+            let Some(pending) = self.untriggered.get_mut(symbol) else { return };
+            let orders = &self.orders;
+            let Some(i) = pending.iter().position(|id| {
+                orders.get(id).is_some_and(|o| is_triggered(o, last_price))
+            }) else {
+                return;
+            };
+            let order_id = pending.remove(i);
+            self.execute(order_id, now, events);
+        }
+    }
+
+    fn cancel_order(&mut self, cancel: &Cancel, events: &mut Vec<PaperCptyEvent>) {
+        if self.orders.contains_key(&cancel.order_id) {
+            self.remove_live_order(cancel.order_id, OrderStatus::Canceled);
+            events.push(PaperCptyEvent::Orderflow(Orderflow::OrderCanceled(
+                OrderCanceled {
+                    order_id: cancel.order_id,
+                    cancel_id: Some(cancel.cancel_id),
+                },
+            )));
+        } else {
+            events.push(PaperCptyEvent::Orderflow(Orderflow::CancelReject(
+                cancel.reject(Some("order not open".to_string())),
+            )));
+        }
+    }
+
+    fn cancel_all_orders(
+        &mut self,
+        cancel_id: Uuid,
+        trader: Option<UserId>,
+        account: Option<AccountId>,
+        events: &mut Vec<PaperCptyEvent>,
+    ) {
+        let order_ids: Vec<OrderId> = self
+            .orders
+            .values()
+            .filter(|o| trader.is_none_or(|t| o.trader == t))
+            .filter(|o| account.is_none_or(|a| o.account == a))
+            .map(|o| o.id)
+            .collect();
+        for order_id in order_ids {
+            self.remove_live_order(order_id, OrderStatus::Canceled);
+            events.push(PaperCptyEvent::Orderflow(Orderflow::OrderCanceled(
+                OrderCanceled { order_id, cancel_id: Some(cancel_id) },
+            )));
+        }
+    }
+
+    /// Take an order off the book and out of the live set.
+    fn remove_live_order(&mut self, order_id: OrderId, status: OrderStatus) {
+        let Some(mut order) = self.orders.remove(&order_id) else { return };
+        order.status = status;
+        if let Some(book) = self.books.get_mut(&order.symbol) {
+            if let Some(price) = order.order_type.limit_price() {
+                book.remove(order.dir, price, order_id);
+            }
+        }
+        if let Some(pending) = self.untriggered.get_mut(&order.symbol) {
+            pending.retain(|id| *id != order_id);
+        }
+    }
+
+    fn flush_account_updates(
+        &mut self,
+        now: DateTime<Utc>,
+        events: &mut Vec<PaperCptyEvent>,
+    ) {
+        for account in std::mem::take(&mut self.dirty_accounts) {
+            let Some(keeper) = self.accounts.get(&account) else { continue };
+            let summary = keeper.account_summary(account, now);
+            events.push(PaperCptyEvent::Cpty(CptyResponse::UpdateAccountSummary {
+                account: AccountIdOrName::Id(account),
+                timestamp: now.timestamp(),
+                timestamp_ns: now.timestamp_subsec_nanos(),
+                balances: None,
+                positions: Some(summary.positions),
+                statistics: Some(summary.statistics),
+                is_snapshot: true,
+            }));
+        }
+    }
+}
+
+/// Stop-losses trigger when the price moves against the position the
+/// order would close; take-profits when it moves in favor.
+fn is_triggered(order: &Order, last_price: Decimal) -> bool {
+    match (&order.order_type, order.dir) {
+        (OrderType::StopLossLimit(o), Dir::Buy) => last_price >= o.trigger_price,
+        (OrderType::StopLossLimit(o), Dir::Sell) => last_price <= o.trigger_price,
+        (OrderType::TakeProfitLimit(o), Dir::Buy) => last_price <= o.trigger_price,
+        (OrderType::TakeProfitLimit(o), Dir::Sell) => last_price >= o.trigger_price,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderflow::{
+        LimitOrderType, OrderSource, OrderTracker, TriggerLimitOrderType,
+    };
+    use rust_decimal_macros::dec;
+
+    fn symbol() -> TradableProduct {
+        "BTC Crypto/USD".parse().unwrap()
+    }
+
+    fn order(
+        id: u64,
+        account: AccountId,
+        dir: Dir,
+        quantity: Decimal,
+        order_type: OrderType,
+        time_in_force: TimeInForce,
+    ) -> Order {
+        Order {
+            id: OrderId::nil(id),
+            parent_id: None,
+            exchange_order_id: None,
+            recv_time: 0,
+            recv_time_ns: 0,
+            status: OrderStatus::Pending,
+            reject_reason: None,
+            reject_message: None,
+            symbol: symbol(),
+            trader: UserId::anonymous(),
+            account,
+            dir,
+            quantity,
+            filled_quantity: Decimal::ZERO,
+            average_fill_price: None,
+            order_type,
+            time_in_force,
+            source: OrderSource::API,
+            execution_venue: "PAPER".into(),
+            is_short_sale: None,
+        }
+    }
+
+    fn limit(limit_price: Decimal, post_only: bool) -> OrderType {
+        OrderType::Limit(LimitOrderType { limit_price, post_only })
+    }
+
+    fn orderflow(events: &[PaperCptyEvent]) -> Vec<Orderflow> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                PaperCptyEvent::Orderflow(o) => Some(o.clone()),
+                PaperCptyEvent::Cpty(_) => None,
+            })
+            .collect()
+    }
+
+    fn fills(events: &[PaperCptyEvent]) -> Vec<(OrderId, Decimal, Decimal)> {
+        orderflow(events)
+            .into_iter()
+            .filter_map(|o| match o {
+                Orderflow::Fill(f) => Some((f.order_id.unwrap(), f.quantity, f.price)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matching() {
+        let now = DateTime::from_timestamp(1729700000, 0).unwrap();
+        let maker = AccountId::nil();
+        let taker = AccountId::from_u128(1);
+        let gtc = TimeInForce::GoodTilCancel;
+        let mut cpty = PaperCpty::new("PAPER".into()).with_fees(dec!(0), dec!(0.001));
+        let mut tracker = OrderTracker::new();
+        let place = |cpty: &mut PaperCpty, tracker: &mut OrderTracker, order: Order| {
+            tracker.insert(order.clone());
+            let events = cpty.handle(CptyRequest::PlaceOrder(order), now);
+            for o in orderflow(&events) {
+                tracker.apply(&o).unwrap();
+            }
+            events
+        };
+
+        place(
+            &mut cpty,
+            &mut tracker,
+            order(1, maker, Dir::Sell, dec!(1), limit(dec!(101), false), gtc),
+        );
+        place(
+            &mut cpty,
+            &mut tracker,
+            order(2, maker, Dir::Sell, dec!(2), limit(dec!(100), false), gtc),
+        );
+        place(
+            &mut cpty,
+            &mut tracker,
+            order(3, maker, Dir::Sell, dec!(1), limit(dec!(100), false), gtc),
+        );
+
+        // post-only buy at 100 would cross
+        let events = place(
+            &mut cpty,
+            &mut tracker,
+            order(4, taker, Dir::Buy, dec!(1), limit(dec!(100), true), gtc),
+        );
+        assert!(matches!(orderflow(&events)[..], [Orderflow::OrderReject(_)]));
+
+        // FOK for more than is available through 100 is killed without fills
+        let events = place(
+            &mut cpty,
+            &mut tracker,
+            order(
+                5,
+                taker,
+                Dir::Buy,
+                dec!(4),
+                limit(dec!(100), false),
+                TimeInForce::FillOrKill,
+            ),
+        );
+        assert!(fills(&events).is_empty());
+        assert!(matches!(
+            orderflow(&events)[..],
+            [Orderflow::OrderAck(_), Orderflow::OrderOut(_)]
+        ));
+
+        // sweeps 100 in time priority, then 101; remainder rests at 101
+        let events = place(
+            &mut cpty,
+            &mut tracker,
+            order(6, taker, Dir::Buy, dec!(5), limit(dec!(101), false), gtc),
+        );
+        assert_eq!(
+            fills(&events),
+            vec![
+                (OrderId::nil(6), dec!(2), dec!(100)),
+                (OrderId::nil(2), dec!(2), dec!(100)),
+                (OrderId::nil(6), dec!(1), dec!(100)),
+                (OrderId::nil(3), dec!(1), dec!(100)),
+                (OrderId::nil(6), dec!(1), dec!(101)),
+                (OrderId::nil(1), dec!(1), dec!(101)),
+            ]
+        );
+        let resting = cpty.get_order(OrderId::nil(6)).unwrap();
+        assert_eq!(resting.filled_quantity, dec!(4));
+        assert_eq!(resting.average_fill_price, Some(dec!(100.25)));
+        assert_eq!(tracker.get(&OrderId::nil(6)).unwrap().filled_quantity, dec!(4));
+        assert_eq!(tracker.get(&OrderId::nil(2)).unwrap().status, OrderStatus::Out);
+        let position = cpty.account(&taker).unwrap().position(&symbol()).unwrap();
+        assert_eq!(position.quantity(), dec!(4));
+        assert_eq!(position.fees, dec!(0.401));
+        assert!(events.iter().any(|e| matches!(
+            e,
+            PaperCptyEvent::Cpty(CptyResponse::UpdateAccountSummary { .. })
+        )));
+
+        // IOC sell takes the resting bid and cancels the rest
+        let events = place(
+            &mut cpty,
+            &mut tracker,
+            order(
+                7,
+                maker,
+                Dir::Sell,
+                dec!(3),
+                limit(dec!(99), false),
+                TimeInForce::ImmediateOrCancel,
+            ),
+        );
+        assert_eq!(fills(&events)[0], (OrderId::nil(7), dec!(1), dec!(101)));
+        assert!(matches!(orderflow(&events).last(), Some(Orderflow::OrderOut(_))));
+        assert_eq!(cpty.open_orders().count(), 0);
+    }
+
+    #[test]
+    fn test_triggers_and_cancels() {
+        let now = DateTime::from_timestamp(1729700000, 0).unwrap();
+        let account = AccountId::nil();
+        let gtc = TimeInForce::GoodTilCancel;
+        let mut cpty = PaperCpty::new("PAPER".into());
+        let stop = OrderType::StopLossLimit(TriggerLimitOrderType {
+            limit_price: dec!(94),
+            trigger_price: dec!(95),
+        });
+        cpty.handle(
+            CptyRequest::PlaceOrder(order(
+                1,
+                account,
+                Dir::Buy,
+                dec!(1),
+                limit(dec!(94), false),
+                gtc,
+            )),
+            now,
+        );
+        let events = cpty.handle(
+            CptyRequest::PlaceOrder(order(2, account, Dir::Sell, dec!(1), stop, gtc)),
+            now,
+        );
+        assert!(fills(&events).is_empty());
+        assert!(cpty.update_last_price(&symbol(), dec!(96), now).is_empty());
+        let events = cpty.update_last_price(&symbol(), dec!(95), now);
+        assert_eq!(fills(&events)[0], (OrderId::nil(2), dec!(1), dec!(94)));
+
+        cpty.handle(
+            CptyRequest::PlaceOrder(order(
+                3,
+                account,
+                Dir::Buy,
+                dec!(1),
+                limit(dec!(90), false),
+                gtc,
+            )),
+            now,
+        );
+        let cancel = Cancel {
+            cancel_id: Uuid::nil(),
+            order_id: OrderId::nil(3),
+            recv_time: 0,
+            recv_time_ns: 0,
+            status: crate::orderflow::CancelStatus::Pending,
+            reject_reason: None,
+        };
+        let request =
+            CptyRequest::CancelOrder { cancel: cancel.clone(), original_order: None };
+        let events = cpty.handle(request.clone(), now);
+        assert!(matches!(orderflow(&events)[..], [Orderflow::OrderCanceled(_)]));
+        let events = cpty.handle(request, now);
+        assert!(matches!(orderflow(&events)[..], [Orderflow::CancelReject(_)]));
+    }
+}
@@ -12,6 +12,9 @@ use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 use std::collections::BTreeMap;
 
+pub mod position_keeper;
+pub use position_keeper::{CostBasisMethod, PositionKeeper};
+
 #[grpc(package = "json.architect")]
 #[grpc(service = "Folio", name = "account_summary", response = "AccountSummary")]
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
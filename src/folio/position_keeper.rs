@@ -0,0 +1,426 @@
+//! Local position and PnL keeping over a stream of [`Fill`]s.
+//!
+//! Useful for cross-checking Folio and for offline backtests.
+
+use super::{AccountPosition, AccountSummary};
+use crate::{
+    orderflow::{Fill, FillKind},
+    symbology::{ProductInfo, TradableProduct},
+    AccountId, Dir,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+/// How closing trades are matched against open lots.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum CostBasisMethod {
+    #[default]
+    Fifo,
+    Lifo,
+    AverageCost,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lot {
+    /// Signed quantity; positive for long lots
+    pub quantity: Decimal,
+    pub price: Decimal,
+}
+
+#[derive(Debug, Clone)]
+pub struct Position {
+    pub multiplier: Decimal,
+    pub lots: VecDeque<Lot>,
+    pub realized_pnl: Decimal,
+    /// Fees charged in the price currency; realized PnL is gross of these
+    pub fees: Decimal,
+    pub last_trade_time: Option<DateTime<Utc>>,
+}
+
+impl Position {
+    fn new(multiplier: Decimal) -> Self {
+        Self {
+            multiplier,
+            lots: VecDeque::new(),
+            realized_pnl: Decimal::ZERO,
+            fees: Decimal::ZERO,
+            last_trade_time: None,
+        }
+    }
+
+    /// Signed position quantity
+    pub fn quantity(&self) -> Decimal {
+        self.lots.iter().map(|lot| lot.quantity).sum()
+    }
+
+    /// Signed cost of the open lots, multiplier applied
+    pub fn cost_basis(&self) -> Decimal {
+        self.lots.iter().map(|lot| lot.quantity * lot.price).sum::<Decimal>()
+            * self.multiplier
+    }
+
+    /// Average entry price of the open lots
+    pub fn average_price(&self) -> Option<Decimal> {
+        let quantity = self.quantity();
+        if quantity.is_zero() {
+            None
+        } else {
+            Some(
+                self.lots.iter().map(|lot| lot.quantity * lot.price).sum::<Decimal>()
+                    / quantity,
+            )
+        }
+    }
+
+    pub fn unrealized_pnl(&self, mark: Decimal) -> Decimal {
+        self.lots.iter().map(|lot| (mark - lot.price) * lot.quantity).sum::<Decimal>()
+            * self.multiplier
+    }
+
+    fn trade(&mut self, method: CostBasisMethod, mut quantity: Decimal, price: Decimal) {
+        // close opposing lots first
+        while !quantity.is_zero() {
+            let lot = match method {
+                CostBasisMethod::Fifo | CostBasisMethod::AverageCost => {
+                    self.lots.front_mut()
+                }
+                CostBasisMethod::Lifo => self.lots.back_mut(),
+            };
+            let Some(lot) = lot else { break };
+            if lot.quantity.is_sign_positive() == quantity.is_sign_positive() {
+                break;
+            }
+            let closed = if lot.quantity.abs() <= quantity.abs() {
+                lot.quantity
+            } else {
+                -quantity
+            };
+            self.realized_pnl += (price - lot.price) * closed * self.multiplier;
+            lot.quantity -= closed;
+            quantity += closed;
+            if lot.quantity.is_zero() {
+                match method {
+                    CostBasisMethod::Fifo | CostBasisMethod::AverageCost => {
+                        self.lots.pop_front();
+                    }
+                    CostBasisMethod::Lifo => {
+                        self.lots.pop_back();
+                    }
+                }
+            }
+        }
+        if quantity.is_zero() {
+            return;
+        }
+        match (method, self.lots.front_mut()) {
+            (CostBasisMethod::AverageCost, Some(lot)) => {
+                let total = lot.quantity + quantity;
+                lot.price = (lot.quantity * lot.price + quantity * price) / total;
+                lot.quantity = total;
+            }
+            _ => self.lots.push_back(Lot { quantity, price }),
+        }
+    }
+}
+
+/// Default number of recent fills per symbol that reversals can unwind.
+pub const DEFAULT_REVERSAL_WINDOW: usize = 1000;
+
+/// The most recent fills of a symbol and the position before them, so
+/// reversals of recent fills can be unwound exactly by replaying.
+#[derive(Debug, Clone)]
+struct RecentFills {
+    base: Position,
+    fills: VecDeque<Fill>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PositionKeeper {
+    pub method: CostBasisMethod,
+    reversal_window: usize,
+    multipliers: BTreeMap<TradableProduct, Decimal>,
+    marks: BTreeMap<TradableProduct, Decimal>,
+    positions: BTreeMap<TradableProduct, Position>,
+    recent: BTreeMap<TradableProduct, RecentFills>,
+}
+
+impl PositionKeeper {
+    pub fn new(method: CostBasisMethod) -> Self {
+        Self {
+            method,
+            reversal_window: DEFAULT_REVERSAL_WINDOW,
+            multipliers: BTreeMap::new(),
+            marks: BTreeMap::new(),
+            positions: BTreeMap::new(),
+            recent: BTreeMap::new(),
+        }
+    }
+
+    /// Keep at most `window` recent fills per symbol for unwinding
+    /// reversals.  Reversals of older fills are booked as offsetting trades.
+    pub fn with_reversal_window(mut self, window: usize) -> Self {
+        self.reversal_window = window;
+        self
+    }
+
+    /// Set the contract multiplier for a symbol; symbols without one
+    /// use a multiplier of 1.  Must be set before fills for the symbol
+    /// are applied.
+    pub fn set_multiplier(&mut self, symbol: TradableProduct, multiplier: Decimal) {
+        self.multipliers.insert(symbol, multiplier);
+    }
+
+    pub fn set_product_info(&mut self, symbol: TradableProduct, info: &ProductInfo) {
+        if let Some(multiplier) = info.multiplier() {
+            self.set_multiplier(symbol, multiplier);
+        }
+    }
+
+    pub fn set_mark(&mut self, symbol: TradableProduct, price: Decimal) {
+        self.marks.insert(symbol, price);
+    }
+
+    pub fn position(&self, symbol: &TradableProduct) -> Option<&Position> {
+        self.positions.get(symbol)
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = (&TradableProduct, &Position)> {
+        self.positions.iter()
+    }
+
+    pub fn realized_pnl(&self) -> Decimal {
+        self.positions.values().map(|p| p.realized_pnl).sum()
+    }
+
+    /// Unrealized PnL across positions that have a mark price
+    pub fn unrealized_pnl(&self) -> Decimal {
+        self.positions
+            .iter()
+            .filter_map(|(symbol, p)| Some(p.unrealized_pnl(*self.marks.get(symbol)?)))
+            .sum()
+    }
+
+    pub fn apply_fill(&mut self, fill: &Fill) {
+        let method = self.method;
+        let multiplier = self.multiplier(&fill.symbol);
+        let recent = self.recent.entry(fill.symbol.clone()).or_insert_with(|| {
+            RecentFills { base: Position::new(multiplier), fills: VecDeque::new() }
+        });
+        if fill.fill_kind == FillKind::Reversal {
+            if let Some(i) = recent.fills.iter().rposition(|f| reverses(fill, f)) {
+                recent.fills.remove(i);
+                let mut position = recent.base.clone();
+                for fill in &recent.fills {
+                    apply_to_position(&mut position, method, fill);
+                }
+                self.positions.insert(fill.symbol.clone(), position);
+                return;
+            }
+            // nothing to unwind; book the reversal as an offsetting trade
+        }
+        recent.fills.push_back(fill.clone());
+        while recent.fills.len() > self.reversal_window {
+            if let Some(oldest) = recent.fills.pop_front() {
+                apply_to_position(&mut recent.base, method, &oldest);
+            }
+        }
+        let position = self
+            .positions
+            .entry(fill.symbol.clone())
+            .or_insert_with(|| Position::new(multiplier));
+        apply_to_position(position, method, fill);
+    }
+
+    fn multiplier(&self, symbol: &TradableProduct) -> Decimal {
+        self.multipliers.get(symbol).copied().unwrap_or(Decimal::ONE)
+    }
+
+    pub fn account_summary(
+        &self,
+        account: AccountId,
+        timestamp: DateTime<Utc>,
+    ) -> AccountSummary {
+        let mut summary = AccountSummary::new(account, timestamp);
+        for (symbol, p) in &self.positions {
+            let quantity = p.quantity();
+            if quantity.is_zero() {
+                continue;
+            }
+            let unrealized_pnl =
+                self.marks.get(symbol).map(|mark| p.unrealized_pnl(*mark));
+            summary.positions.insert(
+                symbol.clone(),
+                vec![AccountPosition {
+                    quantity,
+                    trade_time: p.last_trade_time,
+                    cost_basis: Some(p.cost_basis()),
+                    unrealized_pnl,
+                    break_even_price: p.average_price(),
+                    liquidation_price: None,
+                }],
+            );
+        }
+        summary.realized_pnl = Some(self.realized_pnl());
+        summary.unrealized_pnl = Some(self.unrealized_pnl());
+        summary
+    }
+}
+
+/// A reversal cancels the most recent non-reversal fill with the same
+/// exchange fill id, or failing that, the same order, side, size and price.
+fn reverses(reversal: &Fill, fill: &Fill) -> bool {
+    if fill.fill_kind == FillKind::Reversal {
+        return false;
+    }
+    match (&reversal.exchange_fill_id, &fill.exchange_fill_id) {
+        (Some(a), Some(b)) => a == b,
+        _ => {
+            reversal.order_id == fill.order_id
+                && reversal.dir == fill.dir
+                && reversal.quantity == fill.quantity
+                && reversal.price == fill.price
+        }
+    }
+}
+
+fn apply_to_position(position: &mut Position, method: CostBasisMethod, fill: &Fill) {
+    let dir = match fill.fill_kind {
+        FillKind::Normal | FillKind::Correction => fill.dir,
+        FillKind::Reversal => fill.dir.flip(),
+    };
+    let quantity = match dir {
+        Dir::Buy => fill.quantity,
+        Dir::Sell => -fill.quantity,
+    };
+    position.trade(method, quantity, fill.price);
+    if fill.fee_currency.is_none() {
+        if let Some(fee) = fill.fee {
+            match fill.fill_kind {
+                FillKind::Reversal => position.fees -= fee,
+                FillKind::Normal | FillKind::Correction => position.fees += fee,
+            }
+        }
+    }
+    if let Some(trade_time) = fill.trade_time() {
+        if position.last_trade_time.is_none_or(|t| t < trade_time) {
+            position.last_trade_time = Some(trade_time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+    use uuid::Uuid;
+
+    fn fill(dir: Dir, quantity: Decimal, price: Decimal) -> Fill {
+        Fill {
+            fill_id: Uuid::new_v4(),
+            fill_kind: FillKind::Normal,
+            execution_venue: "CME".into(),
+            exchange_fill_id: None,
+            order_id: None,
+            trader: None,
+            account: None,
+            symbol: "ES 20241220 CME Future/USD".parse().unwrap(),
+            dir,
+            quantity,
+            price,
+            is_taker: None,
+            fee: None,
+            fee_currency: None,
+            recv_time: None,
+            recv_time_ns: None,
+            trade_time: 0,
+            trade_time_ns: 0,
+        }
+    }
+
+    fn run(method: CostBasisMethod) -> PositionKeeper {
+        let mut keeper = PositionKeeper::new(method);
+        let symbol: TradableProduct = "ES 20241220 CME Future/USD".parse().unwrap();
+        keeper.set_multiplier(symbol.clone(), dec!(50));
+        keeper.apply_fill(&fill(Dir::Buy, dec!(1), dec!(100)));
+        keeper.apply_fill(&fill(Dir::Buy, dec!(1), dec!(110)));
+        keeper.apply_fill(&fill(Dir::Sell, dec!(1), dec!(120)));
+        keeper.set_mark(symbol, dec!(115));
+        keeper
+    }
+
+    #[test]
+    fn test_cost_basis_methods() {
+        let symbol: TradableProduct = "ES 20241220 CME Future/USD".parse().unwrap();
+
+        let fifo = run(CostBasisMethod::Fifo);
+        assert_eq!(fifo.realized_pnl(), dec!(1000));
+        assert_eq!(fifo.unrealized_pnl(), dec!(250));
+        assert_eq!(fifo.position(&symbol).unwrap().average_price(), Some(dec!(110)));
+
+        let lifo = run(CostBasisMethod::Lifo);
+        assert_eq!(lifo.realized_pnl(), dec!(500));
+        assert_eq!(lifo.unrealized_pnl(), dec!(750));
+
+        let avg = run(CostBasisMethod::AverageCost);
+        assert_eq!(avg.realized_pnl(), dec!(750));
+        assert_eq!(avg.unrealized_pnl(), dec!(500));
+        assert_eq!(avg.position(&symbol).unwrap().cost_basis(), dec!(5250));
+    }
+
+    #[test]
+    fn test_reversal_and_flip() {
+        let symbol: TradableProduct = "ES 20241220 CME Future/USD".parse().unwrap();
+        let mut keeper = PositionKeeper::new(CostBasisMethod::Fifo);
+        keeper.apply_fill(&fill(Dir::Buy, dec!(2), dec!(100)));
+        let mut bad = fill(Dir::Sell, dec!(3), dec!(90));
+        bad.exchange_fill_id = Some("X1".to_string());
+        keeper.apply_fill(&bad);
+        assert_eq!(keeper.position(&symbol).unwrap().quantity(), dec!(-1));
+        assert_eq!(keeper.realized_pnl(), dec!(-20));
+
+        let mut reversal = bad.clone();
+        reversal.fill_id = Uuid::new_v4();
+        reversal.fill_kind = FillKind::Reversal;
+        keeper.apply_fill(&reversal);
+        assert_eq!(keeper.position(&symbol).unwrap().quantity(), dec!(2));
+        assert_eq!(keeper.realized_pnl(), dec!(0));
+
+        let summary = keeper.account_summary(AccountId::nil(), DateTime::UNIX_EPOCH);
+        assert_eq!(summary.positions[&symbol][0].quantity, dec!(2));
+        assert_eq!(summary.positions[&symbol][0].break_even_price, Some(dec!(100)));
+    }
+
+    #[test]
+    fn test_reversal_window() {
+        let symbol: TradableProduct = "ES 20241220 CME Future/USD".parse().unwrap();
+        let mut keeper =
+            PositionKeeper::new(CostBasisMethod::Fifo).with_reversal_window(1);
+        let mut first = fill(Dir::Buy, dec!(1), dec!(100));
+        first.exchange_fill_id = Some("X1".to_string());
+        let mut second = fill(Dir::Buy, dec!(1), dec!(110));
+        second.exchange_fill_id = Some("X2".to_string());
+        keeper.apply_fill(&first);
+        keeper.apply_fill(&second);
+        // the second fill is in the window and unwinds exactly
+        let mut reversal = second.clone();
+        reversal.fill_kind = FillKind::Reversal;
+        keeper.apply_fill(&reversal);
+        assert_eq!(keeper.position(&symbol).unwrap().quantity(), dec!(1));
+        assert_eq!(keeper.position(&symbol).unwrap().average_price(), Some(dec!(100)));
+        // the first has left the window; its reversal is an offsetting trade
+        keeper.apply_fill(&fill(Dir::Buy, dec!(1), dec!(120)));
+        let mut reversal = first.clone();
+        reversal.fill_kind = FillKind::Reversal;
+        keeper.apply_fill(&reversal);
+        let position = keeper.position(&symbol).unwrap();
+        assert_eq!(position.quantity(), dec!(1));
+        assert_eq!(position.average_price(), Some(dec!(120)));
+        assert_eq!(keeper.realized_pnl(), dec!(0));
+    }
+}
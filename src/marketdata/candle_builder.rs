@@ -0,0 +1,364 @@
+//! Build candles locally from trades and L1 book snapshots.
+
+use super::{Candle, CandleWidth, L1BookSnapshot, Trade};
+use crate::Dir;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+
+/// Aggregates a single symbol's [`Trade`]s and [`L1BookSnapshot`]s, or
+/// candles of a smaller width, into candles of one width.
+///
+/// Candles are bucketed by exchange timestamp, see
+/// [`CandleWidth::bucket_start`], and timestamped with the start of the
+/// bucket.  Each `on_*` method
+/// returns the candles it finished, in order.  Events older than the
+/// in-progress bucket are ignored.
+///
+/// With gap filling on (the default), buckets without any events are
+/// emitted as empty candles, and fields of a candle that saw no trades or
+/// no quotes are carried forward flat from the previous candle's close.
+#[derive(Debug, Clone)]
+pub struct CandleBuilder {
+    symbol: String,
+    width: CandleWidth,
+    fill_gaps: bool,
+    current: Option<Candle>,
+    last: Option<Candle>,
+}
+
+impl CandleBuilder {
+    pub fn new(symbol: impl Into<String>, width: CandleWidth) -> Self {
+        Self { symbol: symbol.into(), width, fill_gaps: true, current: None, last: None }
+    }
+
+    pub fn with_fill_gaps(mut self, fill_gaps: bool) -> Self {
+        self.fill_gaps = fill_gaps;
+        self
+    }
+
+    pub fn width(&self) -> CandleWidth {
+        self.width
+    }
+
+    /// The in-progress candle, if any.
+    pub fn current(&self) -> Option<Candle> {
+        let mut candle = self.current.clone()?;
+        self.carry_forward(&mut candle);
+        Some(candle)
+    }
+
+    /// Trade direction is the maker's side, so a trade with a selling maker
+    /// counts toward buy volume and vice versa.
+    pub fn on_trade(&mut self, trade: &Trade) -> Vec<Candle> {
+        let Some((finished, candle)) = self.current_for(trade.timestamp) else {
+            return vec![];
+        };
+        update_ohlc(
+            [&mut candle.open, &mut candle.high, &mut candle.low, &mut candle.close],
+            trade.price,
+        );
+        candle.volume += trade.size;
+        match trade.direction {
+            Some(Dir::Sell) => candle.buy_volume += trade.size,
+            Some(Dir::Buy) => candle.sell_volume += trade.size,
+            None => {}
+        }
+        finished
+    }
+
+    pub fn on_l1_book_snapshot(&mut self, snapshot: &L1BookSnapshot) -> Vec<Candle> {
+        let mid = snapshot.mid_price();
+        let Some((finished, candle)) = self.current_for(snapshot.timestamp) else {
+            return vec![];
+        };
+        if let Some(mid) = mid {
+            update_ohlc(
+                [
+                    &mut candle.mid_open,
+                    &mut candle.mid_high,
+                    &mut candle.mid_low,
+                    &mut candle.mid_close,
+                ],
+                mid,
+            );
+        }
+        if let Some((bid, _)) = snapshot.best_bid {
+            update_ohlc(
+                [
+                    &mut candle.bid_open,
+                    &mut candle.bid_high,
+                    &mut candle.bid_low,
+                    &mut candle.bid_close,
+                ],
+                bid,
+            );
+        }
+        if let Some((ask, _)) = snapshot.best_ask {
+            update_ohlc(
+                [
+                    &mut candle.ask_open,
+                    &mut candle.ask_high,
+                    &mut candle.ask_low,
+                    &mut candle.ask_close,
+                ],
+                ask,
+            );
+        }
+        finished
+    }
+
+    /// Roll up a finished candle of a smaller width, see
+    /// [`CandleWidth::rolls_up_into`].
+    pub fn on_candle(&mut self, candle: &Candle) -> Result<Vec<Candle>> {
+        if !candle.width.rolls_up_into(self.width) {
+            bail!("cannot roll up {} candles into {} candles", candle.width, self.width);
+        }
+        let Some((finished, current)) = self.current_for(candle.timestamp) else {
+            return Ok(vec![]);
+        };
+        merge_ohlc(
+            [&mut current.open, &mut current.high, &mut current.low, &mut current.close],
+            [candle.open, candle.high, candle.low, candle.close],
+        );
+        merge_ohlc(
+            [
+                &mut current.mid_open,
+                &mut current.mid_high,
+                &mut current.mid_low,
+                &mut current.mid_close,
+            ],
+            [candle.mid_open, candle.mid_high, candle.mid_low, candle.mid_close],
+        );
+        merge_ohlc(
+            [
+                &mut current.bid_open,
+                &mut current.bid_high,
+                &mut current.bid_low,
+                &mut current.bid_close,
+            ],
+            [candle.bid_open, candle.bid_high, candle.bid_low, candle.bid_close],
+        );
+        merge_ohlc(
+            [
+                &mut current.ask_open,
+                &mut current.ask_high,
+                &mut current.ask_low,
+                &mut current.ask_close,
+            ],
+            [candle.ask_open, candle.ask_high, candle.ask_low, candle.ask_close],
+        );
+        current.volume += candle.volume;
+        current.buy_volume += candle.buy_volume;
+        current.sell_volume += candle.sell_volume;
+        Ok(finished)
+    }
+
+    /// Finish every bucket that ends at or before `now`, e.g. on a timer
+    /// when the market is quiet.
+    pub fn advance_to(&mut self, now: DateTime<Utc>) -> Vec<Candle> {
+        let mut finished = vec![];
+        self.close_before(self.bucket_start(now.timestamp()), &mut finished);
+        finished
+    }
+
+    /// Finish and return the in-progress candle regardless of time.
+    pub fn flush(&mut self) -> Option<Candle> {
+        let candle = self.current.take()?;
+        Some(self.finish(candle))
+    }
+
+    fn bucket_start(&self, timestamp: i64) -> i64 {
+        let time = DateTime::from_timestamp(timestamp, 0).unwrap_or_default();
+        self.width.bucket_start(time).timestamp()
+    }
+
+    /// Finish any buckets before the one containing `timestamp` and return
+    /// them with the in-progress candle.  None if the bucket containing
+    /// `timestamp` is already finished.
+    fn current_for(&mut self, timestamp: i64) -> Option<(Vec<Candle>, &mut Candle)> {
+        let bucket = self.bucket_start(timestamp);
+        let late = match (&self.current, &self.last) {
+            (Some(current), _) => bucket < current.timestamp,
+            (None, Some(last)) => bucket <= last.timestamp,
+            (None, None) => false,
+        };
+        if late {
+            return None;
+        }
+        let mut finished = vec![];
+        self.close_before(bucket, &mut finished);
+        let candle = self.current.get_or_insert_with(|| {
+            let start = DateTime::from_timestamp(bucket, 0).unwrap_or_default();
+            Candle::default(start, self.width, self.symbol.clone())
+        });
+        Some((finished, candle))
+    }
+
+    fn close_before(&mut self, bucket: i64, finished: &mut Vec<Candle>) {
+        if let Some(current) = self.current.take_if(|c| c.timestamp < bucket) {
+            finished.push(self.finish(current));
+        }
+        if self.current.is_some() || !self.fill_gaps {
+            return;
+        }
+        let Some(t) = self.last.as_ref().map(|c| c.timestamp) else { return };
+        let mut start = DateTime::from_timestamp(t, 0).unwrap_or_default();
+        loop {
+            start = self.width.next_bucket_start(start);
+            if start.timestamp() >= bucket {
+                break;
+            }
+            let empty = Candle::default(start, self.width, self.symbol.clone());
+            finished.push(self.finish(empty));
+        }
+    }
+
+    fn finish(&mut self, mut candle: Candle) -> Candle {
+        self.carry_forward(&mut candle);
+        self.last = Some(candle.clone());
+        candle
+    }
+
+    fn carry_forward(&self, candle: &mut Candle) {
+        let (true, Some(last)) = (self.fill_gaps, &self.last) else { return };
+        if candle.open.is_none() {
+            let close = last.close;
+            (candle.open, candle.high, candle.low, candle.close) =
+                (close, close, close, close);
+        }
+        if candle.mid_open.is_none() {
+            let close = last.mid_close;
+            (candle.mid_open, candle.mid_high, candle.mid_low, candle.mid_close) =
+                (close, close, close, close);
+        }
+        if candle.bid_open.is_none() {
+            let close = last.bid_close;
+            (candle.bid_open, candle.bid_high, candle.bid_low, candle.bid_close) =
+                (close, close, close, close);
+        }
+        if candle.ask_open.is_none() {
+            let close = last.ask_close;
+            (candle.ask_open, candle.ask_high, candle.ask_low, candle.ask_close) =
+                (close, close, close, close);
+        }
+    }
+}
+
+/// `[open, high, low, close]`
+fn update_ohlc(ohlc: [&mut Option<Decimal>; 4], price: Decimal) {
+    let [open, high, low, close] = ohlc;
+    open.get_or_insert(price);
+    *high = Some(high.map_or(price, |h| h.max(price)));
+    *low = Some(low.map_or(price, |l| l.min(price)));
+    *close = Some(price);
+}
+
+fn merge_ohlc(ohlc: [&mut Option<Decimal>; 4], other: [Option<Decimal>; 4]) {
+    let [open, high, low, close] = ohlc;
+    let [other_open, other_high, other_low, other_close] = other;
+    if open.is_none() {
+        *open = other_open;
+    }
+    *high = match (*high, other_high) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    };
+    *low = match (*low, other_low) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    if other_close.is_some() {
+        *close = other_close;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn trade(ts: i64, price: Decimal, size: Decimal, maker: Option<Dir>) -> Trade {
+        Trade {
+            symbol: "ES 20251219 CME Future".to_string(),
+            timestamp: ts,
+            timestamp_ns: 0,
+            direction: maker,
+            price,
+            size,
+        }
+    }
+
+    #[test]
+    fn test_candle_builder() {
+        let mut builder =
+            CandleBuilder::new("ES 20251219 CME Future", CandleWidth::OneMinute);
+        assert!(builder
+            .on_trade(&trade(0, dec!(100), dec!(1), Some(Dir::Sell)))
+            .is_empty());
+        let l1 = L1BookSnapshot {
+            symbol: "ES 20251219 CME Future".to_string(),
+            timestamp: 10,
+            timestamp_ns: 0,
+            recv_time: None,
+            recv_time_ns: None,
+            best_bid: Some((dec!(99), dec!(5))),
+            best_ask: Some((dec!(101), dec!(5))),
+        };
+        assert!(builder.on_l1_book_snapshot(&l1).is_empty());
+        assert!(builder
+            .on_trade(&trade(30, dec!(101), dec!(2), Some(Dir::Buy)))
+            .is_empty());
+        assert_eq!(builder.current().unwrap().close, Some(dec!(101)));
+
+        let finished = builder.on_trade(&trade(185, dec!(102), dec!(1), None));
+        assert_eq!(finished.len(), 3);
+        let first = &finished[0];
+        assert_eq!(first.timestamp, 0);
+        assert_eq!(
+            (first.open, first.high, first.low, first.close),
+            (Some(dec!(100)), Some(dec!(101)), Some(dec!(100)), Some(dec!(101)))
+        );
+        assert_eq!(
+            (first.volume, first.buy_volume, first.sell_volume),
+            (dec!(3), dec!(1), dec!(2))
+        );
+        assert_eq!((first.mid_open, first.bid_close), (Some(dec!(100)), Some(dec!(99))));
+        // gap-filled buckets carry the closes forward
+        for (candle, ts) in finished[1..].iter().zip([60, 120]) {
+            assert_eq!(candle.timestamp, ts);
+            assert_eq!((candle.open, candle.close), (Some(dec!(101)), Some(dec!(101))));
+            assert_eq!(candle.ask_low, Some(dec!(101)));
+            assert_eq!(candle.volume, dec!(0));
+        }
+        // late trade is ignored
+        assert!(builder.on_trade(&trade(100, dec!(90), dec!(1), None)).is_empty());
+        let last = builder.advance_to(DateTime::from_timestamp(240, 0).unwrap());
+        assert_eq!(last.len(), 1);
+        assert_eq!(last[0].low, Some(dec!(102)));
+        assert_eq!(last[0].mid_close, Some(dec!(100)));
+
+        let mut rollup =
+            CandleBuilder::new("ES 20251219 CME Future", CandleWidth::ThreeMinute);
+        assert!(rollup
+            .on_candle(&Candle::default(
+                DateTime::from_timestamp(0, 0).unwrap(),
+                CandleWidth::OneHour,
+                "ES 20251219 CME Future".to_string()
+            ))
+            .is_err());
+        let mut rolled = vec![];
+        for candle in finished.iter().chain(&last) {
+            rolled.extend(rollup.on_candle(candle).unwrap());
+        }
+        assert_eq!(rolled.len(), 1);
+        let rolled = &rolled[0];
+        assert_eq!(rolled.width, CandleWidth::ThreeMinute);
+        assert_eq!(
+            (rolled.open, rolled.high, rolled.low, rolled.close),
+            (Some(dec!(100)), Some(dec!(101)), Some(dec!(100)), Some(dec!(101)))
+        );
+        assert_eq!(rolled.volume, dec!(3));
+        assert_eq!(rollup.current().unwrap().close, Some(dec!(102)));
+    }
+}
@@ -1,39 +1,59 @@
-use anyhow::anyhow;
-use chrono::TimeDelta;
-use derive_more::Display;
-use schemars::JsonSchema_repr;
-use serde_repr::{Deserialize_repr, Serialize_repr};
-use std::str::FromStr;
-
-#[derive(
-    Debug,
-    Display,
-    Clone,
-    Copy,
-    PartialEq,
-    Eq,
-    PartialOrd,
-    Ord,
-    strum::EnumIter,
-    Hash,
-    Serialize_repr,
-    Deserialize_repr,
-    JsonSchema_repr,
-)]
-#[cfg_attr(feature = "juniper", derive(juniper::GraphQLEnum))]
-#[repr(i32)]
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Datelike, Months, NaiveTime, TimeDelta, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use schemars::{
+    r#gen::SchemaGenerator,
+    schema::{InstanceType, Metadata, Schema, SchemaObject, SubschemaValidation},
+    JsonSchema,
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{borrow::Cow, fmt, num::NonZeroU32, str::FromStr};
+
+/// Width of a candle.
+///
+/// Fixed widths are aligned to the unix epoch and serialize as their length
+/// in seconds, so the named variants keep their original wire values.
+/// Construct other fixed widths with [`CandleWidth::from_seconds`].
+///
+/// Calendar and session aligned widths serialize as strings, see
+/// [`CandleWidth::spec`].
+///
+/// Compatibility with the former closed enum: serde and JSON schema wire
+/// values of the named variants are unchanged, as are `Display`,
+/// `IntoEnumIterator` and [`CandleWidth::as_str`] for them.
+///
+/// **Breaking change:** GraphQL exposes `CandleWidth` as a scalar rather
+/// than an enum, since an enum can't carry the parameterized widths.  Named
+/// variants are still output as their enum names, e.g. `ONE_MINUTE`, and
+/// enum names are accepted as input, but introspection reports a scalar and
+/// clients generated from the old schema need regenerating.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "juniper", derive(juniper::GraphQLScalar))]
 pub enum CandleWidth {
-    OneSecond = 1,
-    FiveSecond = 5,
-    OneMinute = 60,
-    TwoMinute = 120,
-    ThreeMinute = 180,
-    FifteenMinute = 900,
-    OneHour = 3600,
-    OneDay = 86400,
+    OneSecond,
+    FiveSecond,
+    OneMinute,
+    TwoMinute,
+    ThreeMinute,
+    FifteenMinute,
+    OneHour,
+    OneDay,
+    /// Any other fixed width, in seconds
+    Seconds(NonZeroU32),
+    /// Calendar week starting Monday 00:00 UTC
+    OneWeek,
+    /// Calendar month starting on the 1st 00:00 UTC
+    OneMonth,
+    /// Trading day starting at `session_start` local time in `time_zone`,
+    /// e.g. 17:00 America/Chicago for CME Globex.
+    SessionDay {
+        time_zone: Tz,
+        session_start: NaiveTime,
+    },
 }
 
 impl CandleWidth {
+    /// The named widths with fixed wire values.
     pub fn all() -> Vec<Self> {
         vec![
             Self::OneSecond,
@@ -47,6 +67,45 @@ impl CandleWidth {
         ]
     }
 
+    /// Fixed width of `seconds`, using the named variant if there is one.
+    pub fn from_seconds(seconds: u32) -> Option<Self> {
+        let width = match seconds {
+            1 => Self::OneSecond,
+            5 => Self::FiveSecond,
+            60 => Self::OneMinute,
+            120 => Self::TwoMinute,
+            180 => Self::ThreeMinute,
+            900 => Self::FifteenMinute,
+            3600 => Self::OneHour,
+            86400 => Self::OneDay,
+            _ => Self::Seconds(NonZeroU32::new(seconds)?),
+        };
+        Some(width)
+    }
+
+    pub fn session_day(time_zone: Tz, session_start: NaiveTime) -> Self {
+        Self::SessionDay { time_zone, session_start }
+    }
+
+    fn variant_name(&self) -> Option<&'static str> {
+        let name = match self {
+            Self::OneSecond => "OneSecond",
+            Self::FiveSecond => "FiveSecond",
+            Self::OneMinute => "OneMinute",
+            Self::TwoMinute => "TwoMinute",
+            Self::ThreeMinute => "ThreeMinute",
+            Self::FifteenMinute => "FifteenMinute",
+            Self::OneHour => "OneHour",
+            Self::OneDay => "OneDay",
+            _ => return None,
+        };
+        Some(name)
+    }
+
+    /// Short name of the named and calendar widths, e.g. "1m", "1w" or
+    /// "1mo".  Parameterized widths have no static name and return "custom"
+    /// or "session"; use [`CandleWidth::spec`] for a string that identifies
+    /// any width.
     pub fn as_str(&self) -> &'static str {
         match self {
             Self::OneSecond => "1s",
@@ -57,11 +116,56 @@ impl CandleWidth {
             Self::FifteenMinute => "15m",
             Self::OneHour => "1h",
             Self::OneDay => "1d",
+            Self::OneWeek => "1w",
+            Self::OneMonth => "1mo",
+            Self::Seconds(_) => "custom",
+            Self::SessionDay { .. } => "session",
+        }
+    }
+
+    /// Any width as parsed by `FromStr`, e.g. "5m", "4h", "1w", "1mo" or
+    /// "1d@America/Chicago@17:00"; the session start is omitted if it's
+    /// midnight.
+    pub fn spec(&self) -> Cow<'static, str> {
+        match self {
+            Self::Seconds(s) => {
+                let s = s.get();
+                if s % 86400 == 0 {
+                    format!("{}d", s / 86400).into()
+                } else if s % 3600 == 0 {
+                    format!("{}h", s / 3600).into()
+                } else if s % 60 == 0 {
+                    format!("{}m", s / 60).into()
+                } else {
+                    format!("{s}s").into()
+                }
+            }
+            Self::SessionDay { time_zone, session_start } => {
+                if *session_start == NaiveTime::MIN {
+                    format!("1d@{}", time_zone.name()).into()
+                } else {
+                    format!("1d@{}@{}", time_zone.name(), session_start.format("%H:%M"))
+                        .into()
+                }
+            }
+            _ => self.as_str().into(),
         }
     }
 
+    /// Length in seconds; nominal for calendar and session aligned widths,
+    /// which treat a month as 30 days and ignore DST transitions.
     pub fn as_seconds(&self) -> i64 {
         match self {
+            Self::OneWeek => 7 * 86400,
+            Self::OneMonth => 30 * 86400,
+            Self::SessionDay { .. } => 86400,
+            _ => self.fixed_seconds().unwrap_or_default(),
+        }
+    }
+
+    /// Length in seconds of widths that are aligned to the unix epoch.
+    pub fn fixed_seconds(&self) -> Option<i64> {
+        let seconds = match self {
             Self::OneSecond => 1,
             Self::FiveSecond => 5,
             Self::OneMinute => 60,
@@ -70,25 +174,171 @@ impl CandleWidth {
             Self::FifteenMinute => 900,
             Self::OneHour => 3600,
             Self::OneDay => 86400,
+            Self::Seconds(s) => s.get() as i64,
+            Self::OneWeek | Self::OneMonth | Self::SessionDay { .. } => return None,
+        };
+        Some(seconds)
+    }
+
+    /// Start of the candle containing `time`.
+    pub fn bucket_start(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let ts = time.timestamp();
+        match self {
+            Self::OneWeek => {
+                // 1970-01-01 was a Thursday
+                let start = ts - (ts - 4 * 86400).rem_euclid(7 * 86400);
+                DateTime::from_timestamp(start, 0).unwrap_or(time)
+            }
+            Self::OneMonth => Utc
+                .with_ymd_and_hms(time.year(), time.month(), 1, 0, 0, 0)
+                .single()
+                .unwrap_or(time),
+            Self::SessionDay { time_zone, session_start } => {
+                let local = time.with_timezone(time_zone).naive_local();
+                let since_start =
+                    TimeDelta::seconds(session_start.num_seconds_from_midnight() as i64);
+                let day = (local - since_start).date();
+                resolve_local(time_zone, day.and_time(*session_start)).unwrap_or(time)
+            }
+            _ => {
+                let width = self.fixed_seconds().unwrap_or(1);
+                DateTime::from_timestamp(ts - ts.rem_euclid(width), 0).unwrap_or(time)
+            }
+        }
+    }
+
+    /// Start of the candle after the one starting at `bucket_start`.
+    pub fn next_bucket_start(&self, bucket_start: DateTime<Utc>) -> DateTime<Utc> {
+        let start = self.bucket_start(bucket_start);
+        match self {
+            Self::OneMonth => start + Months::new(1),
+            Self::SessionDay { time_zone, session_start } => {
+                let local = start.with_timezone(time_zone).naive_local();
+                let next_day = (local + TimeDelta::days(1)).date();
+                resolve_local(time_zone, next_day.and_time(*session_start))
+                    .unwrap_or(start + TimeDelta::days(1))
+            }
+            _ => start + TimeDelta::seconds(self.as_seconds()),
+        }
+    }
+
+    /// Whether candles of this width can be aggregated into candles of
+    /// `larger`, i.e. every `larger` boundary is also a boundary of this width.
+    pub fn rolls_up_into(&self, larger: CandleWidth) -> bool {
+        let Some(seconds) = self.fixed_seconds() else {
+            return false;
+        };
+        match larger {
+            Self::OneWeek | Self::OneMonth => 86400 % seconds == 0,
+            // UTC offsets are whole quarter hours
+            Self::SessionDay { session_start, .. } => {
+                900 % seconds == 0
+                    && session_start.num_seconds_from_midnight() as i64 % seconds == 0
+            }
+            _ => larger
+                .fixed_seconds()
+                .is_some_and(|larger| larger > seconds && larger % seconds == 0),
         }
     }
 }
 
+/// Resolve a local time to UTC, moving forward past DST gaps.
+pub(crate) fn resolve_local(
+    time_zone: &Tz,
+    local: chrono::NaiveDateTime,
+) -> Option<DateTime<Utc>> {
+    (0..=4).find_map(|quarter_hours| {
+        time_zone
+            .from_local_datetime(&(local + TimeDelta::minutes(15 * quarter_hours)))
+            .earliest()
+            .map(|dt| dt.with_timezone(&Utc))
+    })
+}
+
+/// Named variants display as their names, e.g. "OneMinute", as they always
+/// have; other widths display as [`CandleWidth::spec`].
+impl fmt::Display for CandleWidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.variant_name() {
+            Some(name) => f.write_str(name),
+            None => f.write_str(&self.spec()),
+        }
+    }
+}
+
+impl strum::IntoEnumIterator for CandleWidth {
+    type Iterator = std::vec::IntoIter<Self>;
+
+    /// The named widths, see [`CandleWidth::all`].
+    fn iter() -> Self::Iterator {
+        Self::all().into_iter()
+    }
+}
+
 impl FromStr for CandleWidth {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(session) = s.strip_prefix("1d@") {
+            let (time_zone, session_start) = match session.split_once('@') {
+                Some((time_zone, start)) => (
+                    time_zone,
+                    NaiveTime::parse_from_str(start, "%H:%M")
+                        .map_err(|_| anyhow!("invalid session start: {start}"))?,
+                ),
+                None => (session, NaiveTime::MIN),
+            };
+            let time_zone = Tz::from_str(time_zone)
+                .map_err(|_| anyhow!("invalid time zone: {time_zone}"))?;
+            return Ok(Self::SessionDay { time_zone, session_start });
+        }
         match s {
-            "1s" => Ok(Self::OneSecond),
-            "5s" => Ok(Self::FiveSecond),
-            "1m" => Ok(Self::OneMinute),
-            "2m" => Ok(Self::TwoMinute),
-            "3m" => Ok(Self::ThreeMinute),
-            "15m" => Ok(Self::FifteenMinute),
-            "1h" => Ok(Self::OneHour),
-            "1d" => Ok(Self::OneDay),
-            _ => Err(anyhow!("invalid candle width: {}", s)),
+            "1w" => return Ok(Self::OneWeek),
+            "1mo" | "1M" => return Ok(Self::OneMonth),
+            _ => {}
         }
+        let unit_at = s
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(|| anyhow!("invalid candle width: {s}"))?;
+        let (n, unit) = s.split_at(unit_at);
+        let n: u32 = n.parse().map_err(|_| anyhow!("invalid candle width: {s}"))?;
+        let unit_seconds = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "d" => 86400,
+            _ => bail!("invalid candle width: {s}"),
+        };
+        n.checked_mul(unit_seconds)
+            .and_then(Self::from_seconds)
+            .ok_or_else(|| anyhow!("invalid candle width: {s}"))
+    }
+}
+
+impl PartialEq for CandleWidth {
+    fn eq(&self, other: &Self) -> bool {
+        self.spec() == other.spec()
+    }
+}
+
+impl Eq for CandleWidth {}
+
+impl std::hash::Hash for CandleWidth {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.spec().hash(state)
+    }
+}
+
+impl PartialOrd for CandleWidth {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Ordered by length
+impl Ord for CandleWidth {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.as_seconds(), self.spec()).cmp(&(other.as_seconds(), other.spec()))
     }
 }
 
@@ -97,3 +347,218 @@ impl From<CandleWidth> for TimeDelta {
         TimeDelta::seconds(val.as_seconds())
     }
 }
+
+impl Serialize for CandleWidth {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.fixed_seconds() {
+            Some(seconds) => serializer.serialize_i64(seconds),
+            None => serializer.serialize_str(&self.spec()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CandleWidth {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CandleWidthVisitor;
+
+        impl de::Visitor<'_> for CandleWidthVisitor {
+            type Value = CandleWidth;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a candle width in seconds or a candle width string")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                u32::try_from(v)
+                    .ok()
+                    .and_then(CandleWidth::from_seconds)
+                    .ok_or_else(|| E::custom(format!("invalid candle width: {v}")))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                u32::try_from(v)
+                    .ok()
+                    .and_then(CandleWidth::from_seconds)
+                    .ok_or_else(|| E::custom(format!("invalid candle width: {v}")))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(CandleWidthVisitor)
+    }
+}
+
+impl JsonSchema for CandleWidth {
+    fn schema_name() -> String {
+        "CandleWidth".to_string()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        let seconds = SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "Fixed width in seconds, aligned to the unix epoch".to_string(),
+                ),
+                ..Default::default()
+            })),
+            instance_type: Some(InstanceType::Integer.into()),
+            format: Some("uint32".to_string()),
+            number: Some(Box::new(schemars::schema::NumberValidation {
+                minimum: Some(1.0),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let string = SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "Calendar or session aligned width, e.g. \"1w\", \"1mo\" or \
+                     \"1d@America/Chicago@17:00\""
+                        .to_string(),
+                ),
+                ..Default::default()
+            })),
+            instance_type: Some(InstanceType::String.into()),
+            ..Default::default()
+        };
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![seconds.into(), string.into()]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+#[cfg(feature = "juniper")]
+impl CandleWidth {
+    /// Names of the named variants when CandleWidth was a GraphQL enum
+    const GRAPHQL_ENUM_NAMES: [(&'static str, Self); 8] = [
+        ("ONE_SECOND", Self::OneSecond),
+        ("FIVE_SECOND", Self::FiveSecond),
+        ("ONE_MINUTE", Self::OneMinute),
+        ("TWO_MINUTE", Self::TwoMinute),
+        ("THREE_MINUTE", Self::ThreeMinute),
+        ("FIFTEEN_MINUTE", Self::FifteenMinute),
+        ("ONE_HOUR", Self::OneHour),
+        ("ONE_DAY", Self::OneDay),
+    ];
+
+    #[allow(clippy::wrong_self_convention)]
+    fn to_output<S: juniper::ScalarValue>(&self) -> juniper::Value<S> {
+        match Self::GRAPHQL_ENUM_NAMES.iter().find(|(_, width)| width == self) {
+            Some((name, _)) => juniper::Value::scalar(name.to_string()),
+            None => juniper::Value::scalar(self.spec().into_owned()),
+        }
+    }
+
+    fn from_input<S>(v: &juniper::InputValue<S>) -> Result<Self, String>
+    where
+        S: juniper::ScalarValue,
+    {
+        let s = v
+            .as_enum_value()
+            .or_else(|| v.as_string_value())
+            .ok_or_else(|| format!("Expected `String`, found: {v}"))?;
+        match Self::GRAPHQL_ENUM_NAMES.iter().find(|(name, _)| *name == s) {
+            Some((_, width)) => Ok(*width),
+            None => Self::from_str(s).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn parse_token<S>(value: juniper::ScalarToken<'_>) -> juniper::ParseScalarResult<S>
+    where
+        S: juniper::ScalarValue,
+    {
+        <String as juniper::ParseScalarValue<S>>::from_str(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candle_width_serde() {
+        for width in CandleWidth::all() {
+            let json = serde_json::to_string(&width).unwrap();
+            assert_eq!(json, width.as_seconds().to_string());
+            assert_eq!(width.as_str().parse::<CandleWidth>().unwrap(), width);
+            assert_eq!(width.spec(), width.as_str());
+        }
+        let five_minute: CandleWidth = serde_json::from_str("300").unwrap();
+        assert_eq!(five_minute, CandleWidth::from_seconds(300).unwrap());
+        assert_eq!(five_minute.spec(), "5m");
+        assert_eq!(five_minute.as_str(), "custom");
+        assert_eq!(CandleWidth::OneMonth.as_str(), "1mo");
+        assert_eq!("60m".parse::<CandleWidth>().unwrap(), CandleWidth::OneHour);
+        assert_eq!(
+            "4h".parse::<CandleWidth>().unwrap(),
+            CandleWidth::from_seconds(14400).unwrap()
+        );
+        assert!(serde_json::from_str::<CandleWidth>("0").is_err());
+        assert!("0m".parse::<CandleWidth>().is_err());
+        assert_eq!(CandleWidth::from_seconds(0), None);
+        assert_eq!(CandleWidth::OneMinute.to_string(), "OneMinute");
+        assert_eq!(five_minute.to_string(), "5m");
+        assert_eq!(
+            <CandleWidth as strum::IntoEnumIterator>::iter().collect::<Vec<_>>(),
+            CandleWidth::all()
+        );
+
+        let cme = CandleWidth::session_day(
+            chrono_tz::America::Chicago,
+            NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        );
+        let json = serde_json::to_string(&cme).unwrap();
+        assert_eq!(json, r#""1d@America/Chicago@17:00""#);
+        assert_eq!(serde_json::from_str::<CandleWidth>(&json).unwrap(), cme);
+        let mut buf = vec![];
+        rmp_serde::encode::write(&mut buf, &cme).unwrap();
+        assert_eq!(rmp_serde::decode::from_slice::<CandleWidth>(&buf).unwrap(), cme);
+        assert_eq!(serde_json::to_string(&CandleWidth::OneMonth).unwrap(), r#""1mo""#);
+        assert!(
+            CandleWidth::OneMinute < five_minute && five_minute < CandleWidth::OneHour
+        );
+    }
+
+    #[test]
+    fn test_candle_width_buckets() {
+        let t = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        let now = t("2025-03-09T20:30:00Z");
+        assert_eq!(CandleWidth::OneWeek.bucket_start(now), t("2025-03-03T00:00:00Z"));
+        assert_eq!(CandleWidth::OneMonth.bucket_start(now), t("2025-03-01T00:00:00Z"));
+        assert_eq!(
+            CandleWidth::OneMonth.next_bucket_start(t("2025-01-01T00:00:00Z")),
+            t("2025-02-01T00:00:00Z")
+        );
+        assert_eq!(
+            CandleWidth::from_seconds(14400).unwrap().bucket_start(now),
+            t("2025-03-09T20:00:00Z")
+        );
+        // US DST starts 2025-03-09, Chicago goes from UTC-6 to UTC-5
+        let cme: CandleWidth = "1d@America/Chicago@17:00".parse().unwrap();
+        assert_eq!(cme.bucket_start(now), t("2025-03-08T23:00:00Z"));
+        assert_eq!(
+            cme.next_bucket_start(cme.bucket_start(now)),
+            t("2025-03-09T22:00:00Z")
+        );
+        assert_eq!(
+            cme.bucket_start(t("2025-03-09T22:00:00Z")),
+            t("2025-03-09T22:00:00Z")
+        );
+
+        let five_minute = CandleWidth::from_seconds(300).unwrap();
+        assert!(CandleWidth::OneMinute.rolls_up_into(five_minute));
+        assert!(!CandleWidth::TwoMinute.rolls_up_into(five_minute));
+        assert!(CandleWidth::OneHour.rolls_up_into(CandleWidth::OneMonth));
+        assert!(CandleWidth::FifteenMinute.rolls_up_into(cme));
+        assert!(!CandleWidth::OneHour.rolls_up_into(cme));
+        assert!(!CandleWidth::OneWeek.rolls_up_into(CandleWidth::OneMonth));
+    }
+}
@@ -0,0 +1,292 @@
+//! Stitching candles of consecutive futures contracts into one continuous
+//! series, with optional back-adjustment at each roll.
+//!
+//! Back-adjusted series leave the most recent contract untouched and shift
+//! (difference) or scale (ratio) all earlier history so that there is no
+//! price jump at the roll.  Unadjusted series simply splice the contracts
+//! together and keep the gaps.
+
+use super::{Candle, CandleWidth, HistoricalCandlesRequest};
+use crate::symbology::{MarketdataVenue, RollEvent};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BackAdjustment {
+    /// Splice contracts together as-is; prices jump at each roll
+    Unadjusted,
+    /// Add the roll gap (new close - old close) to all earlier prices,
+    /// a.k.a. the panama method; preserves point moves
+    Difference,
+    /// Multiply all earlier prices by the roll ratio (new close / old
+    /// close); preserves percentage moves
+    Ratio,
+}
+
+/// Where the continuous series switched contracts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct RollPoint {
+    pub time: DateTime<Utc>,
+    pub from: String,
+    pub to: String,
+    /// Close of the outgoing contract in the last candle before the roll
+    pub from_price: Option<Decimal>,
+    /// Close of the incoming contract in the same candle, or its last
+    /// candle before the roll
+    pub to_price: Option<Decimal>,
+    /// Index into the continuous candles of the first candle from `to`
+    pub index: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ContinuousCandles {
+    pub symbol: String,
+    pub adjustment: BackAdjustment,
+    /// Candles with `symbol` set to the continuous symbol
+    pub candles: Vec<Candle>,
+    pub rolls: Vec<RollPoint>,
+}
+
+/// Historical candle requests covering `start..end` for each contract in
+/// the roll sequence.  Requests for incoming contracts start `overlap`
+/// before their roll so the roll gap can be measured.
+pub fn continuous_candles_requests(
+    rolls: &[RollEvent],
+    venue: Option<MarketdataVenue>,
+    candle_width: CandleWidth,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    overlap: Duration,
+) -> Result<Vec<HistoricalCandlesRequest>> {
+    let contracts = roll_sequence(rolls)?;
+    let mut requests = vec![];
+    for (i, contract) in contracts.iter().enumerate() {
+        let segment_start = if i == 0 { start } else { rolls[i - 1].time - overlap };
+        let segment_end = rolls.get(i).map_or(end, |roll| roll.time);
+        let start_date = segment_start.max(start);
+        let end_date = segment_end.min(end);
+        if start_date < end_date {
+            requests.push(HistoricalCandlesRequest {
+                venue: venue.clone(),
+                symbol: contract.to_string(),
+                candle_width,
+                start_date,
+                end_date,
+            });
+        }
+    }
+    Ok(requests)
+}
+
+/// Stitch the candles of every contract in the roll sequence into one
+/// series named `symbol`, e.g. "ES c1".  Each contract contributes its
+/// candles from the previous roll (inclusive) to its own roll (exclusive).
+///
+/// `candles` may mix contracts and be in any order; candles of symbols
+/// outside the roll sequence are ignored.
+pub fn stitch_continuous_candles(
+    symbol: impl Into<String>,
+    rolls: &[RollEvent],
+    candles: impl IntoIterator<Item = Candle>,
+    adjustment: BackAdjustment,
+) -> Result<ContinuousCandles> {
+    let symbol = symbol.into();
+    let contracts = roll_sequence(rolls)?;
+    let mut by_contract: BTreeMap<&str, Vec<Candle>> =
+        contracts.iter().map(|c| (*c, vec![])).collect();
+    for candle in candles {
+        if let Some(list) = by_contract.get_mut(candle.symbol.as_str()) {
+            list.push(candle);
+        }
+    }
+    for list in by_contract.values_mut() {
+        list.sort_by_key(|c| (c.timestamp, c.timestamp_ns));
+    }
+    // measure the gap at each roll
+    let mut roll_points = vec![];
+    for roll in rolls {
+        let from = &by_contract[roll.from.as_str()];
+        let to = &by_contract[roll.to.as_str()];
+        let from_last =
+            from.iter().rev().find(|c| before(c, roll.time) && c.close.is_some());
+        let to_price = from_last
+            .and_then(|last| {
+                to.iter().find(|c| c.timestamp == last.timestamp).and_then(|c| c.close)
+            })
+            .or_else(|| {
+                to.iter().rev().find(|c| before(c, roll.time)).and_then(|c| c.close)
+            });
+        roll_points.push(RollPoint {
+            time: roll.time,
+            from: roll.from.to_string(),
+            to: roll.to.to_string(),
+            from_price: from_last.and_then(|c| c.close),
+            to_price,
+            index: 0,
+        });
+    }
+    // cumulative adjustment per contract, working back from the latest
+    let mut offsets = vec![Decimal::ZERO; contracts.len()];
+    let mut factors = vec![Decimal::ONE; contracts.len()];
+    for (i, point) in roll_points.iter().enumerate().rev() {
+        let (mut offset, mut factor) = (offsets[i + 1], factors[i + 1]);
+        if let (Some(from), Some(to)) = (point.from_price, point.to_price) {
+            match adjustment {
+                BackAdjustment::Unadjusted => {}
+                BackAdjustment::Difference => offset += to - from,
+                BackAdjustment::Ratio => {
+                    if from <= Decimal::ZERO || to <= Decimal::ZERO {
+                        bail!(
+                            "ratio adjustment needs positive prices at roll {} -> {}",
+                            point.from,
+                            point.to
+                        );
+                    }
+                    factor *= to / from;
+                }
+            }
+        }
+        offsets[i] = offset;
+        factors[i] = factor;
+    }
+    let mut stitched = vec![];
+    for (i, contract) in contracts.iter().enumerate() {
+        if i > 0 {
+            roll_points[i - 1].index = stitched.len();
+        }
+        let start = i.checked_sub(1).map(|j| rolls[j].time);
+        let end = rolls.get(i).map(|roll| roll.time);
+        for candle in &by_contract[contract] {
+            if start.is_some_and(|start| before(candle, start))
+                || end.is_some_and(|end| !before(candle, end))
+            {
+                continue;
+            }
+            let mut candle = candle.clone();
+            candle.symbol.clone_from(&symbol);
+            adjust_prices(&mut candle, |px| px * factors[i] + offsets[i]);
+            stitched.push(candle);
+        }
+    }
+    Ok(ContinuousCandles { symbol, adjustment, candles: stitched, rolls: roll_points })
+}
+
+fn roll_sequence(rolls: &[RollEvent]) -> Result<Vec<&str>> {
+    let Some(first) = rolls.first() else {
+        bail!("no rolls to stitch");
+    };
+    let mut contracts = vec![first.from.as_str()];
+    for (i, roll) in rolls.iter().enumerate() {
+        if roll.from.as_str() != contracts[i] {
+            bail!(
+                "roll {i} is from {} but the previous roll was to {}",
+                roll.from,
+                contracts[i]
+            );
+        }
+        if i > 0 && roll.time < rolls[i - 1].time {
+            bail!("rolls are not in time order");
+        }
+        contracts.push(roll.to.as_str());
+    }
+    Ok(contracts)
+}
+
+fn before(candle: &Candle, time: DateTime<Utc>) -> bool {
+    (candle.timestamp, candle.timestamp_ns)
+        < (time.timestamp(), time.timestamp_subsec_nanos())
+}
+
+fn adjust_prices(candle: &mut Candle, f: impl Fn(Decimal) -> Decimal) {
+    for px in [
+        &mut candle.open,
+        &mut candle.high,
+        &mut candle.low,
+        &mut candle.close,
+        &mut candle.mid_open,
+        &mut candle.mid_high,
+        &mut candle.mid_low,
+        &mut candle.mid_close,
+        &mut candle.bid_open,
+        &mut candle.bid_high,
+        &mut candle.bid_low,
+        &mut candle.bid_close,
+        &mut candle.ask_open,
+        &mut candle.ask_high,
+        &mut candle.ask_low,
+        &mut candle.ask_close,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        *px = f(*px);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_stitch_continuous_candles() -> Result<()> {
+        let day = |d| Utc.with_ymd_and_hms(2026, 3, d, 0, 0, 0).unwrap();
+        let candle = |symbol: &str, d, close: Decimal| {
+            let mut candle = Candle::default(day(d), CandleWidth::OneDay, symbol.into());
+            candle.open = Some(close);
+            candle.close = Some(close);
+            candle
+        };
+        let (h, m) = ("ES 20260320 CME Future", "ES 20260618 CME Future");
+        let rolls = [RollEvent { time: day(12), from: h.parse()?, to: m.parse()? }];
+        let candles = vec![
+            candle(m, 11, dec!(110)),
+            candle(h, 10, dec!(98)),
+            candle(h, 11, dec!(100)),
+            candle(h, 12, dec!(101)),
+            candle(m, 12, dec!(112)),
+            candle(m, 13, dec!(113)),
+        ];
+        let closes = |c: &ContinuousCandles| {
+            c.candles.iter().map(|c| c.close.unwrap()).collect::<Vec<_>>()
+        };
+        let unadjusted = stitch_continuous_candles(
+            "ES c1",
+            &rolls,
+            candles.clone(),
+            BackAdjustment::Unadjusted,
+        )?;
+        assert_eq!(closes(&unadjusted), vec![dec!(98), dec!(100), dec!(112), dec!(113)]);
+        assert_eq!(unadjusted.rolls[0].index, 2);
+        assert_eq!(unadjusted.rolls[0].from_price, Some(dec!(100)));
+        assert_eq!(unadjusted.rolls[0].to_price, Some(dec!(110)));
+        assert!(unadjusted.candles.iter().all(|c| c.symbol == "ES c1"));
+        let difference = stitch_continuous_candles(
+            "ES c1",
+            &rolls,
+            candles.clone(),
+            BackAdjustment::Difference,
+        )?;
+        assert_eq!(closes(&difference), vec![dec!(108), dec!(110), dec!(112), dec!(113)]);
+        let ratio =
+            stitch_continuous_candles("ES c1", &rolls, candles, BackAdjustment::Ratio)?;
+        assert_eq!(closes(&ratio), vec![dec!(107.8), dec!(110.0), dec!(112), dec!(113)]);
+        let requests = continuous_candles_requests(
+            &rolls,
+            None,
+            CandleWidth::OneDay,
+            day(1),
+            day(20),
+            Duration::days(1),
+        )?;
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].start_date, day(11));
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+//! Implied probabilities from event contract markets.
+//!
+//! An event contract pays `payout` (usually 1 unit of the quote currency)
+//! if its outcome resolves Yes.  Buying No at `p` is equivalent to selling
+//! Yes at `payout - p`, so separate Yes and No books can be consolidated
+//! into a single Yes book, and prices divided by the payout read as
+//! probabilities.
+//!
+//! For a series whose outcomes are mutually exclusive, exactly one outcome
+//! resolves Yes, so buying Yes on every outcome costs the sum of the asks
+//! and pays out once.  If the asks sum to less than one, or the bids to
+//! more than one, the set can be traded as an arbitrage.
+
+use super::{L1BookSnapshot, L2Book};
+use crate::{
+    symbology::{EventContractOutcomes, EventContractSeriesInfo},
+    Dir,
+};
+use anyhow::{bail, Result};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use std::collections::BTreeMap;
+
+/// Best bid and ask for the Yes side, in probability units.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImpliedProbability {
+    /// (probability, quantity)
+    pub bid: Option<(Decimal, Decimal)>,
+    /// (probability, quantity)
+    pub ask: Option<(Decimal, Decimal)>,
+}
+
+impl ImpliedProbability {
+    /// Consolidate the Yes and No BBOs of one outcome; either may be
+    /// missing, e.g. for venues that list a single product per outcome.
+    pub fn from_l1(
+        yes: Option<&L1BookSnapshot>,
+        no: Option<&L1BookSnapshot>,
+        payout: Decimal,
+    ) -> Result<Self> {
+        if payout <= Decimal::ZERO {
+            bail!("payout must be positive");
+        }
+        let yes_bid = yes.and_then(|book| book.best_bid);
+        let yes_ask = yes.and_then(|book| book.best_ask);
+        // selling No is buying Yes and vice versa
+        let no_as_yes_bid =
+            no.and_then(|book| book.best_ask).map(|(px, sz)| (payout - px, sz));
+        let no_as_yes_ask =
+            no.and_then(|book| book.best_bid).map(|(px, sz)| (payout - px, sz));
+        let to_probability = |(px, sz): (Decimal, Decimal)| (px / payout, sz);
+        Ok(Self {
+            bid: best_level(Dir::Buy, yes_bid, no_as_yes_bid).map(to_probability),
+            ask: best_level(Dir::Sell, yes_ask, no_as_yes_ask).map(to_probability),
+        })
+    }
+
+    pub fn mid(&self) -> Option<Decimal> {
+        let (bid, _) = self.bid?;
+        let (ask, _) = self.ask?;
+        Some((bid + ask) / dec!(2))
+    }
+
+    /// True if the consolidated bid is at or through the ask, i.e. buying
+    /// Yes and No together costs no more than the payout.
+    pub fn is_crossed(&self) -> bool {
+        matches!((self.bid, self.ask), (Some((bid, _)), Some((ask, _))) if bid >= ask)
+    }
+}
+
+/// Better of two levels on the same side; sizes at the same price add.
+fn best_level(
+    side: Dir,
+    a: Option<(Decimal, Decimal)>,
+    b: Option<(Decimal, Decimal)>,
+) -> Option<(Decimal, Decimal)> {
+    match (a, b) {
+        (Some((a_px, a_sz)), Some((b_px, b_sz))) => {
+            if a_px == b_px {
+                Some((a_px, a_sz + b_sz))
+            } else if (side == Dir::Buy) == (a_px > b_px) {
+                Some((a_px, a_sz))
+            } else {
+                Some((b_px, b_sz))
+            }
+        }
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Consolidate Yes and No L2 books into a single book for the Yes side,
+/// in price (not probability) units.  The result has no sequence number.
+pub fn consolidated_yes_book(yes: &L2Book, no: &L2Book, payout: Decimal) -> L2Book {
+    let mut bids = yes.bids.clone();
+    let mut asks = yes.asks.clone();
+    for (px, sz) in &no.asks {
+        *bids.entry(payout - px).or_insert(Decimal::ZERO) += sz;
+    }
+    for (px, sz) in &no.bids {
+        *asks.entry(payout - px).or_insert(Decimal::ZERO) += sz;
+    }
+    let latest = if (no.timestamp, no.timestamp_ns) > (yes.timestamp, yes.timestamp_ns) {
+        no
+    } else {
+        yes
+    };
+    L2Book {
+        timestamp: latest.timestamp,
+        timestamp_ns: latest.timestamp_ns,
+        sequence: None,
+        bids,
+        asks,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventContractArbitrage {
+    /// Buy or sell Yes on every outcome
+    pub dir: Dir,
+    /// Guaranteed profit per set, in probability units
+    pub edge: Decimal,
+    /// Number of sets available at the top of book
+    pub quantity: Decimal,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutcomeSetReport {
+    /// Outcome name -> consolidated market
+    pub outcomes: BTreeMap<String, ImpliedProbability>,
+    /// None if any outcome has no bid
+    pub sum_bid: Option<Decimal>,
+    /// None if any outcome has no ask
+    pub sum_ask: Option<Decimal>,
+    /// None if any outcome has no two-sided market
+    pub sum_mid: Option<Decimal>,
+    pub arbitrage: Option<EventContractArbitrage>,
+}
+
+impl OutcomeSetReport {
+    /// Consolidated markets for a mutually exclusive, exhaustive set of
+    /// outcomes.  `min_edge` is the edge per set, e.g. fees, that an
+    /// arbitrage must exceed to be reported.
+    pub fn new(
+        outcomes: impl IntoIterator<Item = (String, ImpliedProbability)>,
+        min_edge: Decimal,
+    ) -> Self {
+        let outcomes: BTreeMap<_, _> = outcomes.into_iter().collect();
+        let sum = |f: &dyn Fn(&ImpliedProbability) -> Option<Decimal>| {
+            outcomes.values().map(f).sum::<Option<Decimal>>()
+        };
+        let sum_bid = sum(&|p| p.bid.map(|(px, _)| px));
+        let sum_ask = sum(&|p| p.ask.map(|(px, _)| px));
+        let sum_mid = sum(&|p| p.mid());
+        let min_size = |f: &dyn Fn(&ImpliedProbability) -> Option<Decimal>| {
+            outcomes.values().filter_map(f).min().unwrap_or_default()
+        };
+        let arbitrage = if let Some(sum_ask) =
+            sum_ask.filter(|s| Decimal::ONE - s > min_edge)
+        {
+            Some(EventContractArbitrage {
+                dir: Dir::Buy,
+                edge: Decimal::ONE - sum_ask,
+                quantity: min_size(&|p| p.ask.map(|(_, sz)| sz)),
+            })
+        } else if let Some(sum_bid) = sum_bid.filter(|s| s - Decimal::ONE > min_edge) {
+            Some(EventContractArbitrage {
+                dir: Dir::Sell,
+                edge: sum_bid - Decimal::ONE,
+                quantity: min_size(&|p| p.bid.map(|(_, sz)| sz)),
+            })
+        } else {
+            None
+        };
+        Self { outcomes, sum_bid, sum_ask, sum_mid, arbitrage }
+    }
+
+    /// Like [`Self::new`], checking that the series' outcomes are mutually
+    /// exclusive and that every enumerated outcome is quoted.
+    pub fn for_series(
+        series: &EventContractSeriesInfo,
+        outcomes: impl IntoIterator<Item = (String, ImpliedProbability)>,
+        min_edge: Decimal,
+    ) -> Result<Self> {
+        if !series.outcomes_are_mutually_exclusive {
+            bail!(
+                "outcomes of {} are not mutually exclusive",
+                series.event_contract_series
+            );
+        }
+        let EventContractOutcomes::Enumerated { outcomes: expected } = &series.outcomes
+        else {
+            bail!("mutually exclusive outcome sets must be enumerated");
+        };
+        let report = Self::new(outcomes, min_edge);
+        for outcome in expected {
+            if !report.outcomes.contains_key(&outcome.name) {
+                bail!("missing market for outcome {}", outcome.name);
+            }
+        }
+        if report.outcomes.len() != expected.len() {
+            bail!("markets given for outcomes not in the series");
+        }
+        Ok(report)
+    }
+
+    /// True if the mid probabilities sum to within `tolerance` of one.
+    pub fn is_consistent(&self, tolerance: Decimal) -> bool {
+        self.sum_mid.is_some_and(|sum| (sum - Decimal::ONE).abs() <= tolerance)
+    }
+
+    /// Mid probabilities scaled to sum to one, for outcomes with a
+    /// two-sided market.
+    pub fn normalized_probabilities(&self) -> Option<BTreeMap<String, Decimal>> {
+        let sum_mid = self.sum_mid.filter(|sum| !sum.is_zero())?;
+        self.outcomes
+            .iter()
+            .map(|(name, p)| Some((name.clone(), p.mid()? / sum_mid)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbology::{EventContractSeriesInstance, Outcome};
+
+    fn l1(
+        bid: Option<(Decimal, Decimal)>,
+        ask: Option<(Decimal, Decimal)>,
+    ) -> L1BookSnapshot {
+        L1BookSnapshot {
+            symbol: String::new(),
+            timestamp: 0,
+            timestamp_ns: 0,
+            recv_time: None,
+            recv_time_ns: None,
+            best_bid: bid,
+            best_ask: ask,
+        }
+    }
+
+    fn series() -> EventContractSeriesInfo {
+        EventContractSeriesInfo {
+            event_contract_series: "2024 Presidential Election KALSHI Event Contracts"
+                .parse()
+                .unwrap(),
+            quote_symbol: "USD".parse().unwrap(),
+            underlying: None,
+            expiration: None,
+            outcomes: EventContractOutcomes::Enumerated {
+                outcomes: ["Harris", "Trump", "Other"]
+                    .into_iter()
+                    .map(|name| Outcome { name: name.into() })
+                    .collect(),
+            },
+            outcomes_side: None,
+            outcomes_are_mutually_exclusive: true,
+        }
+    }
+
+    #[test]
+    fn test_implied_probability() -> Result<()> {
+        // No ask 0.45 implies a Yes bid of 0.55, better than the Yes book
+        let yes = l1(Some((dec!(0.52), dec!(10))), Some((dec!(0.58), dec!(5))));
+        let no = l1(Some((dec!(0.42), dec!(3))), Some((dec!(0.45), dec!(7))));
+        let p = ImpliedProbability::from_l1(Some(&yes), Some(&no), Decimal::ONE)?;
+        assert_eq!(p.bid, Some((dec!(0.55), dec!(7))));
+        assert_eq!(p.ask, Some((dec!(0.58), dec!(8))));
+        assert_eq!(p.mid(), Some(dec!(0.565)));
+        // prices in cents
+        let yes = l1(Some((dec!(52), dec!(10))), None);
+        let p = ImpliedProbability::from_l1(Some(&yes), None, dec!(100))?;
+        assert_eq!(p.bid, Some((dec!(0.52), dec!(10))));
+        assert_eq!(p.ask, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_outcome_set_arbitrage() -> Result<()> {
+        let series = series();
+        let product = series.get_product(&EventContractSeriesInstance::Enumerated {
+            outcome: Outcome { name: "Trump".into() },
+        })?;
+        assert_eq!(
+            product.as_str(),
+            "2024 Presidential Election KALSHI Trump Event Contract"
+        );
+        let quote = |bid: Decimal, ask: Decimal, size: Decimal| ImpliedProbability {
+            bid: Some((bid, size)),
+            ask: Some((ask, size)),
+        };
+        let markets = vec![
+            ("Harris".to_string(), quote(dec!(0.40), dec!(0.42), dec!(100))),
+            ("Trump".to_string(), quote(dec!(0.52), dec!(0.54), dec!(50))),
+            ("Other".to_string(), quote(dec!(0.01), dec!(0.02), dec!(1000))),
+        ];
+        let report = OutcomeSetReport::for_series(&series, markets.clone(), dec!(0))?;
+        assert_eq!(report.sum_ask, Some(dec!(0.98)));
+        assert!(!report.is_consistent(dec!(0.01)));
+        assert!(report.is_consistent(dec!(0.05)));
+        assert_eq!(
+            report.arbitrage,
+            Some(EventContractArbitrage {
+                dir: Dir::Buy,
+                edge: dec!(0.02),
+                quantity: dec!(50)
+            })
+        );
+        // fees eat the edge
+        let report = OutcomeSetReport::for_series(&series, markets.clone(), dec!(0.03))?;
+        assert_eq!(report.arbitrage, None);
+        // every outcome must be quoted
+        assert!(OutcomeSetReport::for_series(&series, markets[..2].to_vec(), dec!(0))
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,316 @@
+//! Client-side L2 order book, maintained from a stream of [`L2BookUpdate`]s.
+
+use super::{L1BookSnapshot, L2BookDiff, L2BookSnapshot, L2BookUpdate};
+use crate::{Dir, DirPair, SequenceIdAndNumber};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use std::collections::BTreeMap;
+
+/// Errors raised while applying updates to an [`L2Book`].
+///
+/// On any error the book is left untouched; the client should re-request
+/// a snapshot from the server before applying further diffs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L2BookError {
+    /// A diff was received before any snapshot.
+    NoSnapshot,
+    /// The diff skipped one or more sequence numbers.
+    SequenceGap { expected: SequenceIdAndNumber, received: SequenceIdAndNumber },
+    /// The diff is at or behind the current sequence number.
+    StaleUpdate { current: SequenceIdAndNumber, received: SequenceIdAndNumber },
+    /// The sequence id changed, signalling a new sequence.
+    SequenceReset { current: SequenceIdAndNumber, received: SequenceIdAndNumber },
+}
+
+impl std::fmt::Display for L2BookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoSnapshot => write!(f, "received diff before snapshot"),
+            Self::SequenceGap { expected, received } => {
+                write!(f, "sequence gap: expected {expected}, received {received}")
+            }
+            Self::StaleUpdate { current, received } => {
+                write!(f, "stale update: current {current}, received {received}")
+            }
+            Self::SequenceReset { current, received } => {
+                write!(f, "sequence reset: current {current}, received {received}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for L2BookError {}
+
+#[derive(Debug, Default, Clone)]
+pub struct L2Book {
+    pub timestamp: i64,
+    pub timestamp_ns: u32,
+    /// None until the first snapshot is applied
+    pub sequence: Option<SequenceIdAndNumber>,
+    /// price -> quantity
+    pub bids: BTreeMap<Decimal, Decimal>,
+    /// price -> quantity
+    pub asks: BTreeMap<Decimal, Decimal>,
+}
+
+impl L2Book {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_snapshot(snapshot: &L2BookSnapshot) -> Self {
+        let mut book = Self::new();
+        book.apply_snapshot(snapshot);
+        book
+    }
+
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(self.timestamp, self.timestamp_ns)
+    }
+
+    /// True if a snapshot has been applied and the book is in sequence.
+    pub fn is_initialized(&self) -> bool {
+        self.sequence.is_some()
+    }
+
+    pub fn apply(&mut self, update: &L2BookUpdate) -> Result<(), L2BookError> {
+        match update {
+            L2BookUpdate::Snapshot(snapshot) => {
+                self.apply_snapshot(snapshot);
+                Ok(())
+            }
+            L2BookUpdate::Diff(diff) => self.apply_diff(diff),
+        }
+    }
+
+    /// Replace the contents of the book with the snapshot.
+    pub fn apply_snapshot(&mut self, snapshot: &L2BookSnapshot) {
+        self.timestamp = snapshot.timestamp;
+        self.timestamp_ns = snapshot.timestamp_ns;
+        self.sequence = Some(snapshot.sequence);
+        self.bids.clear();
+        self.asks.clear();
+        for (price, size) in &snapshot.bids {
+            if !size.is_zero() {
+                self.bids.insert(*price, *size);
+            }
+        }
+        for (price, size) in &snapshot.asks {
+            if !size.is_zero() {
+                self.asks.insert(*price, *size);
+            }
+        }
+    }
+
+    /// Apply a diff if it is next in sequence; otherwise return an error
+    /// describing why it was not applied.
+    pub fn apply_diff(&mut self, diff: &L2BookDiff) -> Result<(), L2BookError> {
+        let current = self.sequence.ok_or(L2BookError::NoSnapshot)?;
+        let received = diff.sequence;
+        if !received.is_next_in_sequence(&current) {
+            return Err(if received.sequence_id != current.sequence_id {
+                L2BookError::SequenceReset { current, received }
+            } else if received.sequence_number <= current.sequence_number {
+                L2BookError::StaleUpdate { current, received }
+            } else {
+                L2BookError::SequenceGap { expected: current.next(), received }
+            });
+        }
+        self.timestamp = diff.timestamp;
+        self.timestamp_ns = diff.timestamp_ns;
+        self.sequence = Some(received);
+        for (price, size) in &diff.bids {
+            update_level(&mut self.bids, *price, *size);
+        }
+        for (price, size) in &diff.asks {
+            update_level(&mut self.asks, *price, *size);
+        }
+        Ok(())
+    }
+
+    /// Clear the book, e.g. after an error, so that only a new snapshot
+    /// can bring it back in sequence.
+    pub fn clear(&mut self) {
+        self.sequence = None;
+        self.bids.clear();
+        self.asks.clear();
+    }
+
+    /// (price, quantity)
+    pub fn best_bid(&self) -> Option<(Decimal, Decimal)> {
+        self.bids.iter().next_back().map(|(px, sz)| (*px, *sz))
+    }
+
+    /// (price, quantity)
+    pub fn best_ask(&self) -> Option<(Decimal, Decimal)> {
+        self.asks.iter().next().map(|(px, sz)| (*px, *sz))
+    }
+
+    pub fn bbo(&self) -> DirPair<Option<Decimal>> {
+        DirPair {
+            buy: self.best_bid().map(|(px, _)| px),
+            sell: self.best_ask().map(|(px, _)| px),
+        }
+    }
+
+    pub fn mid_price(&self) -> Option<Decimal> {
+        let (bid_px, _) = self.best_bid()?;
+        let (ask_px, _) = self.best_ask()?;
+        Some((bid_px + ask_px) / dec!(2))
+    }
+
+    pub fn spread(&self) -> Option<Decimal> {
+        let (bid_px, _) = self.best_bid()?;
+        let (ask_px, _) = self.best_ask()?;
+        Some(ask_px - bid_px)
+    }
+
+    /// Iterate over the levels of one side of the book, best price first.
+    /// `Dir::Buy` is the bid side, `Dir::Sell` is the ask side.
+    pub fn levels(&self, dir: Dir) -> Box<dyn Iterator<Item = (Decimal, Decimal)> + '_> {
+        match dir {
+            Dir::Buy => Box::new(self.bids.iter().rev().map(|(px, sz)| (*px, *sz))),
+            Dir::Sell => Box::new(self.asks.iter().map(|(px, sz)| (*px, *sz))),
+        }
+    }
+
+    /// Quantity resting at exactly `price` on the given side.
+    pub fn depth_at_price(&self, dir: Dir, price: Decimal) -> Decimal {
+        let side = match dir {
+            Dir::Buy => &self.bids,
+            Dir::Sell => &self.asks,
+        };
+        side.get(&price).copied().unwrap_or(Decimal::ZERO)
+    }
+
+    /// Total quantity resting at `price` or better on the given side.
+    pub fn cumulative_depth(&self, dir: Dir, price: Decimal) -> Decimal {
+        match dir {
+            Dir::Buy => self.bids.range(price..).map(|(_, sz)| *sz).sum(),
+            Dir::Sell => self.asks.range(..=price).map(|(_, sz)| *sz).sum(),
+        }
+    }
+
+    /// Volume-weighted average price to execute `size` against the book,
+    /// for an aggressing order in direction `dir` (a buy takes the asks).
+    ///
+    /// Returns None if `size` is not positive or the book is too thin.
+    pub fn vwap_to_size(&self, dir: Dir, size: Decimal) -> Option<Decimal> {
+        if size <= Decimal::ZERO {
+            return None;
+        }
+        let mut remaining = size;
+        let mut notional = Decimal::ZERO;
+        for (px, sz) in self.levels(dir.flip()) {
+            let take = remaining.min(sz);
+            notional += take * px;
+            remaining -= take;
+            if remaining.is_zero() {
+                return Some(notional / size);
+            }
+        }
+        None
+    }
+
+    pub fn to_l1_book_snapshot(&self, symbol: impl Into<String>) -> L1BookSnapshot {
+        L1BookSnapshot {
+            symbol: symbol.into(),
+            timestamp: self.timestamp,
+            timestamp_ns: self.timestamp_ns,
+            recv_time: None,
+            recv_time_ns: None,
+            best_bid: self.best_bid(),
+            best_ask: self.best_ask(),
+        }
+    }
+}
+
+fn update_level(side: &mut BTreeMap<Decimal, Decimal>, price: Decimal, size: Decimal) {
+    if size.is_zero() {
+        side.remove(&price);
+    } else {
+        side.insert(price, size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> L2BookUpdate {
+        serde_json::from_str(
+            r#"{
+                "t": "s", "ts": 1729700837, "tn": 0, "sid": 123, "sn": 8999,
+                "b": [["99.00", "3"], ["98.78", "2"]],
+                "a": [["100.00", "1"], ["100.10", "2"]]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn diff(sid: u64, sn: u64, bids: &str, asks: &str) -> L2BookUpdate {
+        serde_json::from_str(&format!(
+            r#"{{"t": "d", "ts": 1729700839, "tn": 0, "sid": {sid}, "sn": {sn},
+                "b": {bids}, "a": {asks}}}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_apply_updates() {
+        let mut book = L2Book::new();
+        assert_eq!(
+            book.apply(&diff(123, 9000, "[]", "[]")),
+            Err(L2BookError::NoSnapshot)
+        );
+        book.apply(&snapshot()).unwrap();
+        assert_eq!(book.best_bid(), Some((dec!(99.00), dec!(3))));
+        assert_eq!(book.best_ask(), Some((dec!(100.00), dec!(1))));
+        assert_eq!(book.mid_price(), Some(dec!(99.50)));
+
+        book.apply(&diff(123, 9000, r#"[["99.00", "0"]]"#, r#"[["99.50", "4"]]"#))
+            .unwrap();
+        assert_eq!(book.best_bid(), Some((dec!(98.78), dec!(2))));
+        assert_eq!(book.best_ask(), Some((dec!(99.50), dec!(4))));
+        assert_eq!(book.sequence, Some(SequenceIdAndNumber::new(123, 9000)));
+
+        assert_eq!(
+            book.apply(&diff(123, 9005, "[]", "[]")),
+            Err(L2BookError::SequenceGap {
+                expected: SequenceIdAndNumber::new(123, 9001),
+                received: SequenceIdAndNumber::new(123, 9005),
+            })
+        );
+        assert_eq!(
+            book.apply(&diff(123, 9000, "[]", "[]")),
+            Err(L2BookError::StaleUpdate {
+                current: SequenceIdAndNumber::new(123, 9000),
+                received: SequenceIdAndNumber::new(123, 9000),
+            })
+        );
+        assert!(matches!(
+            book.apply(&diff(170, 9001, "[]", "[]")),
+            Err(L2BookError::SequenceReset { .. })
+        ));
+        // book is untouched by rejected diffs
+        assert_eq!(book.best_ask(), Some((dec!(99.50), dec!(4))));
+    }
+
+    #[test]
+    fn test_depth_and_vwap() {
+        let mut book = L2Book::new();
+        book.apply(&snapshot()).unwrap();
+        assert_eq!(book.depth_at_price(Dir::Buy, dec!(99.00)), dec!(3));
+        assert_eq!(book.depth_at_price(Dir::Sell, dec!(99.00)), dec!(0));
+        assert_eq!(book.cumulative_depth(Dir::Buy, dec!(98.78)), dec!(5));
+        assert_eq!(book.cumulative_depth(Dir::Sell, dec!(100.00)), dec!(1));
+        assert_eq!(book.vwap_to_size(Dir::Buy, dec!(1)), Some(dec!(100.00)));
+        assert_eq!(book.vwap_to_size(Dir::Buy, dec!(2)), Some(dec!(100.05)));
+        assert_eq!(book.vwap_to_size(Dir::Buy, dec!(4)), None);
+        assert_eq!(book.vwap_to_size(Dir::Sell, dec!(4)), Some(dec!(98.945)));
+        let l1 = book.to_l1_book_snapshot("BTC Crypto/USD");
+        assert_eq!(l1.best_bid, Some((dec!(99.00), dec!(3))));
+        assert_eq!(l1.best_ask, Some((dec!(100.00), dec!(1))));
+    }
+}
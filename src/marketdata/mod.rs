@@ -15,7 +15,16 @@ use strum::EnumString;
 
 pub mod candle_width;
 pub use candle_width::CandleWidth;
+pub mod candle_builder;
+pub mod continuous_candles;
+pub mod event_contract_pricing;
+pub use candle_builder::CandleBuilder;
+pub mod l2_book;
+pub use l2_book::{L2Book, L2BookError};
 pub mod options_marketdata;
+pub mod options_pricing;
+pub mod spread_pricing;
+pub mod vol_surface;
 
 #[grpc(package = "json.architect")]
 #[grpc(service = "Marketdata", name = "l1_book_snapshot", response = "L1BookSnapshot")]
@@ -170,7 +179,8 @@ impl L2BookDiff {
 /// following with diffs.
 ///
 /// Diffs should be applied consecutively to the snapshot in order to reconstruct
-/// the state of the book.
+/// the state of the book.  [`L2Book`] implements this for you; the example below
+/// shows what it does by hand.
 ///
 /// ```rust
 /// # use architect_api::marketdata::*;
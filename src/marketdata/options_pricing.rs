@@ -0,0 +1,585 @@
+//! Local options pricing, greeks and implied volatility.
+//!
+//! European options are priced in closed form with the generalized
+//! Black-Scholes model, which covers Black-Scholes-Merton on spot and
+//! Black-76 on futures through the cost of carry.  American options are
+//! priced on a Cox-Ross-Rubinstein binomial tree.
+//!
+//! Units follow the usual screen conventions: volatility and rates are
+//! annualized fractions (0.2 is 20%), theta is per calendar day, and vega
+//! and rho are per percentage point.
+
+use super::options_marketdata::OptionsGreeks;
+use crate::symbology::{
+    OptionsExerciseType, OptionsSeriesInfo, OptionsSeriesInstance, PutOrCall,
+};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use rust_decimal::{
+    prelude::{FromPrimitive, ToPrimitive},
+    Decimal,
+};
+
+pub(crate) const SECONDS_PER_YEAR: f64 = 365.0 * 86400.0;
+const DEFAULT_BINOMIAL_STEPS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OptionsPricingInputs {
+    pub put_or_call: PutOrCall,
+    pub underlying_price: f64,
+    pub strike: f64,
+    /// In years
+    pub time_to_expiry: f64,
+    pub volatility: f64,
+    /// Continuously compounded risk-free rate
+    pub rate: f64,
+    /// Continuous cost of carry: `rate - dividend_yield` for options on
+    /// spot, zero for options on futures.  Rho holds the dividend yield
+    /// fixed, or the carry fixed at zero when it is zero.
+    pub carry: f64,
+}
+
+impl OptionsPricingInputs {
+    pub fn intrinsic_value(&self) -> f64 {
+        match self.put_or_call {
+            PutOrCall::Call => (self.underlying_price - self.strike).max(0.0),
+            PutOrCall::Put => (self.strike - self.underlying_price).max(0.0),
+        }
+    }
+
+    fn with_volatility(&self, volatility: f64) -> Self {
+        Self { volatility, ..*self }
+    }
+}
+
+/// Price and greeks of one unit of an option.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Greeks {
+    pub price: f64,
+    pub delta: f64,
+    pub gamma: f64,
+    /// Per calendar day
+    pub theta: f64,
+    /// Per percentage point of volatility
+    pub vega: f64,
+    /// Per percentage point of rate
+    pub rho: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionsPricingModel {
+    /// Generalized Black-Scholes
+    European,
+    /// CRR binomial tree with early exercise
+    American { steps: usize },
+}
+
+impl OptionsPricingModel {
+    pub fn for_exercise_type(exercise_type: OptionsExerciseType) -> Self {
+        match exercise_type {
+            OptionsExerciseType::American => {
+                Self::American { steps: DEFAULT_BINOMIAL_STEPS }
+            }
+            OptionsExerciseType::European | OptionsExerciseType::Unknown => {
+                Self::European
+            }
+        }
+    }
+
+    pub fn price(&self, inputs: &OptionsPricingInputs) -> f64 {
+        match self {
+            Self::European => black_scholes(inputs).price,
+            Self::American { steps } => binomial_tree(inputs, *steps).0,
+        }
+    }
+
+    pub fn greeks(&self, inputs: &OptionsPricingInputs) -> Greeks {
+        match self {
+            Self::European => black_scholes(inputs),
+            Self::American { steps } => binomial_greeks(inputs, *steps),
+        }
+    }
+
+    /// Solve for the volatility that reproduces `price`, ignoring the
+    /// volatility in `inputs`.
+    pub fn implied_volatility(
+        &self,
+        inputs: &OptionsPricingInputs,
+        price: f64,
+    ) -> Result<f64> {
+        if inputs.time_to_expiry <= 0.0 {
+            bail!("option is expired");
+        }
+        let (mut lo, mut hi) = (1e-6, 10.0);
+        let price_at = |vol: f64| self.price(&inputs.with_volatility(vol));
+        let (p_lo, p_hi) = (price_at(lo), price_at(hi));
+        if !(p_lo - 1e-9..=p_hi).contains(&price) {
+            bail!("price {price} is outside the no-arbitrage range [{p_lo}, {p_hi}]");
+        }
+        // Newton's method, falling back to bisection when a step leaves the
+        // bracket
+        let mut vol = 0.3;
+        for _ in 0..100 {
+            let greeks = self.greeks(&inputs.with_volatility(vol));
+            let diff = greeks.price - price;
+            if diff.abs() < 1e-10 {
+                return Ok(vol);
+            }
+            if diff > 0.0 {
+                hi = vol;
+            } else {
+                lo = vol;
+            }
+            let vega = greeks.vega * 100.0;
+            let next = vol - diff / vega;
+            vol = if vega > 1e-12 && next > lo && next < hi {
+                next
+            } else {
+                (lo + hi) / 2.0
+            };
+            if hi - lo < 1e-12 {
+                return Ok(vol);
+            }
+        }
+        Ok(vol)
+    }
+}
+
+/// Generalized Black-Scholes price and greeks.
+fn black_scholes(inputs: &OptionsPricingInputs) -> Greeks {
+    let OptionsPricingInputs {
+        put_or_call,
+        underlying_price: s,
+        strike: k,
+        time_to_expiry: t,
+        volatility: v,
+        rate: r,
+        carry: b,
+    } = *inputs;
+    if t <= 0.0 || v <= 0.0 {
+        return expired_greeks(inputs);
+    }
+    let sqrt_t = t.sqrt();
+    let d1 = ((s / k).ln() + (b + v * v / 2.0) * t) / (v * sqrt_t);
+    let d2 = d1 - v * sqrt_t;
+    let carry_df = ((b - r) * t).exp();
+    let df = (-r * t).exp();
+    let pdf_d1 = norm_pdf(d1);
+    let gamma = carry_df * pdf_d1 / (s * v * sqrt_t);
+    let vega = s * carry_df * pdf_d1 * sqrt_t;
+    let decay = -s * carry_df * pdf_d1 * v / (2.0 * sqrt_t);
+    let (price, delta, theta, rho) = match put_or_call {
+        PutOrCall::Call => {
+            let price = s * carry_df * norm_cdf(d1) - k * df * norm_cdf(d2);
+            let theta =
+                decay - (b - r) * s * carry_df * norm_cdf(d1) - r * k * df * norm_cdf(d2);
+            let rho = if b == 0.0 { -t * price } else { t * k * df * norm_cdf(d2) };
+            (price, carry_df * norm_cdf(d1), theta, rho)
+        }
+        PutOrCall::Put => {
+            let price = k * df * norm_cdf(-d2) - s * carry_df * norm_cdf(-d1);
+            let theta = decay
+                + (b - r) * s * carry_df * norm_cdf(-d1)
+                + r * k * df * norm_cdf(-d2);
+            let rho = if b == 0.0 { -t * price } else { -t * k * df * norm_cdf(-d2) };
+            (price, carry_df * (norm_cdf(d1) - 1.0), theta, rho)
+        }
+    };
+    Greeks {
+        price,
+        delta,
+        gamma,
+        theta: theta / 365.0,
+        vega: vega / 100.0,
+        rho: rho / 100.0,
+    }
+}
+
+/// Returns the price and the tree's first two layers of option values,
+/// `[f(1,0), f(1,1)]` and `[f(2,0), f(2,1), f(2,2)]`, for greeks.
+fn binomial_tree(
+    inputs: &OptionsPricingInputs,
+    steps: usize,
+) -> (f64, [f64; 2], [f64; 3]) {
+    let OptionsPricingInputs {
+        put_or_call,
+        underlying_price: s,
+        strike: k,
+        time_to_expiry: t,
+        volatility: v,
+        rate: r,
+        carry: b,
+    } = *inputs;
+    if t <= 0.0 || v <= 0.0 {
+        let price = inputs.intrinsic_value();
+        return (price, [price; 2], [price; 3]);
+    }
+    let steps = steps.max(3);
+    let dt = t / steps as f64;
+    let u = (v * dt.sqrt()).exp();
+    let d = 1.0 / u;
+    let p = ((b * dt).exp() - d) / (u - d);
+    let df = (-r * dt).exp();
+    let exercise = |price: f64| match put_or_call {
+        PutOrCall::Call => (price - k).max(0.0),
+        PutOrCall::Put => (k - price).max(0.0),
+    };
+    // values[i] is the node with i down moves
+    let mut values: Vec<f64> = (0..=steps)
+        .map(|i| exercise(s * u.powi((steps - i) as i32) * d.powi(i as i32)))
+        .collect();
+    let (mut layer1, mut layer2) = ([0.0; 2], [0.0; 3]);
+    for step in (0..steps).rev() {
+        for i in 0..=step {
+            let spot = s * u.powi((step - i) as i32) * d.powi(i as i32);
+            let cont = df * (p * values[i] + (1.0 - p) * values[i + 1]);
+            values[i] = cont.max(exercise(spot));
+        }
+        match step {
+            2 => layer2.copy_from_slice(&values[..3]),
+            1 => layer1.copy_from_slice(&values[..2]),
+            _ => {}
+        }
+    }
+    (values[0], layer1, layer2)
+}
+
+fn binomial_greeks(inputs: &OptionsPricingInputs, steps: usize) -> Greeks {
+    if inputs.time_to_expiry <= 0.0 || inputs.volatility <= 0.0 {
+        return expired_greeks(inputs);
+    }
+    let s = inputs.underlying_price;
+    let dt = inputs.time_to_expiry / steps.max(3) as f64;
+    let u = (inputs.volatility * dt.sqrt()).exp();
+    let d = 1.0 / u;
+    let (price, [f_u, f_d], [f_uu, f_ud, f_dd]) = binomial_tree(inputs, steps);
+    let delta = (f_u - f_d) / (s * u - s * d);
+    let delta_up = (f_uu - f_ud) / (s * u * u - s);
+    let delta_down = (f_ud - f_dd) / (s - s * d * d);
+    let gamma = (delta_up - delta_down) / ((s * u * u - s * d * d) / 2.0);
+    let theta = (f_ud - price) / (2.0 * dt);
+    let dv = 1e-4;
+    let vega = {
+        let up = inputs.with_volatility(inputs.volatility + dv);
+        let down = inputs.with_volatility(inputs.volatility - dv);
+        (binomial_tree(&up, steps).0 - binomial_tree(&down, steps).0) / (2.0 * dv)
+    };
+    let dr = 1e-4;
+    let rho = {
+        // holding the dividend yield fixed for spot, the carry moves with the rate
+        let carry_bump = if inputs.carry == 0.0 { 0.0 } else { dr };
+        let up = OptionsPricingInputs {
+            rate: inputs.rate + dr,
+            carry: inputs.carry + carry_bump,
+            ..*inputs
+        };
+        let down = OptionsPricingInputs {
+            rate: inputs.rate - dr,
+            carry: inputs.carry - carry_bump,
+            ..*inputs
+        };
+        (binomial_tree(&up, steps).0 - binomial_tree(&down, steps).0) / (2.0 * dr)
+    };
+    Greeks {
+        price,
+        delta,
+        gamma,
+        theta: theta / 365.0,
+        vega: vega / 100.0,
+        rho: rho / 100.0,
+    }
+}
+
+fn expired_greeks(inputs: &OptionsPricingInputs) -> Greeks {
+    let price = inputs.intrinsic_value();
+    let delta = match inputs.put_or_call {
+        PutOrCall::Call if price > 0.0 => 1.0,
+        PutOrCall::Put if price > 0.0 => -1.0,
+        _ => 0.0,
+    };
+    Greeks { price, delta, ..Default::default() }
+}
+
+fn norm_pdf(x: f64) -> f64 {
+    (-x * x / 2.0).exp() / (2.0 * std::f64::consts::PI).sqrt()
+}
+
+/// Hart's double precision approximation, per West (2005).
+fn norm_cdf(x: f64) -> f64 {
+    let x_abs = x.abs();
+    let tail = if x_abs > 37.0 {
+        0.0
+    } else {
+        let e = (-x_abs * x_abs / 2.0).exp();
+        if x_abs < 7.07106781186547 {
+            let mut n = 3.52624965998911e-02 * x_abs + 0.700383064443688;
+            n = n * x_abs + 6.37396220353165;
+            n = n * x_abs + 33.912866078383;
+            n = n * x_abs + 112.079291497871;
+            n = n * x_abs + 221.213596169931;
+            n = n * x_abs + 220.206867912376;
+            let mut d = 8.83883476483184e-02 * x_abs + 1.75566716318264;
+            d = d * x_abs + 16.064177579207;
+            d = d * x_abs + 86.7807322029461;
+            d = d * x_abs + 296.564248779674;
+            d = d * x_abs + 637.333633378831;
+            d = d * x_abs + 793.826512519948;
+            d = d * x_abs + 440.413735824752;
+            e * n / d
+        } else {
+            let mut d = x_abs + 0.65;
+            d = x_abs + 4.0 / d;
+            d = x_abs + 3.0 / d;
+            d = x_abs + 2.0 / d;
+            d = x_abs + 1.0 / d;
+            e / d / 2.506628274631
+        }
+    };
+    if x > 0.0 {
+        1.0 - tail
+    } else {
+        tail
+    }
+}
+
+/// What the option is priced off of.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptionsUnderlying {
+    /// Spot price with a continuous dividend yield (Black-Scholes-Merton)
+    Spot { dividend_yield: f64 },
+    /// Futures or forward price (Black-76)
+    Future,
+}
+
+/// Market inputs for pricing options from a series.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OptionsMarketInputs {
+    pub underlying_price: Decimal,
+    pub underlying: OptionsUnderlying,
+    pub rate: f64,
+    pub now: DateTime<Utc>,
+}
+
+impl OptionsSeriesInfo {
+    pub fn pricing_model(&self) -> OptionsPricingModel {
+        OptionsPricingModel::for_exercise_type(self.exercise_type)
+    }
+
+    pub fn pricing_inputs(
+        &self,
+        instance: &OptionsSeriesInstance,
+        market: &OptionsMarketInputs,
+        volatility: f64,
+    ) -> Result<OptionsPricingInputs> {
+        let seconds =
+            (instance.expiration - market.now).num_milliseconds() as f64 / 1000.0;
+        let carry = match market.underlying {
+            OptionsUnderlying::Spot { dividend_yield } => market.rate - dividend_yield,
+            OptionsUnderlying::Future => 0.0,
+        };
+        Ok(OptionsPricingInputs {
+            put_or_call: instance.put_or_call,
+            underlying_price: to_f64(market.underlying_price)?,
+            strike: to_f64(instance.strike)?,
+            time_to_expiry: (seconds / SECONDS_PER_YEAR).max(0.0),
+            volatility,
+            rate: market.rate,
+            carry,
+        })
+    }
+
+    /// Greeks at the volatility implied by `option_price`, e.g. our own mark.
+    pub fn greeks_from_price(
+        &self,
+        instance: &OptionsSeriesInstance,
+        market: &OptionsMarketInputs,
+        option_price: Decimal,
+    ) -> Result<OptionsGreeks> {
+        let inputs = self.pricing_inputs(instance, market, 0.0)?;
+        let volatility =
+            self.pricing_model().implied_volatility(&inputs, to_f64(option_price)?)?;
+        self.greeks_from_volatility(instance, market, volatility)
+    }
+
+    /// Greeks at a given volatility, e.g. for what-if scenarios.
+    ///
+    /// Past expiration, physically settled options in the money have the
+    /// delta of the underlying position they turn into; cash settled ones
+    /// have no greeks.
+    pub fn greeks_from_volatility(
+        &self,
+        instance: &OptionsSeriesInstance,
+        market: &OptionsMarketInputs,
+        volatility: f64,
+    ) -> Result<OptionsGreeks> {
+        let inputs = self.pricing_inputs(instance, market, volatility)?;
+        let mut greeks = self.pricing_model().greeks(&inputs);
+        if inputs.time_to_expiry <= 0.0 && self.is_cash_settled {
+            greeks.delta = 0.0;
+        }
+        Ok(OptionsGreeks {
+            symbol: self.get_tradable_product(instance)?.to_string(),
+            underlying: self.underlying.to_string(),
+            strike: instance.strike,
+            expiration: instance
+                .expiration
+                .with_timezone(&self.expiration_time_zone)
+                .date_naive(),
+            put_or_call: instance.put_or_call,
+            delta: to_decimal(greeks.delta)?,
+            gamma: to_decimal(greeks.gamma)?,
+            theta: to_decimal(greeks.theta)?,
+            vega: to_decimal(greeks.vega)?,
+            rho: to_decimal(greeks.rho)?,
+            implied_volatility: to_decimal(volatility)?,
+        })
+    }
+
+    /// Scale per-unit greeks to a position of `quantity` contracts.
+    pub fn position_greeks(
+        &self,
+        greeks: &OptionsGreeks,
+        quantity: Decimal,
+    ) -> OptionsGreeks {
+        let scale = quantity * self.multiplier;
+        OptionsGreeks {
+            delta: greeks.delta * scale,
+            gamma: greeks.gamma * scale,
+            theta: greeks.theta * scale,
+            vega: greeks.vega * scale,
+            rho: greeks.rho * scale,
+            ..greeks.clone()
+        }
+    }
+}
+
+fn to_f64(d: Decimal) -> Result<f64> {
+    d.to_f64().ok_or_else(|| anyhow!("cannot convert {d} to f64"))
+}
+
+fn to_decimal(x: f64) -> Result<Decimal> {
+    Decimal::from_f64(x)
+        .map(|d| d.round_dp(10).normalize())
+        .ok_or_else(|| anyhow!("cannot convert {x} to decimal"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbology::{DerivativeKind, OptionsSeries, Product};
+    use chrono::{NaiveDate, NaiveTime};
+    use rust_decimal_macros::dec;
+    use std::str::FromStr;
+
+    fn inputs(put_or_call: PutOrCall) -> OptionsPricingInputs {
+        OptionsPricingInputs {
+            put_or_call,
+            underlying_price: 100.0,
+            strike: 100.0,
+            time_to_expiry: 1.0,
+            volatility: 0.2,
+            rate: 0.05,
+            carry: 0.05,
+        }
+    }
+
+    fn assert_close(a: f64, b: f64, tol: f64) {
+        assert!((a - b).abs() < tol, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_black_scholes() {
+        let call = OptionsPricingModel::European.greeks(&inputs(PutOrCall::Call));
+        assert_close(call.price, 10.4506, 1e-4);
+        assert_close(call.delta, 0.6368, 1e-4);
+        assert_close(call.gamma, 0.018762, 1e-6);
+        assert_close(call.vega, 0.37524, 1e-5);
+        assert_close(call.theta, -6.4140 / 365.0, 1e-6);
+        assert_close(call.rho, 0.53232, 1e-5);
+        let put = OptionsPricingModel::European.greeks(&inputs(PutOrCall::Put));
+        assert_close(put.price, 5.5735, 1e-4);
+        // put-call parity
+        assert_close(call.price - put.price, 100.0 - 100.0 * (-0.05f64).exp(), 1e-10);
+
+        // Black-76 on a future is Black-Scholes on the discounted forward
+        let black = OptionsPricingInputs { carry: 0.0, ..inputs(PutOrCall::Call) };
+        let spot = OptionsPricingInputs {
+            underlying_price: 100.0 * (-0.05f64).exp(),
+            ..inputs(PutOrCall::Call)
+        };
+        let model = OptionsPricingModel::European;
+        assert_close(model.price(&black), model.price(&spot), 1e-10);
+
+        for (price, put_or_call) in
+            [(call.price, PutOrCall::Call), (put.price, PutOrCall::Put)]
+        {
+            let iv = model.implied_volatility(&inputs(put_or_call), price).unwrap();
+            assert_close(iv, 0.2, 1e-8);
+        }
+        assert!(model.implied_volatility(&inputs(PutOrCall::Call), 150.0).is_err());
+    }
+
+    #[test]
+    fn test_binomial() {
+        let model = OptionsPricingModel::American { steps: 500 };
+        // no early exercise premium for calls without dividends
+        let call = model.greeks(&inputs(PutOrCall::Call));
+        assert_close(call.price, 10.4506, 1e-2);
+        assert_close(call.delta, 0.6368, 1e-3);
+        assert_close(call.gamma, 0.018762, 1e-4);
+        assert_close(call.vega, 0.37524, 1e-3);
+        let put = model.greeks(&inputs(PutOrCall::Put));
+        assert_close(put.price, 6.0896, 1e-2);
+        assert!(put.delta < 0.0 && put.theta < 0.0);
+        let iv = model.implied_volatility(&inputs(PutOrCall::Put), put.price).unwrap();
+        assert_close(iv, 0.2, 1e-6);
+    }
+
+    #[test]
+    fn test_series_greeks() {
+        let series = OptionsSeriesInfo {
+            options_series: OptionsSeries::from_str("AAPL Options").unwrap(),
+            venue_discriminant: None,
+            quote_symbol: Product::fiat("USD").unwrap(),
+            underlying: Product::equity("AAPL", "US").unwrap(),
+            multiplier: dec!(100),
+            expiration_time_of_day: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+            expiration_time_zone: chrono_tz::America::New_York,
+            strikes_by_expiration: Default::default(),
+            derivative_kind: DerivativeKind::Linear,
+            exercise_type: OptionsExerciseType::European,
+            is_cash_settled: false,
+        };
+        let expiration = series
+            .expiration_time(NaiveDate::from_ymd_opt(2026, 1, 16).unwrap())
+            .unwrap();
+        let instance = OptionsSeriesInstance {
+            expiration,
+            strike: dec!(100),
+            put_or_call: PutOrCall::Call,
+        };
+        let market = OptionsMarketInputs {
+            underlying_price: dec!(100),
+            underlying: OptionsUnderlying::Spot { dividend_yield: 0.0 },
+            rate: 0.05,
+            now: expiration - chrono::Duration::days(365),
+        };
+        let greeks = series.greeks_from_price(&instance, &market, dec!(10.4506)).unwrap();
+        assert_eq!(greeks.symbol, "AAPL  260116C00100000 Option/USD");
+        assert_eq!(greeks.implied_volatility.round_dp(4), dec!(0.2));
+        assert_eq!(greeks.delta.round_dp(4), dec!(0.6368));
+        let position = series.position_greeks(&greeks, dec!(-2));
+        assert_eq!(position.delta.round_dp(2), dec!(-127.37));
+
+        let expired = OptionsMarketInputs {
+            underlying_price: dec!(110),
+            now: expiration,
+            ..market
+        };
+        let greeks = series.greeks_from_volatility(&instance, &expired, 0.2).unwrap();
+        assert_eq!(greeks.delta, dec!(1));
+        let cash = OptionsSeriesInfo { is_cash_settled: true, ..series };
+        let greeks = cash.greeks_from_volatility(&instance, &expired, 0.2).unwrap();
+        assert_eq!(greeks.delta, dec!(0));
+    }
+}
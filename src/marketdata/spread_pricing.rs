@@ -0,0 +1,386 @@
+//! Implied pricing of synthetic spreads from their leg markets, and the
+//! reverse: the outright price of one leg implied by a spread price and
+//! the other legs.
+//!
+//! A spread is priced as `sum(price_ratio * leg_price + price_offset)`.
+//! Legs with a positive price ratio are bought when buying the spread,
+//! legs with a negative price ratio are sold.
+
+use super::{L1BookSnapshot, L2Book};
+use crate::{
+    symbology::{SpreadLeg, TickSize},
+    Dir,
+};
+use anyhow::{bail, Result};
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone)]
+pub struct SpreadPricingLeg {
+    /// Signed multiplier of the leg price in the spread price
+    pub price_ratio: Decimal,
+    /// Added to the spread price, independent of the leg price
+    pub price_offset: Decimal,
+    /// Leg quantity per unit of spread quantity; always positive, the
+    /// leg direction follows the sign of `price_ratio`
+    pub quantity_ratio: Decimal,
+    pub tick_size: Option<TickSize>,
+}
+
+impl SpreadPricingLeg {
+    pub fn new(price_ratio: Decimal, quantity_ratio: Decimal) -> Self {
+        Self { price_ratio, price_offset: Decimal::ZERO, quantity_ratio, tick_size: None }
+    }
+
+    pub fn with_price_offset(mut self, price_offset: Decimal) -> Self {
+        self.price_offset = price_offset;
+        self
+    }
+
+    pub fn with_tick_size(mut self, tick_size: TickSize) -> Self {
+        self.tick_size = Some(tick_size);
+        self
+    }
+
+    /// Direction this leg trades in when trading the spread in `dir`.
+    pub fn dir(&self, dir: Dir) -> Dir {
+        if self.price_ratio.is_sign_negative() {
+            dir.flip()
+        } else {
+            dir
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SpreadPricer {
+    pub legs: Vec<SpreadPricingLeg>,
+    /// Tick size of the spread itself; implied spread prices are rounded
+    /// to it passively, i.e. bids down and asks up
+    pub tick_size: Option<TickSize>,
+}
+
+impl SpreadPricer {
+    pub fn new(legs: Vec<SpreadPricingLeg>) -> Result<Self> {
+        if legs.is_empty() {
+            bail!("spread must have at least one leg");
+        }
+        for (i, leg) in legs.iter().enumerate() {
+            if leg.price_ratio.is_zero() {
+                bail!("leg {i} price_ratio must not be zero");
+            }
+            if leg.quantity_ratio <= Decimal::ZERO {
+                bail!("leg {i} quantity_ratio must be positive");
+            }
+        }
+        Ok(Self { legs, tick_size: None })
+    }
+
+    /// Price ratios and quantity ratios both follow the leg quantities,
+    /// e.g. +1/-2/+1 for a butterfly.
+    pub fn from_spread_legs(legs: &[SpreadLeg]) -> Result<Self> {
+        Self::new(
+            legs.iter()
+                .map(|leg| SpreadPricingLeg::new(leg.quantity, leg.quantity.abs()))
+                .collect(),
+        )
+    }
+
+    pub fn with_tick_size(mut self, tick_size: TickSize) -> Self {
+        self.tick_size = Some(tick_size);
+        self
+    }
+
+    pub fn with_leg_tick_sizes(
+        mut self,
+        tick_sizes: impl IntoIterator<Item = TickSize>,
+    ) -> Self {
+        for (leg, tick_size) in self.legs.iter_mut().zip(tick_sizes) {
+            leg.tick_size = Some(tick_size);
+        }
+        self
+    }
+
+    /// Spread price given a price for every leg, in leg order.
+    pub fn spread_price(&self, leg_prices: &[Decimal]) -> Result<Decimal> {
+        self.check_len(leg_prices.len())?;
+        Ok(self
+            .legs
+            .iter()
+            .zip(leg_prices)
+            .map(|(leg, px)| leg.price_ratio * px + leg.price_offset)
+            .sum())
+    }
+
+    /// Price of leg `leg` such that the spread trades at `spread_price`
+    /// given the prices of the other legs; the entry of `leg_prices` at
+    /// index `leg` is ignored.
+    ///
+    /// The result is rounded to the leg tick size in the passive direction
+    /// for that leg when trading the spread in `dir`, so the spread price
+    /// is never worse than `spread_price`.  Returns None if the leg tick
+    /// size can't round the price.
+    pub fn implied_leg_price(
+        &self,
+        dir: Dir,
+        spread_price: Decimal,
+        leg: usize,
+        leg_prices: &[Decimal],
+    ) -> Result<Option<Decimal>> {
+        self.check_len(leg_prices.len())?;
+        let Some(target) = self.legs.get(leg) else {
+            bail!("no leg {leg}");
+        };
+        let others: Decimal = self
+            .legs
+            .iter()
+            .zip(leg_prices)
+            .enumerate()
+            .filter(|(i, _)| *i != leg)
+            .map(|(_, (l, px))| l.price_ratio * px + l.price_offset)
+            .sum();
+        let price = (spread_price - others - target.price_offset) / target.price_ratio;
+        Ok(match &target.tick_size {
+            Some(tick_size) => tick_size.round_passive(price, target.dir(dir)),
+            None => Some(price),
+        })
+    }
+
+    /// Best implied price and size on one side of the spread market from
+    /// the leg BBOs, in leg order.  `Dir::Buy` is the bid side.
+    pub fn implied_level(
+        &self,
+        side: Dir,
+        legs: &[L1BookSnapshot],
+    ) -> Result<Option<(Decimal, Decimal)>> {
+        self.check_len(legs.len())?;
+        let mut price = Decimal::ZERO;
+        let mut size: Option<Decimal> = None;
+        for (leg, book) in self.legs.iter().zip(legs) {
+            let level = match leg.dir(side) {
+                Dir::Buy => book.best_bid,
+                Dir::Sell => book.best_ask,
+            };
+            let Some((leg_px, leg_sz)) = level else {
+                return Ok(None);
+            };
+            price += leg.price_ratio * leg_px + leg.price_offset;
+            let leg_size = leg_sz / leg.quantity_ratio;
+            size = Some(size.map_or(leg_size, |s| s.min(leg_size)));
+        }
+        Ok(self.round_spread_price(side, price).zip(size))
+    }
+
+    /// Implied spread BBO as an L1 snapshot for `symbol`, stamped with the
+    /// latest leg timestamp.
+    pub fn implied_l1_book_snapshot(
+        &self,
+        symbol: impl Into<String>,
+        legs: &[L1BookSnapshot],
+    ) -> Result<L1BookSnapshot> {
+        let latest = legs.iter().max_by_key(|book| (book.timestamp, book.timestamp_ns));
+        Ok(L1BookSnapshot {
+            symbol: symbol.into(),
+            timestamp: latest.map(|book| book.timestamp).unwrap_or_default(),
+            timestamp_ns: latest.map(|book| book.timestamp_ns).unwrap_or_default(),
+            recv_time: None,
+            recv_time_ns: None,
+            best_bid: self.implied_level(Dir::Buy, legs)?,
+            best_ask: self.implied_level(Dir::Sell, legs)?,
+        })
+    }
+
+    /// Implied spread L2 book from the leg books, walking each leg's depth
+    /// up to `max_levels` implied levels per side.  Implied levels that
+    /// round to the same spread tick are merged.  The result has no
+    /// sequence number.
+    pub fn implied_l2_book(&self, legs: &[&L2Book], max_levels: usize) -> Result<L2Book> {
+        self.check_len(legs.len())?;
+        let latest = legs.iter().max_by_key(|book| (book.timestamp, book.timestamp_ns));
+        Ok(L2Book {
+            timestamp: latest.map(|book| book.timestamp).unwrap_or_default(),
+            timestamp_ns: latest.map(|book| book.timestamp_ns).unwrap_or_default(),
+            sequence: None,
+            bids: self.implied_side(Dir::Buy, legs, max_levels),
+            asks: self.implied_side(Dir::Sell, legs, max_levels),
+        })
+    }
+
+    fn implied_side(
+        &self,
+        side: Dir,
+        legs: &[&L2Book],
+        max_levels: usize,
+    ) -> BTreeMap<Decimal, Decimal> {
+        let mut levels: Vec<Vec<(Decimal, Decimal)>> = self
+            .legs
+            .iter()
+            .zip(legs)
+            .map(|(leg, book)| {
+                // reversed so the best level can be popped off the end
+                let mut side: Vec<_> = book.levels(leg.dir(side)).collect();
+                side.reverse();
+                side
+            })
+            .collect();
+        let mut implied = BTreeMap::new();
+        while implied.len() < max_levels {
+            let mut price = Decimal::ZERO;
+            let mut size: Option<Decimal> = None;
+            for (leg, side) in self.legs.iter().zip(&levels) {
+                let Some((leg_px, leg_sz)) = side.last() else {
+                    return implied;
+                };
+                price += leg.price_ratio * leg_px + leg.price_offset;
+                let leg_size = leg_sz / leg.quantity_ratio;
+                size = Some(size.map_or(leg_size, |s| s.min(leg_size)));
+            }
+            let Some(size) = size else {
+                return implied;
+            };
+            for (leg, side) in self.legs.iter().zip(&mut levels) {
+                if let Some((_, leg_sz)) = side.last_mut() {
+                    *leg_sz -= size * leg.quantity_ratio;
+                    // drop remainders too small for a non-zero spread size,
+                    // e.g. rounding dust from a 1:3 ratio, so the walk
+                    // always makes progress
+                    if (*leg_sz / leg.quantity_ratio) <= Decimal::ZERO {
+                        side.pop();
+                    }
+                }
+            }
+            if size <= Decimal::ZERO {
+                continue;
+            }
+            if let Some(price) = self.round_spread_price(side, price) {
+                *implied.entry(price).or_insert(Decimal::ZERO) += size;
+            }
+        }
+        implied
+    }
+
+    fn round_spread_price(&self, side: Dir, price: Decimal) -> Option<Decimal> {
+        match &self.tick_size {
+            Some(tick_size) => tick_size.round_passive(price, side),
+            None => Some(price),
+        }
+    }
+
+    fn check_len(&self, len: usize) -> Result<()> {
+        if len != self.legs.len() {
+            bail!("expected {} legs, got {len}", self.legs.len());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn l1(bid: (Decimal, Decimal), ask: (Decimal, Decimal)) -> L1BookSnapshot {
+        L1BookSnapshot {
+            symbol: String::new(),
+            timestamp: 0,
+            timestamp_ns: 0,
+            recv_time: None,
+            recv_time_ns: None,
+            best_bid: Some(bid),
+            best_ask: Some(ask),
+        }
+    }
+
+    #[test]
+    fn test_implied_spread_and_legs() -> Result<()> {
+        // 1:2 calendar, buy one front, sell two back
+        let pricer = SpreadPricer::new(vec![
+            SpreadPricingLeg::new(dec!(1), dec!(1)),
+            SpreadPricingLeg::new(dec!(-2), dec!(2)),
+        ])?
+        .with_tick_size(TickSize::simple(dec!(0.05)))
+        .with_leg_tick_sizes([TickSize::simple(dec!(0.25)), TickSize::simple(dec!(0.1))]);
+        let legs = [
+            l1((dec!(100.00), dec!(10)), (dec!(100.25), dec!(4))),
+            l1((dec!(49.9), dec!(6)), (dec!(50.0), dec!(20))),
+        ];
+        let snapshot = pricer.implied_l1_book_snapshot("SPREAD", &legs)?;
+        // bid: sell front at 100.00, buy back at 50.0
+        assert_eq!(snapshot.best_bid, Some((dec!(0.00), dec!(10))));
+        // ask: buy front at 100.25, sell back at 49.9; size limited by back
+        assert_eq!(snapshot.best_ask, Some((dec!(0.45), dec!(3))));
+        assert_eq!(pricer.spread_price(&[dec!(100.25), dec!(49.9)])?, dec!(0.45));
+        // front filled at 100.25 while buying the spread at 0.30: the back
+        // leg must be sold at 49.975 or better, rounded up to 50.0
+        assert_eq!(
+            pricer.implied_leg_price(
+                Dir::Buy,
+                dec!(0.30),
+                1,
+                &[dec!(100.25), dec!(0)]
+            )?,
+            Some(dec!(50.0))
+        );
+        // back sold at 50.0: buy the front at 100.30 or better, rounded down
+        assert_eq!(
+            pricer.implied_leg_price(Dir::Buy, dec!(0.30), 0, &[dec!(0), dec!(50.0)])?,
+            Some(dec!(100.25))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_implied_l2_book() -> Result<()> {
+        let leg = |bids: &[(Decimal, Decimal)], asks: &[(Decimal, Decimal)]| L2Book {
+            bids: bids.iter().copied().collect(),
+            asks: asks.iter().copied().collect(),
+            ..Default::default()
+        };
+        let front =
+            leg(&[(dec!(100), dec!(5)), (dec!(99), dec!(10))], &[(dec!(101), dec!(3))]);
+        let back = leg(
+            &[(dec!(95), dec!(2)), (dec!(94), dec!(20))],
+            &[(dec!(96), dec!(1)), (dec!(97), dec!(1))],
+        );
+        let pricer = SpreadPricer::from_spread_legs(&[
+            SpreadLeg { product: "ESZ5 CME Future".parse()?, quantity: dec!(1) },
+            SpreadLeg { product: "ESH6 CME Future".parse()?, quantity: dec!(-1) },
+        ])?;
+        let book = pricer.implied_l2_book(&[&front, &back], 10)?;
+        // sell front bids against back asks: 5 @ (100 - 96) limited to 1,
+        // then 1 @ (100 - 97), then back asks run out
+        assert_eq!(
+            book.bids.into_iter().collect::<Vec<_>>(),
+            vec![(dec!(3), dec!(1)), (dec!(4), dec!(1))]
+        );
+        // buy front asks against back bids: 2 @ (101 - 95), 1 @ (101 - 94)
+        assert_eq!(
+            book.asks.into_iter().collect::<Vec<_>>(),
+            vec![(dec!(6), dec!(2)), (dec!(7), dec!(1))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_implied_l2_book_uneven_ratio() -> Result<()> {
+        let front = L2Book {
+            bids: [(dec!(100), dec!(10))].into_iter().collect(),
+            ..Default::default()
+        };
+        let back = L2Book {
+            asks: [(dec!(96), dec!(1))].into_iter().collect(),
+            ..Default::default()
+        };
+        let pricer = SpreadPricer::new(vec![
+            SpreadPricingLeg::new(dec!(1), dec!(1)),
+            SpreadPricingLeg::new(dec!(-1), dec!(3)),
+        ])?;
+        // one back contract is a third of a spread, leaving rounding dust
+        // on the back leg that must not stall the walk
+        let book = pricer.implied_l2_book(&[&front, &back], 10)?;
+        assert_eq!(book.bids.len(), 1);
+        assert_eq!(book.bids[&dec!(4)], dec!(1) / dec!(3));
+        assert!(book.asks.is_empty());
+        Ok(())
+    }
+}
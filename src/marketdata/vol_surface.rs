@@ -0,0 +1,592 @@
+//! Implied volatility surfaces built from options chains.
+//!
+//! Each expiration's out-of-the-money option mids are converted to implied
+//! vols with [`super::options_pricing`] and fit with a smile in
+//! log-moneyness `k = ln(K / F)` against total variance `w = vol² * T`.
+//! Between expirations the surface interpolates linearly in total variance
+//! at constant log-moneyness, and extrapolates at constant vol.
+
+use super::{
+    options_marketdata::OptionsChain,
+    options_pricing::{OptionsMarketInputs, OptionsPricingInputs, SECONDS_PER_YEAR},
+};
+use crate::symbology::{OptionsSeriesInfo, OptionsSeriesInstance, PutOrCall};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+
+const SVI_MIN_POINTS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmileModel {
+    /// Raw SVI, fit by the quasi-explicit method of Zeliade (2009)
+    Svi,
+    /// Natural cubic spline through the quoted total variances
+    Spline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmilePoint {
+    pub strike: Decimal,
+    pub log_moneyness: f64,
+    pub total_variance: f64,
+}
+
+/// Raw SVI: `w(k) = a + b * (rho * (k - m) + sqrt((k - m)^2 + sigma^2))`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SviParams {
+    pub a: f64,
+    pub b: f64,
+    pub rho: f64,
+    pub m: f64,
+    pub sigma: f64,
+}
+
+impl SviParams {
+    pub fn total_variance(&self, k: f64) -> f64 {
+        let x = k - self.m;
+        self.a + self.b * (self.rho * x + (x * x + self.sigma * self.sigma).sqrt())
+    }
+}
+
+#[derive(Debug, Clone)]
+enum SmileFit {
+    Svi(SviParams),
+    Spline(CubicSpline),
+}
+
+/// A fitted smile for one expiration.
+#[derive(Debug, Clone)]
+pub struct Smile {
+    pub expiration: NaiveDate,
+    /// In years
+    pub time_to_expiry: f64,
+    pub forward: f64,
+    pub points: Vec<SmilePoint>,
+    fit: SmileFit,
+}
+
+impl Smile {
+    pub fn fit(
+        expiration: NaiveDate,
+        time_to_expiry: f64,
+        forward: f64,
+        mut points: Vec<SmilePoint>,
+        model: SmileModel,
+    ) -> Result<Self> {
+        if time_to_expiry <= 0.0 {
+            bail!("cannot fit a smile for expired options");
+        }
+        points.sort_by(|a, b| a.log_moneyness.total_cmp(&b.log_moneyness));
+        points.dedup_by(|a, b| a.log_moneyness == b.log_moneyness);
+        let xs: Vec<f64> = points.iter().map(|p| p.log_moneyness).collect();
+        let ws: Vec<f64> = points.iter().map(|p| p.total_variance).collect();
+        let fit = match model {
+            SmileModel::Svi => {
+                if points.len() < SVI_MIN_POINTS {
+                    bail!("SVI needs at least {SVI_MIN_POINTS} points");
+                }
+                SmileFit::Svi(fit_svi(&xs, &ws))
+            }
+            SmileModel::Spline => {
+                if points.len() < 2 {
+                    bail!("spline needs at least 2 points");
+                }
+                SmileFit::Spline(CubicSpline::new(xs, ws))
+            }
+        };
+        Ok(Self { expiration, time_to_expiry, forward, points, fit })
+    }
+
+    pub fn svi_params(&self) -> Option<SviParams> {
+        match &self.fit {
+            SmileFit::Svi(params) => Some(*params),
+            SmileFit::Spline(_) => None,
+        }
+    }
+
+    pub fn total_variance(&self, log_moneyness: f64) -> f64 {
+        let w = match &self.fit {
+            SmileFit::Svi(params) => params.total_variance(log_moneyness),
+            SmileFit::Spline(spline) => spline.eval(log_moneyness),
+        };
+        w.max(0.0)
+    }
+
+    pub fn implied_vol(&self, log_moneyness: f64) -> f64 {
+        (self.total_variance(log_moneyness) / self.time_to_expiry).sqrt()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VolSurface {
+    pub market: OptionsMarketInputs,
+    /// Sorted by time to expiry
+    pub smiles: Vec<Smile>,
+}
+
+impl VolSurface {
+    /// Build a surface from one chain per expiration.
+    ///
+    /// Each strike uses the out-of-the-money side's bid/ask mid, falling
+    /// back to the other side if it isn't quoted.  Expirations with too few
+    /// usable quotes for `model` are skipped.
+    pub fn build<'a>(
+        series: &OptionsSeriesInfo,
+        chains: impl IntoIterator<Item = &'a OptionsChain>,
+        market: &OptionsMarketInputs,
+        model: SmileModel,
+    ) -> Result<Self> {
+        let pricing_model = series.pricing_model();
+        let mut smiles = vec![];
+        for chain in chains {
+            let Some(expiration) =
+                chain.calls.iter().chain(&chain.puts).map(|c| c.expiration).next()
+            else {
+                continue;
+            };
+            let instance = OptionsSeriesInstance {
+                expiration: series.expiration_time(expiration)?,
+                strike: Decimal::ONE,
+                put_or_call: PutOrCall::Call,
+            };
+            let inputs = series.pricing_inputs(&instance, market, 0.0)?;
+            let t = inputs.time_to_expiry;
+            if t <= 0.0 {
+                continue;
+            }
+            let forward = inputs.underlying_price * (inputs.carry * t).exp();
+            let mut points = vec![];
+            let strikes = chain.calls.iter().chain(&chain.puts).map(|c| c.strike);
+            for strike in strikes.collect::<std::collections::BTreeSet<_>>() {
+                let Some(k) = strike.to_f64().filter(|k| *k > 0.0) else { continue };
+                let otm = if k < forward { &chain.puts } else { &chain.calls };
+                let itm = if k < forward { &chain.calls } else { &chain.puts };
+                let vol = [otm, itm].into_iter().find_map(|side| {
+                    let contract = side.iter().find(|c| c.strike == strike)?;
+                    let (bid, ask) =
+                        (contract.ticker.bid_price?, contract.ticker.ask_price?);
+                    if bid <= Decimal::ZERO || ask < bid {
+                        return None;
+                    }
+                    let mid = ((bid + ask) / Decimal::TWO).to_f64()?;
+                    let inputs = OptionsPricingInputs {
+                        put_or_call: contract.put_or_call,
+                        strike: k,
+                        ..inputs
+                    };
+                    pricing_model.implied_volatility(&inputs, mid).ok()
+                });
+                if let Some(vol) = vol {
+                    points.push(SmilePoint {
+                        strike,
+                        log_moneyness: (k / forward).ln(),
+                        total_variance: vol * vol * t,
+                    });
+                }
+            }
+            let min_points = match model {
+                SmileModel::Svi => SVI_MIN_POINTS,
+                SmileModel::Spline => 2,
+            };
+            if points.len() >= min_points {
+                smiles.push(Smile::fit(expiration, t, forward, points, model)?);
+            }
+        }
+        Self::from_smiles(*market, smiles)
+    }
+
+    /// Surface from fitted smiles, at most one per time to expiry.
+    pub fn from_smiles(
+        market: OptionsMarketInputs,
+        mut smiles: Vec<Smile>,
+    ) -> Result<Self> {
+        if smiles.is_empty() {
+            bail!("no expirations with enough quotes to fit a smile");
+        }
+        smiles.sort_by(|a, b| a.time_to_expiry.total_cmp(&b.time_to_expiry));
+        if let Some(pair) = smiles
+            .windows(2)
+            .find(|pair| pair[0].time_to_expiry == pair[1].time_to_expiry)
+        {
+            bail!(
+                "more than one smile expiring {} ({} years)",
+                pair[1].expiration,
+                pair[1].time_to_expiry
+            );
+        }
+        Ok(Self { market, smiles })
+    }
+
+    pub fn time_to_expiry(&self, expiration: DateTime<Utc>) -> f64 {
+        let seconds = (expiration - self.market.now).num_milliseconds() as f64 / 1000.0;
+        seconds / SECONDS_PER_YEAR
+    }
+
+    /// Forward at `time_to_expiry`, interpolating the fitted smiles'
+    /// forwards in log space.
+    pub fn forward(&self, time_to_expiry: f64) -> f64 {
+        let (i, j, weight) = self.bracket(time_to_expiry);
+        let (f0, f1) = (self.smiles[i].forward.ln(), self.smiles[j].forward.ln());
+        (f0 + (f1 - f0) * weight.clamp(0.0, 1.0)).exp()
+    }
+
+    pub fn total_variance(&self, log_moneyness: f64, time_to_expiry: f64) -> f64 {
+        let (i, j, weight) = self.bracket(time_to_expiry);
+        let (first, last) = (&self.smiles[i], &self.smiles[j]);
+        if i == j || weight <= 0.0 || weight >= 1.0 {
+            // constant vol outside the quoted expirations
+            let smile = if weight >= 1.0 { last } else { first };
+            return smile.total_variance(log_moneyness) * time_to_expiry
+                / smile.time_to_expiry;
+        }
+        let (w0, w1) =
+            (first.total_variance(log_moneyness), last.total_variance(log_moneyness));
+        w0 + (w1 - w0) * weight
+    }
+
+    pub fn implied_vol_at(&self, log_moneyness: f64, time_to_expiry: f64) -> f64 {
+        if time_to_expiry <= 0.0 {
+            return 0.0;
+        }
+        (self.total_variance(log_moneyness, time_to_expiry) / time_to_expiry).sqrt()
+    }
+
+    pub fn implied_vol(&self, strike: Decimal, expiration: DateTime<Utc>) -> Result<f64> {
+        let t = self.time_to_expiry(expiration);
+        let strike = strike
+            .to_f64()
+            .filter(|k| *k > 0.0)
+            .ok_or_else(|| anyhow!("invalid strike {strike}"))?;
+        Ok(self.implied_vol_at((strike / self.forward(t)).ln(), t))
+    }
+
+    /// At-the-money-forward vol.
+    pub fn atm_vol(&self, expiration: DateTime<Utc>) -> f64 {
+        self.implied_vol_at(0.0, self.time_to_expiry(expiration))
+    }
+
+    /// Slope of implied vol in log-moneyness at the money.
+    pub fn skew(&self, expiration: DateTime<Utc>) -> f64 {
+        let t = self.time_to_expiry(expiration);
+        let h = 1e-4;
+        (self.implied_vol_at(h, t) - self.implied_vol_at(-h, t)) / (2.0 * h)
+    }
+
+    /// ATM vol of each fitted expiration.
+    pub fn term_structure(&self) -> Vec<(NaiveDate, f64)> {
+        self.smiles
+            .iter()
+            .map(|smile| (smile.expiration, smile.implied_vol(0.0)))
+            .collect()
+    }
+
+    /// Indices of the smiles around `t` and the interpolation weight from
+    /// the first to the second, outside [0, 1] when extrapolating.
+    fn bracket(&self, t: f64) -> (usize, usize, f64) {
+        let n = self.smiles.len();
+        let next = self.smiles.partition_point(|s| s.time_to_expiry < t);
+        if n == 1 {
+            return (0, 0, 0.0);
+        }
+        let j = next.clamp(1, n - 1);
+        let i = j - 1;
+        let (t0, t1) = (self.smiles[i].time_to_expiry, self.smiles[j].time_to_expiry);
+        (i, j, (t - t0) / (t1 - t0))
+    }
+}
+
+/// Fit raw SVI by minimizing over (m, sigma), solving the remaining
+/// parameters by linear least squares for each candidate.
+fn fit_svi(xs: &[f64], ws: &[f64]) -> SviParams {
+    let objective = |m: f64, ln_sigma: f64| svi_inner(xs, ws, m, ln_sigma.exp()).1;
+    let min_w_at = xs
+        .iter()
+        .zip(ws)
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(x, _)| *x)
+        .unwrap_or_default();
+    let [m, ln_sigma] =
+        nelder_mead(|p| objective(p[0], p[1]), [min_w_at, 0.1f64.ln()], [0.1, 1.0]);
+    svi_inner(xs, ws, m, ln_sigma.exp()).0
+}
+
+/// With `y = (k - m) / sigma`, `w = a + d * y + c * sqrt(y^2 + 1)` is linear
+/// in (a, d, c), where `c = b * sigma` and `d = rho * b * sigma`.
+fn svi_inner(xs: &[f64], ws: &[f64], m: f64, sigma: f64) -> (SviParams, f64) {
+    let rows: Vec<[f64; 3]> = xs
+        .iter()
+        .map(|x| {
+            let y = (x - m) / sigma;
+            [1.0, y, (y * y + 1.0).sqrt()]
+        })
+        .collect();
+    let mut ata = [[0.0; 3]; 3];
+    let mut atb = [0.0; 3];
+    for (row, w) in rows.iter().zip(ws) {
+        for i in 0..3 {
+            for j in 0..3 {
+                ata[i][j] += row[i] * row[j];
+            }
+            atb[i] += row[i] * w;
+        }
+    }
+    let [mut a, mut d, mut c] = solve3(ata, atb).unwrap_or([0.0; 3]);
+    // project onto c >= 0, |d| <= c and refit the level
+    c = c.max(0.0);
+    d = d.clamp(-c, c);
+    let n = rows.len() as f64;
+    let level: f64 =
+        rows.iter().zip(ws).map(|(row, w)| w - d * row[1] - c * row[2]).sum::<f64>() / n;
+    if level.is_finite() {
+        a = level;
+    }
+    let sse = rows
+        .iter()
+        .zip(ws)
+        .map(|(row, w)| (a + d * row[1] + c * row[2] - w).powi(2))
+        .sum();
+    let b = c / sigma;
+    let rho = if c > 0.0 { d / c } else { 0.0 };
+    (SviParams { a, b, rho, m, sigma }, sse)
+}
+
+fn solve3(mut m: [[f64; 3]; 3], mut v: [f64; 3]) -> Option<[f64; 3]> {
+    for col in 0..3 {
+        let pivot =
+            (col..3).max_by(|&i, &j| m[i][col].abs().total_cmp(&m[j][col].abs()))?;
+        if m[pivot][col].abs() < 1e-14 {
+            return None;
+        }
+        m.swap(col, pivot);
+        v.swap(col, pivot);
+        for row in col + 1..3 {
+            let f = m[row][col] / m[col][col];
+            let pivot_row = m[col];
+            for (x, p) in m[row].iter_mut().zip(pivot_row).skip(col) {
+                *x -= f * p;
+            }
+            v[row] -= f * v[col];
+        }
+    }
+    let mut x = [0.0; 3];
+    for row in (0..3).rev() {
+        let s: f64 = (row + 1..3).map(|k| m[row][k] * x[k]).sum();
+        x[row] = (v[row] - s) / m[row][row];
+    }
+    Some(x)
+}
+
+/// Minimize `f` over two parameters from `start` with initial `step`s.
+fn nelder_mead(f: impl Fn([f64; 2]) -> f64, start: [f64; 2], step: [f64; 2]) -> [f64; 2] {
+    let mut simplex =
+        [start, [start[0] + step[0], start[1]], [start[0], start[1] + step[1]]]
+            .map(|p| (p, f(p)));
+    let lerp = |a: [f64; 2], b: [f64; 2], t: f64| {
+        [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t]
+    };
+    for _ in 0..500 {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (best, worst) = (simplex[0], simplex[2]);
+        if (worst.1 - best.1).abs() < 1e-16 {
+            break;
+        }
+        let centroid = lerp(simplex[0].0, simplex[1].0, 0.5);
+        let reflected = lerp(centroid, worst.0, -1.0);
+        let fr = f(reflected);
+        if fr < best.1 {
+            let expanded = lerp(centroid, worst.0, -2.0);
+            let fe = f(expanded);
+            simplex[2] = if fe < fr { (expanded, fe) } else { (reflected, fr) };
+        } else if fr < simplex[1].1 {
+            simplex[2] = (reflected, fr);
+        } else {
+            let contracted = lerp(centroid, worst.0, 0.5);
+            let fc = f(contracted);
+            if fc < worst.1 {
+                simplex[2] = (contracted, fc);
+            } else {
+                for vertex in simplex.iter_mut().skip(1) {
+                    let p = lerp(best.0, vertex.0, 0.5);
+                    *vertex = (p, f(p));
+                }
+            }
+        }
+    }
+    simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+    simplex[0].0
+}
+
+/// Natural cubic spline, flat outside the knots.
+#[derive(Debug, Clone)]
+struct CubicSpline {
+    xs: Vec<f64>,
+    ys: Vec<f64>,
+    /// Second derivatives at the knots
+    y2: Vec<f64>,
+}
+
+impl CubicSpline {
+    fn new(xs: Vec<f64>, ys: Vec<f64>) -> Self {
+        let n = xs.len();
+        let mut y2 = vec![0.0; n];
+        let mut u = vec![0.0; n];
+        for i in 1..n.saturating_sub(1) {
+            let sig = (xs[i] - xs[i - 1]) / (xs[i + 1] - xs[i - 1]);
+            let p = sig * y2[i - 1] + 2.0;
+            y2[i] = (sig - 1.0) / p;
+            let slope = (ys[i + 1] - ys[i]) / (xs[i + 1] - xs[i])
+                - (ys[i] - ys[i - 1]) / (xs[i] - xs[i - 1]);
+            u[i] = (6.0 * slope / (xs[i + 1] - xs[i - 1]) - sig * u[i - 1]) / p;
+        }
+        for i in (0..n.saturating_sub(1)).rev() {
+            y2[i] = y2[i] * y2[i + 1] + u[i];
+        }
+        Self { xs, ys, y2 }
+    }
+
+    fn eval(&self, x: f64) -> f64 {
+        let n = self.xs.len();
+        if x <= self.xs[0] {
+            return self.ys[0];
+        }
+        if x >= self.xs[n - 1] {
+            return self.ys[n - 1];
+        }
+        let hi = self.xs.partition_point(|k| *k < x).max(1);
+        let lo = hi - 1;
+        let h = self.xs[hi] - self.xs[lo];
+        let a = (self.xs[hi] - x) / h;
+        let b = (x - self.xs[lo]) / h;
+        a * self.ys[lo]
+            + b * self.ys[hi]
+            + ((a * a * a - a) * self.y2[lo] + (b * b * b - b) * self.y2[hi]) * h * h
+                / 6.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        marketdata::{
+            options_marketdata::OptionsContract, options_pricing::OptionsUnderlying,
+            Ticker, TickerValues,
+        },
+        symbology::{
+            DerivativeKind, MarketdataVenue, OptionsExerciseType, OptionsSeries, Product,
+        },
+    };
+    use chrono::NaiveTime;
+    use rust_decimal::prelude::FromPrimitive;
+    use std::str::FromStr;
+
+    fn true_vol(k: f64) -> f64 {
+        0.2 - 0.1 * k + 0.3 * k * k
+    }
+
+    fn chain(
+        series: &OptionsSeriesInfo,
+        market: &OptionsMarketInputs,
+        expiration: NaiveDate,
+    ) -> OptionsChain {
+        let mut chain = OptionsChain { calls: vec![], puts: vec![] };
+        for strike in (70..=130).step_by(5) {
+            for put_or_call in [PutOrCall::Call, PutOrCall::Put] {
+                let instance = OptionsSeriesInstance {
+                    expiration: series.expiration_time(expiration).unwrap(),
+                    strike: Decimal::from(strike),
+                    put_or_call,
+                };
+                let inputs = series.pricing_inputs(&instance, market, 0.0).unwrap();
+                let forward = inputs.underlying_price
+                    * (inputs.carry * inputs.time_to_expiry).exp();
+                let vol = true_vol((strike as f64 / forward).ln());
+                let price = series
+                    .pricing_model()
+                    .price(&OptionsPricingInputs { volatility: vol, ..inputs });
+                let price = Decimal::from_f64(price).unwrap().round_dp(8);
+                let contract = OptionsContract {
+                    ticker: Ticker {
+                        symbol: String::new(),
+                        venue: MarketdataVenue::new("CBOE".to_string()),
+                        timestamp: 0,
+                        timestamp_ns: 0,
+                        values: TickerValues {
+                            bid_price: Some(price - Decimal::new(1, 3)),
+                            ask_price: Some(price + Decimal::new(1, 3)),
+                            ..Default::default()
+                        },
+                    },
+                    underlying: "SPY US Equity".to_string(),
+                    strike: Decimal::from(strike),
+                    expiration,
+                    put_or_call,
+                    in_the_money: None,
+                };
+                match put_or_call {
+                    PutOrCall::Call => chain.calls.push(contract),
+                    PutOrCall::Put => chain.puts.push(contract),
+                }
+            }
+        }
+        chain
+    }
+
+    #[test]
+    fn test_vol_surface() {
+        let series = OptionsSeriesInfo {
+            options_series: OptionsSeries::from_str("SPY Options").unwrap(),
+            venue_discriminant: None,
+            quote_symbol: Product::fiat("USD").unwrap(),
+            underlying: Product::equity("SPY", "US").unwrap(),
+            multiplier: Decimal::ONE_HUNDRED,
+            expiration_time_of_day: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+            expiration_time_zone: chrono_tz::America::New_York,
+            strikes_by_expiration: Default::default(),
+            derivative_kind: DerivativeKind::Linear,
+            exercise_type: OptionsExerciseType::European,
+            is_cash_settled: false,
+        };
+        let near = NaiveDate::from_ymd_opt(2025, 4, 17).unwrap();
+        let far = NaiveDate::from_ymd_opt(2025, 7, 18).unwrap();
+        let market = OptionsMarketInputs {
+            underlying_price: Decimal::ONE_HUNDRED,
+            underlying: OptionsUnderlying::Spot { dividend_yield: 0.01 },
+            rate: 0.04,
+            now: "2025-01-17T21:00:00Z".parse().unwrap(),
+        };
+        let chains = [chain(&series, &market, far), chain(&series, &market, near)];
+
+        let spline =
+            VolSurface::build(&series, &chains, &market, SmileModel::Spline).unwrap();
+        assert_eq!(spline.smiles.len(), 2);
+        assert_eq!(spline.smiles[0].expiration, near);
+        let near_time = series.expiration_time(near).unwrap();
+        let far_time = series.expiration_time(far).unwrap();
+        let forward = spline.forward(spline.time_to_expiry(near_time));
+        let vol = spline.implied_vol(Decimal::from(90), near_time).unwrap();
+        assert!((vol - true_vol((90.0 / forward).ln())).abs() < 1e-4, "{vol}");
+
+        let svi = VolSurface::build(&series, &chains, &market, SmileModel::Svi).unwrap();
+        assert!(svi.smiles[0].svi_params().is_some());
+        for expiration in [near_time, far_time] {
+            assert!((svi.atm_vol(expiration) - 0.2).abs() < 1e-3);
+            assert!((svi.skew(expiration) + 0.1).abs() < 1e-2);
+        }
+        // halfway in time between the expirations
+        let mid_time = near_time + (far_time - near_time) / 2;
+        assert!((svi.atm_vol(mid_time) - 0.2).abs() < 1e-3);
+        let term_structure = svi.term_structure();
+        assert_eq!(
+            term_structure.iter().map(|(d, _)| *d).collect::<Vec<_>>(),
+            [near, far]
+        );
+        // equal expiries would leave nothing to interpolate between
+        let near_smile = spline.smiles[0].clone();
+        assert!(VolSurface::from_smiles(market, vec![near_smile.clone(), near_smile])
+            .is_err());
+        let chains = [chain(&series, &market, near), chain(&series, &market, near)];
+        assert!(VolSurface::build(&series, &chains, &market, SmileModel::Spline).is_err());
+    }
+}
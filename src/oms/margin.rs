@@ -0,0 +1,639 @@
+//! Client-side margin estimates for positions, working orders and
+//! proposed [`PlaceOrderRequest`]s.
+//!
+//! A [`MarginCalculator`] holds a [`MarginRule`] per execution venue plus
+//! optional [`SpreadCredit`]s for offsetting positions.  Rules read prices
+//! and contract details through the [`MarginContext`] trait; [`MarginState`]
+//! is a simple in-memory implementation.
+//!
+//! Positions in an [`AccountPositions`] carry no venue, so callers say
+//! which venue the account's positions margin at.  Working orders margin
+//! at their own execution venue.
+
+use super::{OpenOrdersResponse, PlaceOrderRequest};
+use crate::{
+    folio::AccountPositions,
+    orderflow::Order,
+    symbology::{ExecutionInfo, ExecutionVenue, TradableProduct},
+    Dir,
+};
+use anyhow::{anyhow, Result};
+use rust_decimal::Decimal;
+use std::{
+    collections::BTreeMap,
+    ops::{Add, AddAssign, Sub},
+};
+
+/// Prices and contract details visible to margin rules.
+pub trait MarginContext {
+    /// Price that notional is computed at, e.g. mark or last.
+    fn mark_price(&self, symbol: &TradableProduct) -> Option<Decimal>;
+
+    /// Contract multiplier used for notional; defaults to 1 if None.
+    fn multiplier(&self, symbol: &TradableProduct) -> Option<Decimal>;
+
+    fn execution_info(
+        &self,
+        symbol: &TradableProduct,
+        venue: &ExecutionVenue,
+    ) -> Option<&ExecutionInfo>;
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MarginRequirement {
+    pub initial: Decimal,
+    pub maintenance: Decimal,
+}
+
+impl MarginRequirement {
+    pub fn new(initial: Decimal, maintenance: Decimal) -> Self {
+        Self { initial, maintenance }
+    }
+
+    pub fn scale(self, factor: Decimal) -> Self {
+        Self { initial: self.initial * factor, maintenance: self.maintenance * factor }
+    }
+}
+
+impl Add for MarginRequirement {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            initial: self.initial + rhs.initial,
+            maintenance: self.maintenance + rhs.maintenance,
+        }
+    }
+}
+
+impl AddAssign for MarginRequirement {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for MarginRequirement {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self {
+            initial: self.initial - rhs.initial,
+            maintenance: self.maintenance - rhs.maintenance,
+        }
+    }
+}
+
+pub trait MarginRule: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Margin for a signed net position in one symbol.
+    fn margin(
+        &self,
+        symbol: &TradableProduct,
+        venue: &ExecutionVenue,
+        quantity: Decimal,
+        ctx: &dyn MarginContext,
+    ) -> Result<MarginRequirement>;
+}
+
+/// Per-contract margins from the venue's [`ExecutionInfo`].  A missing
+/// maintenance margin defaults to the initial margin.
+#[derive(Debug, Default, Clone)]
+pub struct ExecutionInfoMargin;
+
+impl MarginRule for ExecutionInfoMargin {
+    fn name(&self) -> &str {
+        "execution_info"
+    }
+
+    fn margin(
+        &self,
+        symbol: &TradableProduct,
+        venue: &ExecutionVenue,
+        quantity: Decimal,
+        ctx: &dyn MarginContext,
+    ) -> Result<MarginRequirement> {
+        let info = ctx
+            .execution_info(symbol, venue)
+            .ok_or_else(|| anyhow!("no execution info for {symbol} on {venue}"))?;
+        let initial = info
+            .initial_margin
+            .ok_or_else(|| anyhow!("no initial margin for {symbol} on {venue}"))?;
+        let maintenance = info.maintenance_margin.unwrap_or(initial);
+        Ok(MarginRequirement::new(initial, maintenance).scale(quantity.abs()))
+    }
+}
+
+/// Fixed margin per contract, with per-symbol overrides.
+#[derive(Debug, Default, Clone)]
+pub struct FlatPerContract {
+    pub default: Option<MarginRequirement>,
+    pub by_symbol: BTreeMap<TradableProduct, MarginRequirement>,
+}
+
+impl MarginRule for FlatPerContract {
+    fn name(&self) -> &str {
+        "flat_per_contract"
+    }
+
+    fn margin(
+        &self,
+        symbol: &TradableProduct,
+        _venue: &ExecutionVenue,
+        quantity: Decimal,
+        _ctx: &dyn MarginContext,
+    ) -> Result<MarginRequirement> {
+        let per_contract = self
+            .by_symbol
+            .get(symbol)
+            .or(self.default.as_ref())
+            .ok_or_else(|| anyhow!("no per-contract margin for {symbol}"))?;
+        Ok(per_contract.scale(quantity.abs()))
+    }
+}
+
+/// Margin as a fraction of notional at the mark price, e.g. 0.1 for 10%.
+#[derive(Debug, Clone)]
+pub struct PercentOfNotional {
+    pub initial: Decimal,
+    pub maintenance: Decimal,
+}
+
+impl MarginRule for PercentOfNotional {
+    fn name(&self) -> &str {
+        "percent_of_notional"
+    }
+
+    fn margin(
+        &self,
+        symbol: &TradableProduct,
+        _venue: &ExecutionVenue,
+        quantity: Decimal,
+        ctx: &dyn MarginContext,
+    ) -> Result<MarginRequirement> {
+        let price = ctx
+            .mark_price(symbol)
+            .ok_or_else(|| anyhow!("no mark price for {symbol}"))?;
+        let multiplier = ctx.multiplier(symbol).unwrap_or(Decimal::ONE);
+        let notional = (quantity * price * multiplier).abs();
+        Ok(MarginRequirement::new(self.initial, self.maintenance).scale(notional))
+    }
+}
+
+/// A credit of `rate` times the legs' margin for each whole unit of a
+/// spread held, long or short, e.g. legs [(ESZ4, 1), (ESH5, -1)] and rate
+/// 0.8 for an 80% calendar spread credit.
+#[derive(Debug, Clone)]
+pub struct SpreadCredit {
+    pub name: String,
+    pub legs: Vec<(TradableProduct, Decimal)>,
+    pub rate: Decimal,
+}
+
+impl SpreadCredit {
+    /// Signed whole spread units held in `net`.  Legs with a zero ratio
+    /// don't constrain the count.
+    fn units(&self, net: &BTreeMap<TradableProduct, Decimal>) -> Decimal {
+        let held = |sign: Decimal| {
+            self.legs
+                .iter()
+                .filter(|(_, ratio)| !ratio.is_zero())
+                .map(|(symbol, ratio)| {
+                    let quantity = net.get(symbol).copied().unwrap_or_default();
+                    (quantity / (*ratio * sign)).trunc().max(Decimal::ZERO)
+                })
+                .min()
+                .unwrap_or_default()
+        };
+        let long = held(Decimal::ONE);
+        if long > Decimal::ZERO {
+            long
+        } else {
+            -held(Decimal::NEGATIVE_ONE)
+        }
+    }
+}
+
+#[derive(Default)]
+struct VenueMarginRules {
+    rule: Option<Box<dyn MarginRule>>,
+    spread_credits: Vec<SpreadCredit>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarginImpact {
+    pub before: MarginRequirement,
+    pub after: MarginRequirement,
+}
+
+impl MarginImpact {
+    pub fn delta(&self) -> MarginRequirement {
+        self.after - self.before
+    }
+}
+
+/// Margin rules by execution venue.  Venues without a rule of their own
+/// use the default rule, if any.
+#[derive(Default)]
+pub struct MarginCalculator {
+    default_rule: Option<Box<dyn MarginRule>>,
+    venues: BTreeMap<ExecutionVenue, VenueMarginRules>,
+}
+
+impl MarginCalculator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_default(mut self, rule: impl MarginRule + 'static) -> Self {
+        self.default_rule = Some(Box::new(rule));
+        self
+    }
+
+    pub fn with_venue(
+        mut self,
+        venue: ExecutionVenue,
+        rule: impl MarginRule + 'static,
+    ) -> Self {
+        self.set_rule(venue, rule);
+        self
+    }
+
+    pub fn with_spread_credit(
+        mut self,
+        venue: ExecutionVenue,
+        credit: SpreadCredit,
+    ) -> Self {
+        self.venues.entry(venue).or_default().spread_credits.push(credit);
+        self
+    }
+
+    pub fn set_rule(&mut self, venue: ExecutionVenue, rule: impl MarginRule + 'static) {
+        self.venues.entry(venue).or_default().rule = Some(Box::new(rule));
+    }
+
+    fn rule(&self, venue: &ExecutionVenue) -> Result<&dyn MarginRule> {
+        self.venues
+            .get(venue)
+            .and_then(|rules| rules.rule.as_deref())
+            .or(self.default_rule.as_deref())
+            .ok_or_else(|| anyhow!("no margin rule for {venue}"))
+    }
+
+    /// Margin for signed net positions at one venue, after spread credits.
+    /// Credits are applied greedily in the order they were added.
+    pub fn margin(
+        &self,
+        venue: &ExecutionVenue,
+        net: &BTreeMap<TradableProduct, Decimal>,
+        ctx: &dyn MarginContext,
+    ) -> Result<MarginRequirement> {
+        let rule = self.rule(venue)?;
+        let mut total = MarginRequirement::default();
+        for (symbol, quantity) in net {
+            if !quantity.is_zero() {
+                total += rule.margin(symbol, venue, *quantity, ctx)?;
+            }
+        }
+        let Some(rules) = self.venues.get(venue) else {
+            return Ok(total);
+        };
+        let mut remaining = net.clone();
+        for credit in &rules.spread_credits {
+            let units = credit.units(&remaining);
+            if units.is_zero() {
+                continue;
+            }
+            let mut legs = MarginRequirement::default();
+            for (symbol, ratio) in credit.legs.iter().filter(|(_, r)| !r.is_zero()) {
+                let quantity = units * ratio;
+                legs += rule.margin(symbol, venue, quantity, ctx)?;
+                *remaining.entry(symbol.clone()).or_default() -= quantity;
+            }
+            total = total - legs.scale(credit.rate);
+        }
+        Ok(total)
+    }
+
+    /// Margin on current positions only, i.e. `position_margin` in
+    /// [`AccountStatistics`](crate::folio::AccountStatistics).
+    pub fn position_margin(
+        &self,
+        venue: &ExecutionVenue,
+        positions: &AccountPositions,
+        ctx: &dyn MarginContext,
+    ) -> Result<MarginRequirement> {
+        self.margin(venue, &net_positions(positions), ctx)
+    }
+
+    /// Worst-case margin if working orders fill, i.e. `total_margin` in
+    /// [`AccountStatistics`](crate::folio::AccountStatistics).  For each
+    /// symbol, either all of its buys or all of its sells fill, whichever
+    /// needs more margin.
+    pub fn total_margin(
+        &self,
+        venue: &ExecutionVenue,
+        positions: &AccountPositions,
+        open_orders: &OpenOrdersResponse,
+        ctx: &dyn MarginContext,
+    ) -> Result<MarginRequirement> {
+        let working = open_orders
+            .open_orders
+            .iter()
+            .filter(|o| o.status.is_alive())
+            .map(WorkingOrder::from_order);
+        self.worst_case_margin(venue, positions, working, ctx)
+    }
+
+    /// Total margin before and after adding `order` to the working orders.
+    /// Orders without an execution venue margin at `venue`.
+    pub fn order_impact(
+        &self,
+        venue: &ExecutionVenue,
+        positions: &AccountPositions,
+        open_orders: &OpenOrdersResponse,
+        order: &PlaceOrderRequest,
+        ctx: &dyn MarginContext,
+    ) -> Result<MarginImpact> {
+        let before = self.total_margin(venue, positions, open_orders, ctx)?;
+        let proposed = WorkingOrder {
+            symbol: order.symbol.parse()?,
+            venue: order.execution_venue.clone().unwrap_or_else(|| venue.clone()),
+            dir: order.dir,
+            quantity: order.quantity,
+        };
+        let working = open_orders
+            .open_orders
+            .iter()
+            .filter(|o| o.status.is_alive())
+            .map(WorkingOrder::from_order)
+            .chain(std::iter::once(proposed));
+        let after = self.worst_case_margin(venue, positions, working, ctx)?;
+        Ok(MarginImpact { before, after })
+    }
+
+    fn worst_case_margin(
+        &self,
+        venue: &ExecutionVenue,
+        positions: &AccountPositions,
+        working: impl IntoIterator<Item = WorkingOrder>,
+        ctx: &dyn MarginContext,
+    ) -> Result<MarginRequirement> {
+        // (buys, sells) by venue and symbol
+        let mut sides: BTreeMap<
+            ExecutionVenue,
+            BTreeMap<TradableProduct, (Decimal, Decimal)>,
+        > = BTreeMap::new();
+        for order in working {
+            let side =
+                sides.entry(order.venue).or_default().entry(order.symbol).or_default();
+            match order.dir {
+                Dir::Buy => side.0 += order.quantity,
+                Dir::Sell => side.1 += order.quantity,
+            }
+        }
+        let mut net_by_venue = BTreeMap::new();
+        net_by_venue.insert(venue.clone(), net_positions(positions));
+        for (order_venue, symbols) in sides {
+            let rule = self.rule(&order_venue)?;
+            let net: &mut BTreeMap<_, _> =
+                net_by_venue.entry(order_venue.clone()).or_default();
+            for (symbol, (buys, sells)) in symbols {
+                let position = net.get(&symbol).copied().unwrap_or_default();
+                let long = rule.margin(&symbol, &order_venue, position + buys, ctx)?;
+                let short = rule.margin(&symbol, &order_venue, position - sells, ctx)?;
+                let worst = if long.initial >= short.initial {
+                    position + buys
+                } else {
+                    position - sells
+                };
+                net.insert(symbol, worst);
+            }
+        }
+        let mut total = MarginRequirement::default();
+        for (venue, net) in &net_by_venue {
+            total += self.margin(venue, net, ctx)?;
+        }
+        Ok(total)
+    }
+}
+
+struct WorkingOrder {
+    symbol: TradableProduct,
+    venue: ExecutionVenue,
+    dir: Dir,
+    quantity: Decimal,
+}
+
+impl WorkingOrder {
+    fn from_order(order: &Order) -> Self {
+        Self {
+            symbol: order.symbol.clone(),
+            venue: order.execution_venue.clone(),
+            dir: order.dir,
+            quantity: (order.quantity - order.filled_quantity).max(Decimal::ZERO),
+        }
+    }
+}
+
+/// Signed net quantity by symbol.
+pub fn net_positions(positions: &AccountPositions) -> BTreeMap<TradableProduct, Decimal> {
+    positions
+        .iter()
+        .map(|(symbol, positions)| {
+            (symbol.clone(), positions.iter().map(|p| p.quantity).sum::<Decimal>())
+        })
+        .collect()
+}
+
+/// In-memory [`MarginContext`] populated by the caller.
+#[derive(Debug, Default, Clone)]
+pub struct MarginState {
+    pub marks: BTreeMap<TradableProduct, Decimal>,
+    pub multipliers: BTreeMap<TradableProduct, Decimal>,
+    pub execution_info: BTreeMap<(TradableProduct, ExecutionVenue), ExecutionInfo>,
+}
+
+impl MarginContext for MarginState {
+    fn mark_price(&self, symbol: &TradableProduct) -> Option<Decimal> {
+        self.marks.get(symbol).copied()
+    }
+
+    fn multiplier(&self, symbol: &TradableProduct) -> Option<Decimal> {
+        self.multipliers.get(symbol).copied()
+    }
+
+    fn execution_info(
+        &self,
+        symbol: &TradableProduct,
+        venue: &ExecutionVenue,
+    ) -> Option<&ExecutionInfo> {
+        self.execution_info.get(&(symbol.clone(), venue.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        folio::AccountPosition,
+        oms::PlaceOrderRequestBuilder,
+        orderflow::{LimitOrderType, OrderType},
+        symbology::{MinOrderQuantityUnit, TickSize},
+    };
+    use rust_decimal_macros::dec;
+
+    const ESZ4: &str = "ES 20241220 CME Future/USD";
+    const ESH5: &str = "ES 20250321 CME Future/USD";
+
+    fn positions(entries: &[(&str, Decimal)]) -> AccountPositions {
+        entries
+            .iter()
+            .map(|(symbol, quantity)| {
+                let position =
+                    AccountPosition { quantity: *quantity, ..Default::default() };
+                (symbol.parse().unwrap(), vec![position])
+            })
+            .collect()
+    }
+
+    fn order(symbol: &str, dir: Dir, quantity: Decimal) -> PlaceOrderRequest {
+        PlaceOrderRequestBuilder::default()
+            .id(None)
+            .parent_id(None)
+            .symbol(symbol.to_string())
+            .dir(dir)
+            .quantity(quantity)
+            .order_type(OrderType::Limit(LimitOrderType {
+                limit_price: dec!(5000),
+                post_only: false,
+            }))
+            .build()
+            .unwrap()
+    }
+
+    fn state() -> MarginState {
+        let mut state = MarginState::default();
+        for symbol in [ESZ4, ESH5] {
+            let symbol: TradableProduct = symbol.parse().unwrap();
+            state.marks.insert(symbol.clone(), dec!(5000));
+            state.multipliers.insert(symbol.clone(), dec!(50));
+            state.execution_info.insert(
+                (symbol, "CME".into()),
+                ExecutionInfo {
+                    execution_venue: "CME".into(),
+                    exchange_symbol: None,
+                    tick_size: TickSize::Simple(dec!(0.25)),
+                    step_size: dec!(1),
+                    min_order_quantity: dec!(1),
+                    min_order_quantity_unit: MinOrderQuantityUnit::Base,
+                    is_delisted: false,
+                    initial_margin: Some(dec!(13000)),
+                    maintenance_margin: Some(dec!(12000)),
+                },
+            );
+        }
+        state
+    }
+
+    #[test]
+    fn test_position_and_order_margin() -> Result<()> {
+        let cme: ExecutionVenue = "CME".into();
+        let state = state();
+        let calculator =
+            MarginCalculator::new().with_venue(cme.clone(), ExecutionInfoMargin);
+        let held = positions(&[(ESZ4, dec!(2))]);
+        assert_eq!(
+            calculator.position_margin(&cme, &held, &state)?,
+            MarginRequirement::new(dec!(26000), dec!(24000))
+        );
+        let open_orders = OpenOrdersResponse { open_orders: vec![] };
+        // selling 3 against a long 2 leaves short 1
+        let impact = calculator.order_impact(
+            &cme,
+            &held,
+            &open_orders,
+            &order(ESZ4, Dir::Sell, dec!(3)),
+            &state,
+        )?;
+        assert_eq!(impact.delta(), MarginRequirement::default());
+        let impact = calculator.order_impact(
+            &cme,
+            &held,
+            &open_orders,
+            &order(ESZ4, Dir::Buy, dec!(1)),
+            &state,
+        )?;
+        assert_eq!(impact.delta(), MarginRequirement::new(dec!(13000), dec!(12000)));
+
+        // percent of notional: 2 * 5000 * 50 * 10%
+        let calculator = MarginCalculator::new().with_default(PercentOfNotional {
+            initial: dec!(0.1),
+            maintenance: dec!(0.08),
+        });
+        assert_eq!(
+            calculator.position_margin(&cme, &held, &state)?,
+            MarginRequirement::new(dec!(50000), dec!(40000))
+        );
+        assert!(calculator
+            .position_margin(&cme, &positions(&[("BTC Crypto/USD", dec!(1))]), &state)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_spread_credit() -> Result<()> {
+        let cme: ExecutionVenue = "CME".into();
+        let state = state();
+        let calculator = MarginCalculator::new()
+            .with_venue(
+                cme.clone(),
+                FlatPerContract {
+                    default: Some(MarginRequirement::new(dec!(1000), dec!(800))),
+                    by_symbol: BTreeMap::new(),
+                },
+            )
+            .with_spread_credit(
+                cme.clone(),
+                SpreadCredit {
+                    name: "ES calendar".to_string(),
+                    legs: vec![(ESZ4.parse()?, dec!(1)), (ESH5.parse()?, dec!(-1))],
+                    rate: dec!(0.75),
+                },
+            );
+        // short 2 spreads plus 1 outright ESH5: 5 contracts less 75% of 4
+        let held = positions(&[(ESZ4, dec!(-2)), (ESH5, dec!(3))]);
+        assert_eq!(
+            calculator.position_margin(&cme, &held, &state)?,
+            MarginRequirement::new(dec!(2000), dec!(1600))
+        );
+        // a zero ratio leg is ignored rather than dividing by zero
+        let credit = SpreadCredit {
+            name: "degenerate".to_string(),
+            legs: vec![(ESZ4.parse()?, dec!(-1)), (ESH5.parse()?, dec!(0))],
+            rate: dec!(0.75),
+        };
+        assert_eq!(credit.units(&net_positions(&held)), dec!(2));
+        // nor is its margin looked up, here a leg without execution info
+        let calculator = MarginCalculator::new()
+            .with_venue(cme.clone(), ExecutionInfoMargin)
+            .with_spread_credit(
+                cme.clone(),
+                SpreadCredit {
+                    name: "ES calendar".to_string(),
+                    legs: vec![
+                        (ESZ4.parse()?, dec!(1)),
+                        (ESH5.parse()?, dec!(-1)),
+                        ("NQ 20241220 CME Future/USD".parse()?, dec!(0)),
+                    ],
+                    rate: dec!(0.75),
+                },
+            );
+        // 5 contracts less 75% of 4
+        assert_eq!(
+            calculator.position_margin(&cme, &held, &state)?,
+            MarginRequirement::new(dec!(26000), dec!(24000))
+        );
+        Ok(())
+    }
+}
@@ -3,9 +3,10 @@ use crate::{
         order_types::*, Cancel, CancelReject, Modify, Order, OrderReject, OrderSource,
         TimeInForce,
     },
-    symbology::ExecutionVenue,
+    symbology::{ExecutionVenue, OptionsStrategy},
     AccountIdOrName, Dir, OrderId, TraderIdOrEmail,
 };
+use anyhow::{bail, Result};
 use chrono::{DateTime, Utc};
 use derive::grpc;
 use derive_builder::Builder;
@@ -15,6 +16,10 @@ use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 use uuid::Uuid;
 
+pub mod margin;
+pub mod risk;
+pub mod validate;
+
 #[grpc(package = "json.architect")]
 #[grpc(service = "Oms", name = "place_order", response = "Order")]
 #[derive(Builder, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
@@ -73,6 +78,66 @@ pub struct PlaceBatchOrderResponse {
     pub order_rejects: Vec<OrderReject>,
 }
 
+/// Place an order for a multi-leg options strategy.  Buying the strategy
+/// executes each leg in its own direction, selling reverses every leg.
+#[grpc(package = "json.architect")]
+#[grpc(service = "Oms", name = "place_options_strategy_order", response = "Order")]
+#[derive(Builder, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+/// <!-- py: unflatten=k/order_type/OptionsStrategyOrderType, tag=k -->
+pub struct PlaceOptionsStrategyOrderRequest {
+    /// If not specified, one will be generated for you; note, in that case,
+    /// you won't know for sure if the specific request went through.
+    pub id: Option<OrderId>,
+    #[serde(rename = "pid", default)]
+    #[schemars(title = "parent_id")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<OrderId>,
+    #[serde(rename = "st")]
+    #[schemars(title = "strategy")]
+    pub strategy: OptionsStrategy,
+    #[serde(rename = "d")]
+    #[schemars(title = "dir")]
+    pub dir: Dir,
+    /// Number of units of the strategy
+    #[serde(rename = "q")]
+    #[schemars(title = "quantity")]
+    pub quantity: Decimal,
+    #[serde(rename = "u", default)]
+    #[schemars(title = "trader")]
+    #[builder(setter(strip_option), default)]
+    pub trader: Option<TraderIdOrEmail>,
+    #[serde(rename = "a", default)]
+    #[schemars(title = "account")]
+    #[builder(setter(strip_option), default)]
+    pub account: Option<AccountIdOrName>,
+    #[serde(flatten)]
+    pub order_type: OptionsStrategyOrderType,
+    #[serde(rename = "tif")]
+    #[schemars(title = "time_in_force")]
+    #[builder(default = "TimeInForce::GoodTilCancel")]
+    pub time_in_force: TimeInForce,
+    #[serde(rename = "src", default)]
+    #[schemars(title = "source")]
+    #[builder(setter(strip_option), default)]
+    pub source: Option<OrderSource>,
+    #[serde(rename = "x", default)]
+    #[schemars(title = "execution_venue")]
+    #[builder(setter(strip_option), default)]
+    pub execution_venue: Option<ExecutionVenue>,
+}
+
+impl PlaceOptionsStrategyOrderRequest {
+    /// Check the strategy's legs and that the quantity is a positive whole
+    /// number of strategy units.
+    pub fn validate(&self) -> Result<()> {
+        self.strategy.validate()?;
+        if self.quantity <= Decimal::ZERO || !self.quantity.fract().is_zero() {
+            bail!("quantity {} must be a positive whole number of units", self.quantity);
+        }
+        Ok(())
+    }
+}
+
 #[grpc(package = "json.architect")]
 #[grpc(service = "Oms", name = "cancel_order", response = "Cancel")]
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
@@ -218,3 +283,63 @@ pub struct ReconcileOutRequest {
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ReconcileOutResponse {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        orderflow::NetLimitOrderType,
+        symbology::{OptionsSeries, PutOrCall},
+    };
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    fn strategy_order(quantity: Decimal) -> PlaceOptionsStrategyOrderRequest {
+        let expiration = Utc.with_ymd_and_hms(2026, 1, 16, 21, 0, 0).unwrap();
+        let strategy = OptionsStrategy::vertical(
+            OptionsSeries::new_unchecked("AAPL US Options"),
+            expiration,
+            PutOrCall::Call,
+            dec!(100),
+            dec!(105),
+        )
+        .unwrap();
+        PlaceOptionsStrategyOrderRequestBuilder::default()
+            .id(None)
+            .parent_id(Some(OrderId::nil(1)))
+            .strategy(strategy)
+            .dir(Dir::Buy)
+            .quantity(quantity)
+            .order_type(OptionsStrategyOrderType::NetLimit(NetLimitOrderType {
+                limit_price: dec!(-1.25),
+                post_only: false,
+            }))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_options_strategy_order_request() -> Result<()> {
+        let order = strategy_order(dec!(2));
+        order.validate()?;
+        let json = serde_json::to_value(&order)?;
+        assert_eq!(json["k"], "NET_LIMIT");
+        assert_eq!(json["pid"], serde_json::to_value(OrderId::nil(1))?);
+        assert_eq!(json["st"]["os"], "AAPL US Options");
+        assert_eq!(json["st"]["l"].as_array().map(|legs| legs.len()), Some(2));
+        let parsed: PlaceOptionsStrategyOrderRequest = serde_json::from_value(json)?;
+        assert_eq!(parsed, order);
+        let mut buf = vec![];
+        rmp_serde::encode::write(&mut buf, &order)?;
+        let parsed: PlaceOptionsStrategyOrderRequest =
+            rmp_serde::decode::from_slice(&buf)?;
+        assert_eq!(parsed, order);
+
+        assert!(strategy_order(dec!(0)).validate().is_err());
+        assert!(strategy_order(dec!(1.5)).validate().is_err());
+        let mut order = strategy_order(dec!(1));
+        order.strategy.legs[1].instance = order.strategy.legs[0].instance;
+        assert!(order.validate().is_err());
+        Ok(())
+    }
+}
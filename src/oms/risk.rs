@@ -0,0 +1,459 @@
+//! Pre-trade risk checks for [`PlaceOrderRequest`]s.
+//!
+//! A [`RiskChecks`] holds an ordered set of [`RiskCheck`]s which are run
+//! against each order before it is sent.  Checks read market and account
+//! state through the [`RiskContext`] trait; [`RiskState`] is a simple
+//! in-memory implementation.
+
+use super::PlaceOrderRequest;
+use crate::{
+    marketdata::TickerValues,
+    orderflow::{OrderReject, OrderRejectReason},
+    symbology::TickSize,
+    AccountIdOrName, Dir, OrderId,
+};
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+/// Market and account state visible to risk checks.
+pub trait RiskContext {
+    /// Price that limit prices are compared against, e.g. mark or last.
+    fn reference_price(&self, symbol: &str) -> Option<Decimal>;
+
+    fn tick_size(&self, symbol: &str) -> Option<TickSize>;
+
+    /// Contract multiplier used for notional; defaults to 1 if None.
+    fn multiplier(&self, symbol: &str) -> Option<Decimal>;
+
+    fn open_orders(&self, account: Option<&AccountIdOrName>) -> usize;
+
+    /// Signed position in `symbol`
+    fn position(&self, account: Option<&AccountIdOrName>, symbol: &str) -> Decimal;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RiskViolation {
+    /// Name of the check that failed
+    pub check: String,
+    pub reason: OrderRejectReason,
+    pub message: String,
+}
+
+impl RiskViolation {
+    pub fn new(check: &dyn RiskCheck, message: String) -> Self {
+        Self { check: check.name().to_string(), reason: check.reject_reason(), message }
+    }
+
+    pub fn to_order_reject(&self, order_id: OrderId) -> OrderReject {
+        OrderReject {
+            order_id,
+            reason: self.reason,
+            message: Some(format!("{}: {}", self.check, self.message)),
+        }
+    }
+}
+
+impl std::fmt::Display for RiskViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}): {}", self.check, self.reason, self.message)
+    }
+}
+
+impl std::error::Error for RiskViolation {}
+
+pub trait RiskCheck: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Reason reported when the check fails.
+    fn reject_reason(&self) -> OrderRejectReason {
+        OrderRejectReason::InvalidOrder
+    }
+
+    fn check(
+        &self,
+        order: &PlaceOrderRequest,
+        ctx: &dyn RiskContext,
+    ) -> Result<(), RiskViolation>;
+}
+
+/// An ordered set of risk checks.
+#[derive(Default)]
+pub struct RiskChecks {
+    checks: Vec<Box<dyn RiskCheck>>,
+}
+
+impl RiskChecks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, check: impl RiskCheck + 'static) -> Self {
+        self.push(check);
+        self
+    }
+
+    pub fn push(&mut self, check: impl RiskCheck + 'static) {
+        self.checks.push(Box::new(check));
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.checks.iter().map(|c| c.name())
+    }
+
+    /// Run checks in order, stopping at the first violation.
+    pub fn check(
+        &self,
+        order: &PlaceOrderRequest,
+        ctx: &dyn RiskContext,
+    ) -> Result<(), RiskViolation> {
+        for check in &self.checks {
+            check.check(order, ctx)?;
+        }
+        Ok(())
+    }
+
+    /// Run every check, returning all violations.
+    pub fn check_all(
+        &self,
+        order: &PlaceOrderRequest,
+        ctx: &dyn RiskContext,
+    ) -> Vec<RiskViolation> {
+        self.checks.iter().filter_map(|c| c.check(order, ctx).err()).collect()
+    }
+}
+
+/// Price used for notional computations: the limit price if any,
+/// otherwise the reference price.
+fn order_price(order: &PlaceOrderRequest, ctx: &dyn RiskContext) -> Option<Decimal> {
+    order.order_type.limit_price().or_else(|| ctx.reference_price(&order.symbol))
+}
+
+#[derive(Debug, Clone)]
+pub struct MaxOrderQuantity {
+    pub max_quantity: Decimal,
+}
+
+impl RiskCheck for MaxOrderQuantity {
+    fn name(&self) -> &str {
+        "max_order_quantity"
+    }
+
+    fn check(
+        &self,
+        order: &PlaceOrderRequest,
+        _ctx: &dyn RiskContext,
+    ) -> Result<(), RiskViolation> {
+        if order.quantity > self.max_quantity {
+            return Err(RiskViolation::new(
+                self,
+                format!("quantity {} exceeds {}", order.quantity, self.max_quantity),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MaxOrderNotional {
+    pub max_notional: Decimal,
+}
+
+impl RiskCheck for MaxOrderNotional {
+    fn name(&self) -> &str {
+        "max_order_notional"
+    }
+
+    fn reject_reason(&self) -> OrderRejectReason {
+        OrderRejectReason::InsufficientMargin
+    }
+
+    fn check(
+        &self,
+        order: &PlaceOrderRequest,
+        ctx: &dyn RiskContext,
+    ) -> Result<(), RiskViolation> {
+        let Some(price) = order_price(order, ctx) else {
+            return Err(RiskViolation::new(self, "no price to compute notional".into()));
+        };
+        let multiplier = ctx.multiplier(&order.symbol).unwrap_or(Decimal::ONE);
+        let notional = (order.quantity * price * multiplier).abs();
+        if notional > self.max_notional {
+            return Err(RiskViolation::new(
+                self,
+                format!("notional {notional} exceeds {}", self.max_notional),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Reject limit or trigger prices more than `max_deviation` (as a
+/// fraction, e.g. 0.05 for 5%) away from the reference price.
+#[derive(Debug, Clone)]
+pub struct PriceBand {
+    pub max_deviation: Decimal,
+}
+
+impl RiskCheck for PriceBand {
+    fn name(&self) -> &str {
+        "price_band"
+    }
+
+    fn check(
+        &self,
+        order: &PlaceOrderRequest,
+        ctx: &dyn RiskContext,
+    ) -> Result<(), RiskViolation> {
+        let prices = [order.order_type.limit_price(), order.order_type.trigger_price()];
+        if prices.iter().all(Option::is_none) {
+            return Ok(());
+        }
+        let Some(reference) = ctx.reference_price(&order.symbol) else {
+            return Err(RiskViolation::new(self, "no reference price".into()));
+        };
+        if reference.is_zero() {
+            return Err(RiskViolation::new(self, "reference price is zero".into()));
+        }
+        for price in prices.into_iter().flatten() {
+            let deviation = ((price - reference) / reference).abs();
+            if deviation > self.max_deviation {
+                return Err(RiskViolation::new(
+                    self,
+                    format!(
+                        "price {price} is {deviation} away from reference {reference}"
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MaxOpenOrders {
+    pub max_open_orders: usize,
+}
+
+impl RiskCheck for MaxOpenOrders {
+    fn name(&self) -> &str {
+        "max_open_orders"
+    }
+
+    fn check(
+        &self,
+        order: &PlaceOrderRequest,
+        ctx: &dyn RiskContext,
+    ) -> Result<(), RiskViolation> {
+        let open = ctx.open_orders(order.account.as_ref());
+        if open >= self.max_open_orders {
+            return Err(RiskViolation::new(
+                self,
+                format!("{open} open orders, limit is {}", self.max_open_orders),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Reject orders that would take the absolute position past `max_position`.
+/// Orders that reduce the absolute position are always allowed.
+#[derive(Debug, Clone)]
+pub struct PositionLimit {
+    pub max_position: Decimal,
+}
+
+impl RiskCheck for PositionLimit {
+    fn name(&self) -> &str {
+        "position_limit"
+    }
+
+    fn reject_reason(&self) -> OrderRejectReason {
+        OrderRejectReason::InsufficientMargin
+    }
+
+    fn check(
+        &self,
+        order: &PlaceOrderRequest,
+        ctx: &dyn RiskContext,
+    ) -> Result<(), RiskViolation> {
+        let position = ctx.position(order.account.as_ref(), &order.symbol);
+        let after = position + order.quantity * order.dir.position_sign();
+        if after.abs() > self.max_position && after.abs() > position.abs() {
+            return Err(RiskViolation::new(
+                self,
+                format!("resulting position {after} exceeds {}", self.max_position),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Fat-finger check: reject limit prices more than `max_ticks` through
+/// the reference price in the aggressive direction.
+#[derive(Debug, Clone)]
+pub struct MaxTicksAway {
+    pub max_ticks: Decimal,
+}
+
+impl RiskCheck for MaxTicksAway {
+    fn name(&self) -> &str {
+        "max_ticks_away"
+    }
+
+    fn check(
+        &self,
+        order: &PlaceOrderRequest,
+        ctx: &dyn RiskContext,
+    ) -> Result<(), RiskViolation> {
+        let Some(limit_price) = order.order_type.limit_price() else {
+            return Ok(());
+        };
+        let Some(reference) = ctx.reference_price(&order.symbol) else {
+            return Err(RiskViolation::new(self, "no reference price".into()));
+        };
+        let Some(tick_size) = ctx.tick_size(&order.symbol) else {
+            return Err(RiskViolation::new(self, "no tick size".into()));
+        };
+        let Some(ticks) = tick_size.signed_tick_distance(reference, limit_price) else {
+            return Err(RiskViolation::new(self, "cannot compute tick distance".into()));
+        };
+        let ticks_through = match order.dir {
+            Dir::Buy => ticks,
+            Dir::Sell => -ticks,
+        };
+        if ticks_through > self.max_ticks {
+            return Err(RiskViolation::new(
+                self,
+                format!(
+                    "limit price {limit_price} is {ticks_through} ticks through reference {reference}"
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// In-memory [`RiskContext`] populated by the caller.
+#[derive(Debug, Default, Clone)]
+pub struct RiskState {
+    pub tickers: BTreeMap<String, TickerValues>,
+    pub tick_sizes: BTreeMap<String, TickSize>,
+    pub multipliers: BTreeMap<String, Decimal>,
+    pub open_orders: BTreeMap<Option<AccountIdOrName>, usize>,
+    pub positions: BTreeMap<(Option<AccountIdOrName>, String), Decimal>,
+}
+
+impl RiskContext for RiskState {
+    fn reference_price(&self, symbol: &str) -> Option<Decimal> {
+        let ticker = self.tickers.get(symbol)?;
+        ticker.mark_price.or_else(|| ticker.last_or_mid_price())
+    }
+
+    fn tick_size(&self, symbol: &str) -> Option<TickSize> {
+        self.tick_sizes.get(symbol).cloned()
+    }
+
+    fn multiplier(&self, symbol: &str) -> Option<Decimal> {
+        self.multipliers.get(symbol).copied()
+    }
+
+    fn open_orders(&self, account: Option<&AccountIdOrName>) -> usize {
+        self.open_orders.get(&account.cloned()).copied().unwrap_or(0)
+    }
+
+    fn position(&self, account: Option<&AccountIdOrName>, symbol: &str) -> Decimal {
+        self.positions
+            .get(&(account.cloned(), symbol.to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        oms::PlaceOrderRequestBuilder,
+        orderflow::{LimitOrderType, OrderType},
+    };
+    use rust_decimal_macros::dec;
+
+    fn order(dir: Dir, quantity: Decimal, limit_price: Decimal) -> PlaceOrderRequest {
+        PlaceOrderRequestBuilder::default()
+            .id(None)
+            .parent_id(None)
+            .symbol("ES 20241220 CME Future/USD".to_string())
+            .dir(dir)
+            .quantity(quantity)
+            .order_type(OrderType::Limit(LimitOrderType {
+                limit_price,
+                post_only: false,
+            }))
+            .build()
+            .unwrap()
+    }
+
+    fn state() -> RiskState {
+        let symbol = "ES 20241220 CME Future/USD".to_string();
+        let mut state = RiskState::default();
+        state.tickers.insert(
+            symbol.clone(),
+            TickerValues { last_price: Some(dec!(5000)), ..Default::default() },
+        );
+        state.tick_sizes.insert(symbol.clone(), TickSize::Simple(dec!(0.25)));
+        state.multipliers.insert(symbol.clone(), dec!(50));
+        state.positions.insert((None, symbol), dec!(8));
+        state
+    }
+
+    #[test]
+    fn test_risk_checks() {
+        let checks = RiskChecks::new()
+            .with(MaxOrderQuantity { max_quantity: dec!(10) })
+            .with(MaxOrderNotional { max_notional: dec!(1_000_000) })
+            .with(PriceBand { max_deviation: dec!(0.05) })
+            .with(MaxOpenOrders { max_open_orders: 5 })
+            .with(PositionLimit { max_position: dec!(10) })
+            .with(MaxTicksAway { max_ticks: dec!(20) });
+        let state = state();
+        assert_eq!(checks.check(&order(Dir::Buy, dec!(1), dec!(5001)), &state), Ok(()));
+        // reducing the position is fine even when large
+        assert_eq!(checks.check(&order(Dir::Sell, dec!(4), dec!(4999)), &state), Ok(()));
+
+        let names = |o: &PlaceOrderRequest| -> Vec<String> {
+            checks.check_all(o, &state).into_iter().map(|v| v.check).collect()
+        };
+        assert_eq!(
+            names(&order(Dir::Buy, dec!(11), dec!(5000))),
+            ["max_order_quantity", "max_order_notional", "position_limit"]
+        );
+        assert_eq!(names(&order(Dir::Buy, dec!(1), dec!(5010))), ["max_ticks_away"]);
+        // passive prices far from the reference are not fat-fingers
+        assert_eq!(names(&order(Dir::Sell, dec!(1), dec!(5010))), Vec::<String>::new());
+        assert_eq!(
+            names(&order(Dir::Sell, dec!(1), dec!(4000))),
+            ["price_band", "max_ticks_away"]
+        );
+
+        let violation = checks.check(&order(Dir::Buy, dec!(11), dec!(5000)), &state);
+        let reject = violation.unwrap_err().to_order_reject(OrderId::nil(1));
+        assert_eq!(reject.reason, OrderRejectReason::InvalidOrder);
+        assert_eq!(
+            reject.message.as_deref(),
+            Some("max_order_quantity: quantity 11 exceeds 10")
+        );
+        let reasons: Vec<_> = checks
+            .check_all(&order(Dir::Buy, dec!(11), dec!(5000)), &state)
+            .into_iter()
+            .map(|v| (v.check, v.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                ("max_order_quantity".to_string(), OrderRejectReason::InvalidOrder),
+                ("max_order_notional".to_string(), OrderRejectReason::InsufficientMargin),
+                ("position_limit".to_string(), OrderRejectReason::InsufficientMargin),
+            ]
+        );
+    }
+}
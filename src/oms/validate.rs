@@ -0,0 +1,360 @@
+//! Validate orders against a venue's [`ExecutionInfo`] before submission.
+//!
+//! The `validate_*` functions only check; the `normalize_*` functions first
+//! snap prices onto the tick grid and quantities onto the step grid, then
+//! validate the result.
+
+use super::{ModifyOrderRequest, PlaceOrderRequest};
+use crate::{
+    orderflow::{OrderReject, OrderRejectReason, OrderType},
+    symbology::ExecutionInfo,
+    Dir, OrderId,
+};
+use rust_decimal::Decimal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderValidationError {
+    Delisted,
+    /// The tick size is zero or doesn't cover the price.
+    InvalidTickSize {
+        price: Decimal,
+    },
+    PriceOffTick {
+        field: &'static str,
+        price: Decimal,
+    },
+    NonPositiveQuantity {
+        quantity: Decimal,
+    },
+    QuantityOffStep {
+        quantity: Decimal,
+        step_size: Decimal,
+    },
+    /// `min_quantity` is in base units.
+    BelowMinQuantity {
+        quantity: Decimal,
+        min_quantity: Decimal,
+    },
+}
+
+impl OrderValidationError {
+    pub fn to_order_reject(&self, order_id: OrderId) -> OrderReject {
+        OrderReject {
+            order_id,
+            reason: OrderRejectReason::InvalidOrder,
+            message: Some(self.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for OrderValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Delisted => write!(f, "product is delisted"),
+            Self::InvalidTickSize { price } => {
+                write!(f, "no valid tick size for price {price}")
+            }
+            Self::PriceOffTick { field, price } => {
+                write!(f, "{field} {price} is not on a tick")
+            }
+            Self::NonPositiveQuantity { quantity } => {
+                write!(f, "quantity {quantity} must be positive")
+            }
+            Self::QuantityOffStep { quantity, step_size } => {
+                write!(
+                    f,
+                    "quantity {quantity} is not a multiple of step size {step_size}"
+                )
+            }
+            Self::BelowMinQuantity { quantity, min_quantity } => {
+                write!(f, "quantity {quantity} is below minimum {min_quantity}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for OrderValidationError {}
+
+/// How to snap prices onto the tick grid when normalizing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceRounding {
+    /// Round away from the market, e.g. down for a buy.
+    Passive,
+    /// Round through the market, e.g. up for a buy.
+    Aggressive,
+}
+
+pub fn validate_place_order(
+    info: &ExecutionInfo,
+    order: &PlaceOrderRequest,
+) -> Result<(), OrderValidationError> {
+    if info.is_delisted {
+        return Err(OrderValidationError::Delisted);
+    }
+    for (field, price, _) in order_prices(&order.order_type, order.dir) {
+        check_price(info, field, price)?;
+    }
+    check_quantity(info, order.quantity, order.order_type.limit_price())
+}
+
+/// Round prices in the direction given by `rounding`, round the quantity
+/// down to the step size, then validate.  Quantities are never rounded up,
+/// so an order below the minimum after rounding is still rejected.
+pub fn normalize_place_order(
+    info: &ExecutionInfo,
+    order: &mut PlaceOrderRequest,
+    rounding: PriceRounding,
+) -> Result<(), OrderValidationError> {
+    for (_, price, dir) in order_prices_mut(&mut order.order_type, order.dir) {
+        *price = round_price(info, *price, dir, rounding)?;
+    }
+    order.quantity = info.round_quantity_down(order.quantity);
+    validate_place_order(info, order)
+}
+
+pub fn validate_modify_order(
+    info: &ExecutionInfo,
+    modify: &ModifyOrderRequest,
+) -> Result<(), OrderValidationError> {
+    if info.is_delisted {
+        return Err(OrderValidationError::Delisted);
+    }
+    if let Some(price) = modify.new_price {
+        check_price(info, "new_price", price)?;
+    }
+    if let Some(quantity) = modify.new_quantity {
+        check_quantity(info, quantity, modify.new_price)?;
+    }
+    Ok(())
+}
+
+/// Like [`normalize_place_order`]; `dir` is the direction of the order
+/// being modified.
+pub fn normalize_modify_order(
+    info: &ExecutionInfo,
+    modify: &mut ModifyOrderRequest,
+    dir: Dir,
+    rounding: PriceRounding,
+) -> Result<(), OrderValidationError> {
+    if let Some(price) = modify.new_price.as_mut() {
+        *price = round_price(info, *price, dir, rounding)?;
+    }
+    if let Some(quantity) = modify.new_quantity.as_mut() {
+        *quantity = info.round_quantity_down(*quantity);
+    }
+    validate_modify_order(info, modify)
+}
+
+fn check_price(
+    info: &ExecutionInfo,
+    field: &'static str,
+    price: Decimal,
+) -> Result<(), OrderValidationError> {
+    match info.tick_size.round_down(price) {
+        None => Err(OrderValidationError::InvalidTickSize { price }),
+        Some(rounded) if rounded != price => {
+            Err(OrderValidationError::PriceOffTick { field, price })
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+fn check_quantity(
+    info: &ExecutionInfo,
+    quantity: Decimal,
+    price: Option<Decimal>,
+) -> Result<(), OrderValidationError> {
+    if quantity <= Decimal::ZERO {
+        return Err(OrderValidationError::NonPositiveQuantity { quantity });
+    }
+    if info.round_quantity_down(quantity) != quantity {
+        return Err(OrderValidationError::QuantityOffStep {
+            quantity,
+            step_size: info.step_size,
+        });
+    }
+    // for quote-denominated minimums without a price, we can't enforce
+    if let Some(min_quantity) = info.min_quantity_in_base_units(price) {
+        if quantity < min_quantity {
+            return Err(OrderValidationError::BelowMinQuantity {
+                quantity,
+                min_quantity,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn round_price(
+    info: &ExecutionInfo,
+    price: Decimal,
+    dir: Dir,
+    rounding: PriceRounding,
+) -> Result<Decimal, OrderValidationError> {
+    let rounded = match rounding {
+        PriceRounding::Passive => info.tick_size.round_passive(price, dir),
+        PriceRounding::Aggressive => info.tick_size.round_aggressive(price, dir),
+    };
+    rounded.ok_or(OrderValidationError::InvalidTickSize { price })
+}
+
+/// Every price on the order, with the direction of the order leg it
+/// belongs to; bracket take-profit and stop-loss legs exit the position.
+fn order_prices(order_type: &OrderType, dir: Dir) -> Vec<(&'static str, Decimal, Dir)> {
+    let mut order_type = *order_type;
+    order_prices_mut(&mut order_type, dir)
+        .into_iter()
+        .map(|(field, price, dir)| (field, *price, dir))
+        .collect()
+}
+
+fn order_prices_mut(
+    order_type: &mut OrderType,
+    dir: Dir,
+) -> Vec<(&'static str, &mut Decimal, Dir)> {
+    match order_type {
+        OrderType::Market => vec![],
+        OrderType::Limit(limit) => vec![("limit_price", &mut limit.limit_price, dir)],
+        OrderType::StopLossLimit(trigger) | OrderType::TakeProfitLimit(trigger) => {
+            vec![
+                ("limit_price", &mut trigger.limit_price, dir),
+                ("trigger_price", &mut trigger.trigger_price, dir),
+            ]
+        }
+        OrderType::Bracket(bracket) => {
+            let mut prices = vec![("limit_price", &mut bracket.limit_price, dir)];
+            if let Some(price) = bracket.take_profit_price.as_mut() {
+                prices.push(("take_profit_price", price, dir.flip()));
+            }
+            if let Some(stop_loss) = bracket.stop_loss.as_mut() {
+                prices.push((
+                    "stop_loss.limit_price",
+                    &mut stop_loss.limit_price,
+                    dir.flip(),
+                ));
+                prices.push((
+                    "stop_loss.trigger_price",
+                    &mut stop_loss.trigger_price,
+                    dir.flip(),
+                ));
+            }
+            prices
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        oms::PlaceOrderRequestBuilder,
+        orderflow::{LimitOrderType, TriggerLimitOrderType},
+        symbology::{ExecutionVenue, MinOrderQuantityUnit, TickSize},
+    };
+    use rust_decimal_macros::dec;
+
+    fn info() -> ExecutionInfo {
+        ExecutionInfo {
+            execution_venue: ExecutionVenue::from("BINANCE"),
+            exchange_symbol: None,
+            tick_size: TickSize::Varying {
+                thresholds: vec![(dec!(0), dec!(0.01)), (dec!(100), dec!(0.05))],
+            },
+            step_size: dec!(0.001),
+            min_order_quantity: dec!(10),
+            min_order_quantity_unit: MinOrderQuantityUnit::Quote,
+            is_delisted: false,
+            initial_margin: None,
+            maintenance_margin: None,
+        }
+    }
+
+    fn order(dir: Dir, quantity: Decimal, order_type: OrderType) -> PlaceOrderRequest {
+        PlaceOrderRequestBuilder::default()
+            .id(None)
+            .parent_id(None)
+            .symbol("BTC-USDT BINANCE Perpetual/USDT Crypto".to_string())
+            .dir(dir)
+            .quantity(quantity)
+            .order_type(order_type)
+            .build()
+            .unwrap()
+    }
+
+    fn limit(limit_price: Decimal) -> OrderType {
+        OrderType::Limit(LimitOrderType { limit_price, post_only: false })
+    }
+
+    #[test]
+    fn test_validate_place_order() {
+        let info = info();
+        assert_eq!(
+            validate_place_order(&info, &order(Dir::Buy, dec!(1), limit(dec!(99.99)))),
+            Ok(())
+        );
+        assert_eq!(
+            validate_place_order(&info, &order(Dir::Buy, dec!(1), limit(dec!(100.01)))),
+            Err(OrderValidationError::PriceOffTick {
+                field: "limit_price",
+                price: dec!(100.01)
+            })
+        );
+        assert_eq!(
+            validate_place_order(&info, &order(Dir::Buy, dec!(1.0005), limit(dec!(50)))),
+            Err(OrderValidationError::QuantityOffStep {
+                quantity: dec!(1.0005),
+                step_size: dec!(0.001)
+            })
+        );
+        // min quantity is 10 USDT of notional
+        assert_eq!(
+            validate_place_order(&info, &order(Dir::Buy, dec!(0.1), limit(dec!(50)))),
+            Err(OrderValidationError::BelowMinQuantity {
+                quantity: dec!(0.1),
+                min_quantity: dec!(0.2)
+            })
+        );
+        assert_eq!(
+            validate_place_order(&info, &order(Dir::Buy, dec!(0.1), OrderType::Market)),
+            Ok(())
+        );
+        let delisted = ExecutionInfo { is_delisted: true, ..info };
+        assert_eq!(
+            validate_place_order(&delisted, &order(Dir::Buy, dec!(1), OrderType::Market)),
+            Err(OrderValidationError::Delisted)
+        );
+    }
+
+    #[test]
+    fn test_normalize_place_order() {
+        let info = info();
+        let stop = OrderType::StopLossLimit(TriggerLimitOrderType {
+            limit_price: dec!(100.07),
+            trigger_price: dec!(100.12),
+        });
+        let mut o = order(Dir::Sell, dec!(1.2345), stop);
+        normalize_place_order(&info, &mut o, PriceRounding::Passive).unwrap();
+        assert_eq!(o.quantity, dec!(1.234));
+        assert_eq!(o.order_type.limit_price(), Some(dec!(100.10)));
+        assert_eq!(o.order_type.trigger_price(), Some(dec!(100.15)));
+
+        let mut o = order(Dir::Sell, dec!(1), limit(dec!(100.07)));
+        normalize_place_order(&info, &mut o, PriceRounding::Aggressive).unwrap();
+        assert_eq!(o.order_type.limit_price(), Some(dec!(100.05)));
+
+        let mut modify = ModifyOrderRequest {
+            modify_id: None,
+            order_id: OrderId::nil(1),
+            new_quantity: Some(dec!(0.0999)),
+            new_price: Some(dec!(99.994)),
+        };
+        assert_eq!(
+            normalize_modify_order(&info, &mut modify, Dir::Buy, PriceRounding::Passive),
+            Err(OrderValidationError::BelowMinQuantity {
+                quantity: dec!(0.099),
+                min_quantity: dec!(10) / dec!(99.99)
+            })
+        );
+        assert_eq!(modify.new_price, Some(dec!(99.99)));
+    }
+}
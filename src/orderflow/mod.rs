@@ -8,6 +8,7 @@ pub mod fill;
 pub mod modify;
 pub mod order;
 pub mod order_id;
+pub mod order_tracker;
 pub mod order_types;
 
 pub use cancel::*;
@@ -15,6 +16,7 @@ pub use fill::*;
 pub use modify::*;
 pub use order::*;
 pub use order_id::*;
+pub use order_tracker::*;
 pub use order_types::*;
 
 #[grpc(package = "json.architect")]
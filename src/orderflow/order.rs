@@ -240,6 +240,28 @@ impl OrderStatus {
     pub fn is_dead(&self) -> bool {
         !self.is_alive()
     }
+
+    /// Whether an order in this status may legally move to `next`.
+    ///
+    /// Dead statuses are terminal; re-entering the current status is
+    /// always allowed so that duplicate messages are harmless.
+    pub fn can_transition_to(&self, next: OrderStatus) -> bool {
+        if *self == next {
+            return true;
+        }
+        match self {
+            Self::Unknown => true,
+            Self::Pending => !matches!(next, Self::Unknown),
+            Self::Open | Self::Canceling | Self::Stale => {
+                !matches!(next, Self::Pending | Self::Rejected | Self::Unknown)
+            }
+            Self::Out
+            | Self::Canceled
+            | Self::Rejected
+            | Self::ReconciledOut
+            | Self::ModifiedOut => false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
@@ -0,0 +1,428 @@
+//! Client-side order state, maintained by folding [`Orderflow`] events
+//! into a keyed set of [`Order`]s.
+
+use super::{
+    Fill, FillKind, Modify, Order, OrderAck, OrderModified, OrderReject, OrderStatus,
+    Orderflow,
+};
+use crate::OrderId;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, BTreeSet};
+use uuid::Uuid;
+
+/// Errors raised while applying orderflow to an [`OrderTracker`].
+///
+/// On any error the tracked state is left untouched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderTrackerError {
+    UnknownOrder(OrderId),
+    DuplicateOrder(OrderId),
+    /// The event refers to no order, e.g. a fill without an order id.
+    MissingOrderId,
+    IllegalTransition {
+        order_id: OrderId,
+        from: OrderStatus,
+        to: OrderStatus,
+    },
+    DuplicateFill(Uuid),
+    /// Applying the fill would leave the order's filled quantity
+    /// outside of `[0, quantity]`.
+    Overfill {
+        order_id: OrderId,
+        quantity: Decimal,
+        filled_quantity: Decimal,
+    },
+}
+
+impl std::fmt::Display for OrderTrackerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownOrder(order_id) => write!(f, "unknown order {order_id}"),
+            Self::DuplicateOrder(order_id) => write!(f, "duplicate order {order_id}"),
+            Self::MissingOrderId => write!(f, "orderflow event has no order id"),
+            Self::IllegalTransition { order_id, from, to } => {
+                write!(f, "order {order_id}: illegal transition {from} -> {to}")
+            }
+            Self::DuplicateFill(fill_id) => write!(f, "duplicate fill {fill_id}"),
+            Self::Overfill { order_id, quantity, filled_quantity } => write!(
+                f,
+                "order {order_id}: filled quantity {filled_quantity} out of range for quantity {quantity}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OrderTrackerError {}
+
+#[derive(Debug, Default, Clone)]
+pub struct OrderTracker {
+    orders: BTreeMap<OrderId, Order>,
+    pending_modifies: BTreeMap<Uuid, Modify>,
+    /// new order id -> the order id it replaced
+    modified_from: BTreeMap<OrderId, OrderId>,
+    /// order id -> the order id that replaced it
+    modified_to: BTreeMap<OrderId, OrderId>,
+    fill_ids: BTreeSet<Uuid>,
+}
+
+impl OrderTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, order_id: &OrderId) -> Option<&Order> {
+        self.orders.get(order_id)
+    }
+
+    pub fn orders(&self) -> impl Iterator<Item = &Order> {
+        self.orders.values()
+    }
+
+    pub fn open_orders(&self) -> impl Iterator<Item = &Order> {
+        self.orders.values().filter(|o| o.status.is_alive())
+    }
+
+    /// Start tracking an order that already exists, e.g. from an
+    /// `OpenOrdersResponse`, without validating its status.
+    pub fn insert(&mut self, order: Order) -> Option<Order> {
+        self.orders.insert(order.id, order)
+    }
+
+    /// Stop tracking dead orders, returning them.
+    pub fn remove_dead(&mut self) -> Vec<Order> {
+        let dead: Vec<OrderId> =
+            self.orders.values().filter(|o| o.status.is_dead()).map(|o| o.id).collect();
+        dead.into_iter().filter_map(|id| self.orders.remove(&id)).collect()
+    }
+
+    /// Follow modifies backwards to the order id that started the chain.
+    pub fn original_order_id(&self, order_id: OrderId) -> OrderId {
+        let mut id = order_id;
+        while let Some(prev) = self.modified_from.get(&id) {
+            id = *prev;
+        }
+        id
+    }
+
+    /// Follow modifies forwards to the order id currently representing
+    /// the order.
+    pub fn latest_order_id(&self, order_id: OrderId) -> OrderId {
+        let mut id = order_id;
+        while let Some(next) = self.modified_to.get(&id) {
+            id = *next;
+        }
+        id
+    }
+
+    pub fn apply(&mut self, orderflow: &Orderflow) -> Result<(), OrderTrackerError> {
+        match orderflow {
+            Orderflow::OrderPending(order) => {
+                if self.orders.contains_key(&order.id) {
+                    return Err(OrderTrackerError::DuplicateOrder(order.id));
+                }
+                self.orders.insert(order.id, order.clone());
+                Ok(())
+            }
+            Orderflow::OrderAck(OrderAck { order_id, exchange_order_id }) => {
+                let order = self.transition(*order_id, OrderStatus::Open)?;
+                if exchange_order_id.is_some() {
+                    order.exchange_order_id.clone_from(exchange_order_id);
+                }
+                Ok(())
+            }
+            Orderflow::OrderReject(OrderReject { order_id, reason, message }) => {
+                let order = self.transition(*order_id, OrderStatus::Rejected)?;
+                order.reject_reason = Some(*reason);
+                order.reject_message.clone_from(message);
+                Ok(())
+            }
+            Orderflow::OrderOut(out) => {
+                self.transition(out.order_id, OrderStatus::Out).map(|_| ())
+            }
+            Orderflow::OrderReconciledOut(out) => {
+                self.transition(out.order_id, OrderStatus::ReconciledOut).map(|_| ())
+            }
+            Orderflow::OrderStale(stale) => {
+                self.transition(stale.order_id, OrderStatus::Stale).map(|_| ())
+            }
+            Orderflow::CancelPending(cancel) => {
+                self.get_order(cancel.order_id).map(|_| ())
+            }
+            Orderflow::CancelReject(reject) => {
+                // a rejected cancel returns a canceling order to the book
+                let order = self.get_order(reject.order_id)?;
+                if order.status == OrderStatus::Canceling {
+                    self.transition(reject.order_id, OrderStatus::Open)?;
+                }
+                Ok(())
+            }
+            Orderflow::OrderCanceling(canceling) => {
+                self.transition(canceling.order_id, OrderStatus::Canceling).map(|_| ())
+            }
+            Orderflow::OrderCanceled(canceled) => {
+                self.transition(canceled.order_id, OrderStatus::Canceled).map(|_| ())
+            }
+            Orderflow::ModifyPending(modify) => {
+                self.get_order(modify.order_id)?;
+                self.pending_modifies.insert(modify.modify_id, modify.clone());
+                Ok(())
+            }
+            Orderflow::ModifyReject(reject) => {
+                self.pending_modifies.remove(&reject.modify_id);
+                Ok(())
+            }
+            Orderflow::OrderModified(modified) => self.apply_modified(modified),
+            Orderflow::Fill(fill) => self.apply_fill(fill),
+            // Descendant fills are copies of fills on child orders, keyed to the
+            // child algo; aberrant fills don't carry enough detail to apply.
+            Orderflow::DescendantFill(_) | Orderflow::AberrantFill(_) => Ok(()),
+        }
+    }
+
+    fn get_order(&self, order_id: OrderId) -> Result<&Order, OrderTrackerError> {
+        self.orders.get(&order_id).ok_or(OrderTrackerError::UnknownOrder(order_id))
+    }
+
+    fn transition(
+        &mut self,
+        order_id: OrderId,
+        to: OrderStatus,
+    ) -> Result<&mut Order, OrderTrackerError> {
+        let order = self
+            .orders
+            .get_mut(&order_id)
+            .ok_or(OrderTrackerError::UnknownOrder(order_id))?;
+        if !order.status.can_transition_to(to) {
+            return Err(OrderTrackerError::IllegalTransition {
+                order_id,
+                from: order.status,
+                to,
+            });
+        }
+        order.status = to;
+        Ok(order)
+    }
+
+    /// The replaced order goes to `ModifiedOut` and the replacement is
+    /// tracked under its new id as an open order.
+    fn apply_modified(
+        &mut self,
+        modified: &OrderModified,
+    ) -> Result<(), OrderTrackerError> {
+        let OrderModified { order_id, new_order_id, modify_id } = *modified;
+        if self.orders.contains_key(&new_order_id) {
+            return Err(OrderTrackerError::DuplicateOrder(new_order_id));
+        }
+        let order = self.get_order(order_id)?;
+        if !order.status.can_transition_to(OrderStatus::ModifiedOut) {
+            return Err(OrderTrackerError::IllegalTransition {
+                order_id,
+                from: order.status,
+                to: OrderStatus::ModifiedOut,
+            });
+        }
+        let mut new_order = match self.pending_modifies.get(&modify_id) {
+            Some(modify) => {
+                modify.modify(order.clone()).unwrap_or_else(|_| order.clone())
+            }
+            None => order.clone(),
+        };
+        new_order.id = new_order_id;
+        new_order.status = OrderStatus::Open;
+        self.pending_modifies.remove(&modify_id);
+        self.transition(order_id, OrderStatus::ModifiedOut)?;
+        self.orders.insert(new_order_id, new_order);
+        self.modified_from.insert(new_order_id, order_id);
+        self.modified_to.insert(order_id, new_order_id);
+        Ok(())
+    }
+
+    fn apply_fill(&mut self, fill: &Fill) -> Result<(), OrderTrackerError> {
+        let order_id = fill.order_id.ok_or(OrderTrackerError::MissingOrderId)?;
+        if self.fill_ids.contains(&fill.fill_id) {
+            return Err(OrderTrackerError::DuplicateFill(fill.fill_id));
+        }
+        let order = self
+            .orders
+            .get_mut(&order_id)
+            .ok_or(OrderTrackerError::UnknownOrder(order_id))?;
+        let prev_qty = order.filled_quantity;
+        let prev_notional = order.average_fill_price.unwrap_or_default() * prev_qty;
+        // A correction follows a reversal of the fill it corrects,
+        // so it applies like a normal fill.
+        let (filled_quantity, notional) = match fill.fill_kind {
+            FillKind::Normal | FillKind::Correction => {
+                (prev_qty + fill.quantity, prev_notional + fill.quantity * fill.price)
+            }
+            FillKind::Reversal => {
+                (prev_qty - fill.quantity, prev_notional - fill.quantity * fill.price)
+            }
+        };
+        if filled_quantity < Decimal::ZERO || filled_quantity > order.quantity {
+            return Err(OrderTrackerError::Overfill {
+                order_id,
+                quantity: order.quantity,
+                filled_quantity,
+            });
+        }
+        order.filled_quantity = filled_quantity;
+        order.average_fill_price = if filled_quantity.is_zero() {
+            None
+        } else {
+            Some(notional / filled_quantity)
+        };
+        self.fill_ids.insert(fill.fill_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        orderflow::{LimitOrderType, OrderCanceled, OrderSource, OrderType, TimeInForce},
+        AccountId, Dir, UserId,
+    };
+    use rust_decimal_macros::dec;
+
+    fn order(id: u64) -> Order {
+        Order {
+            id: OrderId::nil(id),
+            parent_id: None,
+            exchange_order_id: None,
+            recv_time: 0,
+            recv_time_ns: 0,
+            status: OrderStatus::Pending,
+            reject_reason: None,
+            reject_message: None,
+            symbol: "BTC Crypto/USD".parse().unwrap(),
+            trader: UserId::anonymous(),
+            account: AccountId::nil(),
+            dir: Dir::Buy,
+            quantity: dec!(10),
+            filled_quantity: dec!(0),
+            average_fill_price: None,
+            order_type: OrderType::Limit(LimitOrderType {
+                limit_price: dec!(100),
+                post_only: false,
+            }),
+            time_in_force: TimeInForce::GoodTilCancel,
+            source: OrderSource::API,
+            execution_venue: "BINANCE".into(),
+            is_short_sale: None,
+        }
+    }
+
+    fn fill(id: u64, kind: FillKind, quantity: Decimal, price: Decimal) -> Orderflow {
+        Orderflow::Fill(Fill {
+            fill_id: Uuid::new_v4(),
+            fill_kind: kind,
+            execution_venue: "BINANCE".into(),
+            exchange_fill_id: None,
+            order_id: Some(OrderId::nil(id)),
+            trader: None,
+            account: None,
+            symbol: "BTC Crypto/USD".parse().unwrap(),
+            dir: Dir::Buy,
+            quantity,
+            price,
+            is_taker: None,
+            fee: None,
+            fee_currency: None,
+            recv_time: None,
+            recv_time_ns: None,
+            trade_time: 0,
+            trade_time_ns: 0,
+        })
+    }
+
+    #[test]
+    fn test_order_lifecycle() {
+        let mut tracker = OrderTracker::new();
+        tracker.apply(&Orderflow::OrderPending(order(1))).unwrap();
+        tracker
+            .apply(&Orderflow::OrderAck(OrderAck {
+                order_id: OrderId::nil(1),
+                exchange_order_id: Some("abc".to_string()),
+            }))
+            .unwrap();
+        tracker.apply(&fill(1, FillKind::Normal, dec!(2), dec!(100))).unwrap();
+        tracker.apply(&fill(1, FillKind::Normal, dec!(2), dec!(99))).unwrap();
+        let o = tracker.get(&OrderId::nil(1)).unwrap();
+        assert_eq!(o.status, OrderStatus::Open);
+        assert_eq!(o.exchange_order_id.as_deref(), Some("abc"));
+        assert_eq!(o.filled_quantity, dec!(4));
+        assert_eq!(o.average_fill_price, Some(dec!(99.5)));
+
+        tracker.apply(&fill(1, FillKind::Reversal, dec!(2), dec!(99))).unwrap();
+        let o = tracker.get(&OrderId::nil(1)).unwrap();
+        assert_eq!(o.filled_quantity, dec!(2));
+        assert_eq!(o.average_fill_price, Some(dec!(100)));
+        assert!(matches!(
+            tracker.apply(&fill(1, FillKind::Normal, dec!(9), dec!(99))),
+            Err(OrderTrackerError::Overfill { .. })
+        ));
+
+        let canceled = Orderflow::OrderCanceled(OrderCanceled {
+            order_id: OrderId::nil(1),
+            cancel_id: None,
+        });
+        tracker.apply(&canceled).unwrap();
+        assert_eq!(
+            tracker.apply(&Orderflow::OrderAck(OrderAck {
+                order_id: OrderId::nil(1),
+                exchange_order_id: None,
+            })),
+            Err(OrderTrackerError::IllegalTransition {
+                order_id: OrderId::nil(1),
+                from: OrderStatus::Canceled,
+                to: OrderStatus::Open,
+            })
+        );
+    }
+
+    #[test]
+    fn test_modify_chain() {
+        let mut tracker = OrderTracker::new();
+        tracker.apply(&Orderflow::OrderPending(order(1))).unwrap();
+        tracker
+            .apply(&Orderflow::OrderAck(OrderAck {
+                order_id: OrderId::nil(1),
+                exchange_order_id: None,
+            }))
+            .unwrap();
+        for (from, to) in [(1, 2), (2, 3)] {
+            let modify_id = Uuid::new_v4();
+            tracker
+                .apply(&Orderflow::ModifyPending(Modify {
+                    modify_id,
+                    order_id: OrderId::nil(from),
+                    new_order_id: OrderId::nil(to),
+                    new_price: Some(dec!(101)),
+                    new_quantity: None,
+                    recv_time: 0,
+                    recv_time_ns: 0,
+                    status: crate::orderflow::ModifyStatus::Pending,
+                    reject_reason: None,
+                }))
+                .unwrap();
+            tracker
+                .apply(&Orderflow::OrderModified(OrderModified {
+                    order_id: OrderId::nil(from),
+                    new_order_id: OrderId::nil(to),
+                    modify_id,
+                }))
+                .unwrap();
+        }
+        assert_eq!(
+            tracker.get(&OrderId::nil(1)).unwrap().status,
+            OrderStatus::ModifiedOut
+        );
+        let latest = tracker.get(&OrderId::nil(3)).unwrap();
+        assert_eq!(latest.status, OrderStatus::Open);
+        assert_eq!(latest.order_type.limit_price(), Some(dec!(101)));
+        assert_eq!(tracker.original_order_id(OrderId::nil(3)), OrderId::nil(1));
+        assert_eq!(tracker.latest_order_id(OrderId::nil(1)), OrderId::nil(3));
+        assert_eq!(tracker.open_orders().count(), 1);
+    }
+}
@@ -99,3 +99,43 @@ impl BracketOrderType {
         self.stop_loss.is_some()
     }
 }
+
+/// Order types for multi-leg options strategy orders; prices are the net
+/// price of one unit of the strategy, positive for a debit, negative for
+/// a credit.
+#[derive(
+    Debug, Clone, Copy, IntoStaticStr, Serialize, Deserialize, PartialEq, Eq, JsonSchema,
+)]
+#[serde(tag = "k", rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum OptionsStrategyOrderType {
+    Market,
+    NetLimit(NetLimitOrderType),
+}
+
+impl OptionsStrategyOrderType {
+    pub fn limit_price(&self) -> Option<Decimal> {
+        match self {
+            OptionsStrategyOrderType::NetLimit(net_limit) => Some(net_limit.limit_price),
+            OptionsStrategyOrderType::Market => None,
+        }
+    }
+
+    pub fn post_only(&self) -> Option<bool> {
+        match self {
+            OptionsStrategyOrderType::NetLimit(net_limit) => Some(net_limit.post_only),
+            OptionsStrategyOrderType::Market => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[cfg_attr(feature = "juniper", derive(juniper::GraphQLObject))]
+pub struct NetLimitOrderType {
+    #[serde(rename = "p")]
+    #[schemars(title = "limit_price")]
+    pub limit_price: Decimal,
+    #[serde(rename = "po")]
+    #[schemars(title = "post_only")]
+    pub post_only: bool,
+}
@@ -0,0 +1,618 @@
+//! Differences between two [`SymbologySnapshot`]s, e.g. to review an
+//! [`UploadSymbologyRequest`](super::protocol::UploadSymbologyRequest) or
+//! the effect of pruning expired symbols before committing to it.
+//!
+//! [`diff_symbology`] produces both a [`SymbologyUpdate`] that takes the old
+//! snapshot to the new one and a list of [`SymbologyDiffEntry`]s whose
+//! `Display` forms a human-readable report.
+
+use super::{
+    protocol::{SnapshotOrUpdate, SymbologySnapshot, SymbologyUpdate},
+    AliasKind, ExecutionInfo, ExecutionVenue, OptionsSeries, OptionsSeriesInfo, Product,
+    TickSize, TradableProduct,
+};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.old, self.new)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymbologyDiffEntry {
+    ProductAdded(Product),
+    ProductRemoved(Product),
+    ProductChanged(Product),
+    ExecutionInfoAdded {
+        symbol: TradableProduct,
+        venue: ExecutionVenue,
+    },
+    ExecutionInfoRemoved {
+        symbol: TradableProduct,
+        venue: ExecutionVenue,
+    },
+    ExecutionInfoChanged {
+        symbol: TradableProduct,
+        venue: ExecutionVenue,
+        fields: Vec<FieldChange>,
+    },
+    AliasAdded {
+        kind: AliasKind,
+        alias: String,
+        product: Product,
+    },
+    AliasRemoved {
+        kind: AliasKind,
+        alias: String,
+        product: Product,
+    },
+    AliasChanged {
+        kind: AliasKind,
+        alias: String,
+        old: Product,
+        new: Product,
+    },
+    OptionsSeriesAdded(OptionsSeries),
+    OptionsSeriesRemoved(OptionsSeries),
+    OptionsSeriesChanged {
+        series: OptionsSeries,
+        added_strikes: BTreeMap<NaiveDate, BTreeSet<Decimal>>,
+        removed_strikes: BTreeMap<NaiveDate, BTreeSet<Decimal>>,
+        /// Fields other than strikes that changed
+        fields: Vec<FieldChange>,
+    },
+    ProductCatalogAdded {
+        venue: ExecutionVenue,
+        exchange_product: String,
+    },
+    ProductCatalogRemoved {
+        venue: ExecutionVenue,
+        exchange_product: String,
+    },
+    ProductCatalogChanged {
+        venue: ExecutionVenue,
+        exchange_product: String,
+    },
+}
+
+impl fmt::Display for SymbologyDiffEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ProductAdded(product) => write!(f, "+ product {product}"),
+            Self::ProductRemoved(product) => write!(f, "- product {product}"),
+            Self::ProductChanged(product) => write!(f, "~ product {product}"),
+            Self::ExecutionInfoAdded { symbol, venue } => {
+                write!(f, "+ execution info {symbol} @ {venue}")
+            }
+            Self::ExecutionInfoRemoved { symbol, venue } => {
+                write!(f, "- execution info {symbol} @ {venue}")
+            }
+            Self::ExecutionInfoChanged { symbol, venue, fields } => {
+                write!(f, "~ execution info {symbol} @ {venue}")?;
+                for field in fields {
+                    write!(f, "\n    {field}")?;
+                }
+                Ok(())
+            }
+            Self::AliasAdded { kind, alias, product } => {
+                write!(f, "+ alias {} {alias} => {product}", alias_kind_str(kind))
+            }
+            Self::AliasRemoved { kind, alias, product } => {
+                write!(f, "- alias {} {alias} => {product}", alias_kind_str(kind))
+            }
+            Self::AliasChanged { kind, alias, old, new } => {
+                write!(f, "~ alias {} {alias}: {old} -> {new}", alias_kind_str(kind))
+            }
+            Self::OptionsSeriesAdded(series) => write!(f, "+ options series {series}"),
+            Self::OptionsSeriesRemoved(series) => {
+                write!(f, "- options series {series}")
+            }
+            Self::OptionsSeriesChanged {
+                series,
+                added_strikes,
+                removed_strikes,
+                fields,
+            } => {
+                write!(f, "~ options series {series}")?;
+                for field in fields {
+                    write!(f, "\n    {field}")?;
+                }
+                for (sign, strikes) in [("+", added_strikes), ("-", removed_strikes)] {
+                    for (expiration, strikes) in strikes {
+                        let strikes: Vec<_> =
+                            strikes.iter().map(|s| s.to_string()).collect();
+                        write!(
+                            f,
+                            "\n    {sign} {} strikes {}",
+                            expiration.format("%Y%m%d"),
+                            strikes.join(", ")
+                        )?;
+                    }
+                }
+                Ok(())
+            }
+            Self::ProductCatalogAdded { venue, exchange_product } => {
+                write!(f, "+ catalog {venue} {exchange_product}")
+            }
+            Self::ProductCatalogRemoved { venue, exchange_product } => {
+                write!(f, "- catalog {venue} {exchange_product}")
+            }
+            Self::ProductCatalogChanged { venue, exchange_product } => {
+                write!(f, "~ catalog {venue} {exchange_product}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SymbologyDiff {
+    /// Applying this to the old snapshot yields the new one
+    pub update: SymbologyUpdate,
+    pub entries: Vec<SymbologyDiffEntry>,
+}
+
+impl SymbologyDiff {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// The report: one line per entry, followed by indented field changes.
+impl fmt::Display for SymbologyDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.entries.is_empty() {
+            return writeln!(f, "no changes");
+        }
+        for entry in &self.entries {
+            writeln!(f, "{entry}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare two snapshots; the resulting update carries the new snapshot's
+/// sequence and only the keys that differ.
+pub fn diff_symbology(old: &SymbologySnapshot, new: &SymbologySnapshot) -> SymbologyDiff {
+    let mut entries = vec![];
+    // products
+    let products = diff_map(&old.products, &new.products);
+    for (product, info) in &products {
+        entries.push(match (old.products.contains_key(product), info) {
+            (false, _) => SymbologyDiffEntry::ProductAdded(product.clone()),
+            (true, None) => SymbologyDiffEntry::ProductRemoved(product.clone()),
+            (true, Some(_)) => SymbologyDiffEntry::ProductChanged(product.clone()),
+        });
+    }
+    // execution info
+    let execution_info = diff_map2(&old.execution_info, &new.execution_info);
+    for (symbol, old_infos, new_infos) in
+        union_keys(&old.execution_info, &new.execution_info)
+    {
+        let empty = BTreeMap::new();
+        let old_infos = old_infos.unwrap_or(&empty);
+        let new_infos = new_infos.unwrap_or(&empty);
+        for (venue, old_info, new_info) in union_keys(old_infos, new_infos) {
+            let (symbol, venue) = (symbol.clone(), venue.clone());
+            match (old_info, new_info) {
+                (None, Some(_)) => {
+                    entries.push(SymbologyDiffEntry::ExecutionInfoAdded { symbol, venue })
+                }
+                (Some(_), None) => entries
+                    .push(SymbologyDiffEntry::ExecutionInfoRemoved { symbol, venue }),
+                (Some(old_info), Some(new_info)) => {
+                    let fields = execution_info_changes(old_info, new_info);
+                    if !fields.is_empty() {
+                        entries.push(SymbologyDiffEntry::ExecutionInfoChanged {
+                            symbol,
+                            venue,
+                            fields,
+                        });
+                    }
+                }
+                (None, None) => {}
+            }
+        }
+    }
+    // aliases
+    let product_aliases = diff_map2(&old.product_aliases, &new.product_aliases);
+    for (kind, old_aliases, new_aliases) in
+        union_keys(&old.product_aliases, &new.product_aliases)
+    {
+        let empty = BTreeMap::new();
+        let old_aliases = old_aliases.unwrap_or(&empty);
+        let new_aliases = new_aliases.unwrap_or(&empty);
+        for (alias, old_product, new_product) in union_keys(old_aliases, new_aliases) {
+            let (kind, alias) = (*kind, alias.clone());
+            match (old_product, new_product) {
+                (None, Some(product)) => entries.push(SymbologyDiffEntry::AliasAdded {
+                    kind,
+                    alias,
+                    product: product.clone(),
+                }),
+                (Some(product), None) => entries.push(SymbologyDiffEntry::AliasRemoved {
+                    kind,
+                    alias,
+                    product: product.clone(),
+                }),
+                (Some(old), Some(new)) if old != new => {
+                    entries.push(SymbologyDiffEntry::AliasChanged {
+                        kind,
+                        alias,
+                        old: old.clone(),
+                        new: new.clone(),
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+    // options series
+    let options_series = diff_map(&old.options_series, &new.options_series);
+    for (series, info) in &options_series {
+        match (old.options_series.get(series), info) {
+            (None, _) => {
+                entries.push(SymbologyDiffEntry::OptionsSeriesAdded(series.clone()))
+            }
+            (Some(_), None) => {
+                entries.push(SymbologyDiffEntry::OptionsSeriesRemoved(series.clone()))
+            }
+            (Some(old_info), Some(new_info)) => {
+                entries.push(options_series_changes(old_info, new_info))
+            }
+        }
+    }
+    // product catalog
+    let product_catalog = diff_map2(&old.product_catalog, &new.product_catalog);
+    for (venue, old_catalog, new_catalog) in
+        union_keys(&old.product_catalog, &new.product_catalog)
+    {
+        let empty = BTreeMap::new();
+        let old_catalog = old_catalog.unwrap_or(&empty);
+        let new_catalog = new_catalog.unwrap_or(&empty);
+        for (exchange_product, old_info, new_info) in union_keys(old_catalog, new_catalog)
+        {
+            let (venue, exchange_product) = (venue.clone(), exchange_product.clone());
+            match (old_info, new_info) {
+                (None, Some(_)) => {
+                    entries.push(SymbologyDiffEntry::ProductCatalogAdded {
+                        venue,
+                        exchange_product,
+                    })
+                }
+                (Some(_), None) => {
+                    entries.push(SymbologyDiffEntry::ProductCatalogRemoved {
+                        venue,
+                        exchange_product,
+                    })
+                }
+                (Some(old_info), Some(new_info)) if !same_value(old_info, new_info) => {
+                    entries.push(SymbologyDiffEntry::ProductCatalogChanged {
+                        venue,
+                        exchange_product,
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+    let update = SymbologyUpdate {
+        sequence: new.sequence,
+        products: non_empty(products),
+        product_aliases: non_empty(product_aliases),
+        product_catalog: non_empty(product_catalog),
+        options_series: non_empty(options_series),
+        execution_info: non_empty(execution_info),
+    };
+    SymbologyDiff { update, entries }
+}
+
+fn execution_info_changes(old: &ExecutionInfo, new: &ExecutionInfo) -> Vec<FieldChange> {
+    let mut fields = vec![];
+    let mut check = |field: &str, old: String, new: String| {
+        if old != new {
+            fields.push(FieldChange { field: field.to_string(), old, new });
+        }
+    };
+    check(
+        "exchange_symbol",
+        opt_str(&old.exchange_symbol),
+        opt_str(&new.exchange_symbol),
+    );
+    check("tick_size", tick_size_str(&old.tick_size), tick_size_str(&new.tick_size));
+    check("step_size", old.step_size.to_string(), new.step_size.to_string());
+    check(
+        "min_order_quantity",
+        old.min_order_quantity.to_string(),
+        new.min_order_quantity.to_string(),
+    );
+    check(
+        "min_order_quantity_unit",
+        old.min_order_quantity_unit.to_string(),
+        new.min_order_quantity_unit.to_string(),
+    );
+    check("is_delisted", old.is_delisted.to_string(), new.is_delisted.to_string());
+    check("initial_margin", opt_str(&old.initial_margin), opt_str(&new.initial_margin));
+    check(
+        "maintenance_margin",
+        opt_str(&old.maintenance_margin),
+        opt_str(&new.maintenance_margin),
+    );
+    fields
+}
+
+fn options_series_changes(
+    old: &OptionsSeriesInfo,
+    new: &OptionsSeriesInfo,
+) -> SymbologyDiffEntry {
+    let mut added_strikes = BTreeMap::new();
+    let mut removed_strikes = BTreeMap::new();
+    for (expiration, old_strikes, new_strikes) in
+        union_keys(&old.strikes_by_expiration, &new.strikes_by_expiration)
+    {
+        let empty = BTreeSet::new();
+        let old_strikes = old_strikes.unwrap_or(&empty);
+        let new_strikes = new_strikes.unwrap_or(&empty);
+        let added: BTreeSet<_> = new_strikes.difference(old_strikes).copied().collect();
+        let removed: BTreeSet<_> = old_strikes.difference(new_strikes).copied().collect();
+        if !added.is_empty() {
+            added_strikes.insert(*expiration, added);
+        }
+        if !removed.is_empty() {
+            removed_strikes.insert(*expiration, removed);
+        }
+    }
+    // compare everything else by clearing the strikes
+    let mut fields = vec![];
+    let mut old_rest = old.clone();
+    let mut new_rest = new.clone();
+    old_rest.strikes_by_expiration.clear();
+    new_rest.strikes_by_expiration.clear();
+    if let (
+        Ok(serde_json::Value::Object(old_rest)),
+        Ok(serde_json::Value::Object(new_rest)),
+    ) = (serde_json::to_value(&old_rest), serde_json::to_value(&new_rest))
+    {
+        for (field, old_value) in old_rest {
+            let new_value =
+                new_rest.get(&field).cloned().unwrap_or(serde_json::Value::Null);
+            if old_value != new_value {
+                fields.push(FieldChange {
+                    field,
+                    old: old_value.to_string(),
+                    new: new_value.to_string(),
+                });
+            }
+        }
+    }
+    SymbologyDiffEntry::OptionsSeriesChanged {
+        series: new.options_series.clone(),
+        added_strikes,
+        removed_strikes,
+        fields,
+    }
+}
+
+fn tick_size_str(tick_size: &TickSize) -> String {
+    match tick_size {
+        TickSize::Simple(tick) => tick.to_string(),
+        TickSize::Varying { thresholds } => {
+            let thresholds: Vec<_> = thresholds
+                .iter()
+                .map(|(threshold, tick)| format!("{tick} from {threshold}"))
+                .collect();
+            format!("[{}]", thresholds.join(", "))
+        }
+    }
+}
+
+fn opt_str<T: fmt::Display>(value: &Option<T>) -> String {
+    value.as_ref().map(|v| v.to_string()).unwrap_or_else(|| "none".to_string())
+}
+
+fn alias_kind_str(kind: &AliasKind) -> &'static str {
+    kind.into()
+}
+
+/// Compare values by their serialized form, for types without PartialEq.
+pub(crate) fn same_value<T: Serialize>(a: &T, b: &T) -> bool {
+    match (serde_json::to_value(a), serde_json::to_value(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// (key, old value, new value) for every key in either map
+fn union_keys<'a, K: Ord, V>(
+    old: &'a BTreeMap<K, V>,
+    new: &'a BTreeMap<K, V>,
+) -> Vec<(&'a K, Option<&'a V>, Option<&'a V>)> {
+    let keys: BTreeSet<&K> = old.keys().chain(new.keys()).collect();
+    keys.into_iter().map(|k| (k, old.get(k), new.get(k))).collect()
+}
+
+fn diff_map<K: Ord + Clone, V: Clone + Serialize>(
+    old: &BTreeMap<K, V>,
+    new: &BTreeMap<K, V>,
+) -> Vec<(K, Option<V>)> {
+    let mut updates = vec![];
+    for (k, old_value, new_value) in union_keys(old, new) {
+        match (old_value, new_value) {
+            (Some(_), None) => updates.push((k.clone(), None)),
+            (None, Some(v)) => updates.push((k.clone(), Some(v.clone()))),
+            (Some(a), Some(b)) if !same_value(a, b) => {
+                updates.push((k.clone(), Some(b.clone())))
+            }
+            _ => {}
+        }
+    }
+    updates
+}
+
+fn diff_map2<K0: Ord + Clone, K1: Ord + Clone, V: Clone + Serialize>(
+    old: &BTreeMap<K0, BTreeMap<K1, V>>,
+    new: &BTreeMap<K0, BTreeMap<K1, V>>,
+) -> Vec<(K0, Option<SnapshotOrUpdate<K1, V>>)> {
+    let mut updates = vec![];
+    for (k, old_map, new_map) in union_keys(old, new) {
+        match (old_map, new_map) {
+            (Some(_), None) => updates.push((k.clone(), None)),
+            (None, Some(m)) => updates.push((
+                k.clone(),
+                Some(SnapshotOrUpdate::Snapshot { snapshot: m.clone() }),
+            )),
+            (Some(a), Some(b)) => {
+                let inner = diff_map(a, b);
+                if !inner.is_empty() {
+                    updates.push((
+                        k.clone(),
+                        Some(SnapshotOrUpdate::Update { updates: inner }),
+                    ));
+                }
+            }
+            (None, None) => {}
+        }
+    }
+    updates
+}
+
+fn non_empty<K: Ord, V>(updates: Vec<(K, Option<V>)>) -> Option<SnapshotOrUpdate<K, V>> {
+    if updates.is_empty() {
+        None
+    } else {
+        Some(SnapshotOrUpdate::Update { updates })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbology::{
+        store::SymbologyStore, DerivativeKind, MinOrderQuantityUnit, OptionsExerciseType,
+        ProductInfo, ProductType,
+    };
+    use crate::SequenceIdAndNumber;
+    use chrono::{NaiveTime, TimeZone, Utc};
+    use rust_decimal_macros::dec;
+
+    fn future(root: &str) -> (Product, ProductInfo) {
+        let product = Product::future(
+            root,
+            NaiveDate::from_ymd_opt(2026, 3, 20).unwrap(),
+            Some("CME"),
+        )
+        .unwrap();
+        let info = ProductInfo {
+            product_type: ProductType::Future {
+                series: None,
+                underlying: None,
+                multiplier: dec!(50),
+                expiration: Utc.with_ymd_and_hms(2026, 3, 20, 14, 30, 0).unwrap(),
+                derivative_kind: DerivativeKind::Linear,
+                first_notice_date: None,
+            },
+            primary_venue: Some("CME".into()),
+            price_display_format: None,
+        };
+        (product, info)
+    }
+
+    fn execution_info(tick_size: Decimal) -> ExecutionInfo {
+        ExecutionInfo {
+            execution_venue: "CME".into(),
+            exchange_symbol: Some("ESH6".into()),
+            tick_size: TickSize::Simple(tick_size),
+            step_size: dec!(1),
+            min_order_quantity: dec!(1),
+            min_order_quantity_unit: MinOrderQuantityUnit::Base,
+            is_delisted: false,
+            initial_margin: Some(dec!(0.06)),
+            maintenance_margin: None,
+        }
+    }
+
+    fn options_series(strikes: &[Decimal]) -> OptionsSeriesInfo {
+        let expiration = NaiveDate::from_ymd_opt(2026, 3, 20).unwrap();
+        OptionsSeriesInfo {
+            options_series: "AAPL US Options".parse().unwrap(),
+            venue_discriminant: None,
+            quote_symbol: "USD".parse().unwrap(),
+            underlying: "AAPL US Equity".parse().unwrap(),
+            multiplier: dec!(100),
+            expiration_time_of_day: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+            expiration_time_zone: chrono_tz::America::New_York,
+            strikes_by_expiration: BTreeMap::from_iter([(
+                expiration,
+                strikes.iter().copied().collect(),
+            )]),
+            derivative_kind: DerivativeKind::Linear,
+            exercise_type: OptionsExerciseType::American,
+            is_cash_settled: false,
+        }
+    }
+
+    #[test]
+    fn test_diff_symbology() -> anyhow::Result<()> {
+        let (es, es_info) = future("ES");
+        let (nq, nq_info) = future("NQ");
+        let es_usd = TradableProduct::new(&es, None)?;
+        let series: OptionsSeries = "AAPL US Options".parse()?;
+        let mut old = SymbologySnapshot {
+            sequence: SequenceIdAndNumber::new(1, 5),
+            ..Default::default()
+        };
+        old.products.insert(es.clone(), es_info.clone());
+        old.execution_info
+            .entry(es_usd.clone())
+            .or_default()
+            .insert("CME".into(), execution_info(dec!(0.25)));
+        old.product_aliases
+            .entry(AliasKind::CmeGlobex)
+            .or_default()
+            .insert("ESH6".into(), es.clone());
+        old.options_series
+            .insert(series.clone(), options_series(&[dec!(200), dec!(205)]));
+        let mut new = old.clone();
+        new.sequence = SequenceIdAndNumber::new(1, 6);
+        new.products.insert(nq.clone(), nq_info);
+        new.execution_info
+            .get_mut(&es_usd)
+            .unwrap()
+            .insert("CME".into(), execution_info(dec!(0.5)));
+        new.product_aliases.get_mut(&AliasKind::CmeGlobex).unwrap().remove("ESH6");
+        new.options_series
+            .insert(series.clone(), options_series(&[dec!(205), dec!(210)]));
+        let diff = diff_symbology(&old, &new);
+        assert_eq!(
+            diff.to_string(),
+            "\
++ product NQ 20260320 CME Future
+~ execution info ES 20260320 CME Future @ CME
+    tick_size: 0.25 -> 0.5
+- alias CME_GLOBEX ESH6 => ES 20260320 CME Future
+~ options series AAPL US Options
+    + 20260320 strikes 210
+    - 20260320 strikes 200
+"
+        );
+        assert!(diff_symbology(&new, &new).is_empty());
+        // the update takes old to new
+        let mut store = SymbologyStore::from_snapshot(old);
+        store.apply_update(diff.update)?;
+        assert!(diff_symbology(store.snapshot(), &new).is_empty());
+        Ok(())
+    }
+}
@@ -49,9 +49,9 @@
 //! }
 //! ```
 
-use super::Product;
-use anyhow::{bail, Result};
-use chrono::{DateTime, NaiveTime, Utc};
+use super::{Product, TradableProduct};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use derive_more::{AsRef, Display};
 use rust_decimal::Decimal;
 use schemars::JsonSchema;
@@ -105,6 +105,15 @@ impl EventContractSeries {
         };
         Ok(Self(inner))
     }
+
+    /// The series name without its kind suffix, including any venue
+    /// discriminant, e.g. "2024 Presidential Election KALSHI".
+    pub fn stem(&self) -> &str {
+        self.0
+            .strip_suffix(" Event Contract Series")
+            .or_else(|| self.0.strip_suffix(" Event Contracts"))
+            .unwrap_or(&self.0)
+    }
 }
 
 impl FromStr for EventContractSeries {
@@ -128,19 +137,147 @@ pub struct EventContractSeriesInfo {
 }
 
 impl EventContractSeriesInfo {
-    pub fn get_product(&self, _instance: EventContractSeriesInstance) -> Result<Product> {
-        todo!()
+    /// E.g. "2024 Presidential Election KALSHI Trump Event Contract" or
+    /// "ECES 20241227 CME 20241227 4500 Event Contract"; option-like
+    /// instances without an expiration omit the date.
+    pub fn get_product(&self, instance: &EventContractSeriesInstance) -> Result<Product> {
+        self.check_instance(instance)?;
+        let stem = self.event_contract_series.stem();
+        let symbol = match instance {
+            EventContractSeriesInstance::Enumerated { outcome } => {
+                format!("{stem} {}", outcome.name)
+            }
+            EventContractSeriesInstance::OptionLike { strike, expiration } => {
+                match expiration {
+                    Some(expiration) => format!(
+                        "{stem} {} {}",
+                        self.local_date(*expiration).format("%Y%m%d"),
+                        strike.normalize()
+                    ),
+                    None => format!("{stem} {}", strike.normalize()),
+                }
+            }
+        };
+        Product::event_contract(&symbol)
     }
 
-    pub fn get_tradable_product(&self) -> Result<Product> {
-        todo!()
+    pub fn get_tradable_product(
+        &self,
+        instance: &EventContractSeriesInstance,
+    ) -> Result<TradableProduct> {
+        let base = self.get_product(instance)?;
+        TradableProduct::new(&base, Some(&self.quote_symbol))
     }
 
     pub fn parse_instance(
         &self,
-        _symbol: impl AsRef<str>,
+        symbol: impl AsRef<str>,
     ) -> Result<EventContractSeriesInstance> {
-        todo!()
+        let symbol = symbol.as_ref();
+        let rest = symbol
+            .strip_prefix(self.event_contract_series.stem())
+            .and_then(|s| s.strip_prefix(' '))
+            .and_then(|s| s.strip_suffix(" Event Contract"))
+            .ok_or_else(|| {
+                anyhow!("symbol is not in series {}", self.event_contract_series)
+            })?;
+        let instance = match &self.outcomes {
+            EventContractOutcomes::Enumerated { outcomes } => {
+                let outcome = outcomes
+                    .iter()
+                    .find(|outcome| outcome.name == rest)
+                    .ok_or_else(|| anyhow!("unknown outcome: {rest}"))?;
+                EventContractSeriesInstance::Enumerated { outcome: outcome.clone() }
+            }
+            EventContractOutcomes::OptionLike { strikes_by_expiration, .. } => {
+                let (expiration, strike) = match rest.split_once(' ') {
+                    Some((date, strike)) => {
+                        let date = NaiveDate::parse_from_str(date, "%Y%m%d")?;
+                        let expiration = strikes_by_expiration
+                            .keys()
+                            .find(|expiration| self.local_date(**expiration) == date)
+                            .ok_or_else(|| anyhow!("no expiration on {date}"))?;
+                        (Some(*expiration), strike)
+                    }
+                    None => (None, rest),
+                };
+                EventContractSeriesInstance::OptionLike {
+                    strike: strike.parse()?,
+                    expiration,
+                }
+            }
+        };
+        self.check_instance(&instance)?;
+        Ok(instance)
+    }
+
+    /// Check that the instance is one of the series' outcomes.
+    pub fn check_instance(&self, instance: &EventContractSeriesInstance) -> Result<()> {
+        match (&self.outcomes, instance) {
+            (
+                EventContractOutcomes::Enumerated { outcomes },
+                EventContractSeriesInstance::Enumerated { outcome },
+            ) => {
+                if !outcomes.iter().any(|o| o.name == outcome.name) {
+                    bail!("unknown outcome: {}", outcome.name);
+                }
+            }
+            (
+                EventContractOutcomes::OptionLike { strikes_by_expiration, .. },
+                EventContractSeriesInstance::OptionLike { strike, expiration },
+            ) => {
+                let listed = match expiration {
+                    Some(expiration) => strikes_by_expiration
+                        .get(expiration)
+                        .ok_or_else(|| anyhow!("unknown expiration: {expiration}"))?
+                        .contains(strike),
+                    None => strikes_by_expiration
+                        .values()
+                        .any(|strikes| strikes.contains(strike)),
+                };
+                if !listed {
+                    bail!("strike {strike} not listed");
+                }
+            }
+            _ => bail!("instance kind does not match series outcomes"),
+        }
+        Ok(())
+    }
+
+    /// Every instance in the series, in outcome or (expiration, strike) order.
+    pub fn instances(&self) -> Vec<EventContractSeriesInstance> {
+        match &self.outcomes {
+            EventContractOutcomes::Enumerated { outcomes } => outcomes
+                .iter()
+                .map(|outcome| EventContractSeriesInstance::Enumerated {
+                    outcome: outcome.clone(),
+                })
+                .collect(),
+            EventContractOutcomes::OptionLike { strikes_by_expiration, .. } => {
+                strikes_by_expiration
+                    .iter()
+                    .flat_map(|(expiration, strikes)| {
+                        strikes.iter().map(|strike| {
+                            EventContractSeriesInstance::OptionLike {
+                                strike: *strike,
+                                expiration: Some(*expiration),
+                            }
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Date of the expiration in the series' time zone, or UTC for
+    /// enumerated series.
+    fn local_date(&self, expiration: DateTime<Utc>) -> NaiveDate {
+        match &self.outcomes {
+            EventContractOutcomes::OptionLike { expiration_time_zone, .. } => {
+                expiration.with_timezone(expiration_time_zone).date_naive()
+            }
+            EventContractOutcomes::Enumerated { .. } => expiration.date_naive(),
+        }
     }
 }
 
@@ -200,3 +337,51 @@ pub enum YesOrNo {
 
 #[cfg(feature = "postgres")]
 crate::to_sql_str!(YesOrNo);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_option_like_instances() -> Result<()> {
+        let expiration = Utc.with_ymd_and_hms(2024, 12, 27, 21, 0, 0).unwrap();
+        let series = EventContractSeriesInfo {
+            event_contract_series: EventContractSeries::new(
+                "ECES 20241227",
+                Some("CME"),
+            )?,
+            quote_symbol: "USD".parse()?,
+            underlying: Some("ES 20241220 CME Future".parse()?),
+            expiration: Some(expiration),
+            outcomes: EventContractOutcomes::OptionLike {
+                expiration_time_of_day: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+                expiration_time_zone: chrono_tz::America::New_York,
+                strikes_by_expiration: BTreeMap::from_iter([(
+                    expiration,
+                    BTreeSet::from_iter([dec!(4500), dec!(4525.50)]),
+                )]),
+            },
+            outcomes_side: Some(YesOrNo::Yes),
+            outcomes_are_mutually_exclusive: false,
+        };
+        let instances = series.instances();
+        assert_eq!(instances.len(), 2);
+        let product = series.get_product(&instances[1])?;
+        assert_eq!(product.as_str(), "ECES 20241227 CME 20241227 4525.5 Event Contract");
+        let parsed = series.parse_instance(&product)?;
+        assert_eq!(parsed.expiration(), Some(expiration));
+        assert!(matches!(
+            parsed,
+            EventContractSeriesInstance::OptionLike { strike, .. } if strike == dec!(4525.5)
+        ));
+        assert!(series
+            .parse_instance("ECES 20241227 CME 20241227 4510 Event Contract")
+            .is_err());
+        assert!(series
+            .parse_instance("ECES 20241227 CME 20241228 4500 Event Contract")
+            .is_err());
+        Ok(())
+    }
+}
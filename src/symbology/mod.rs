@@ -1,16 +1,29 @@
+pub mod diff;
 pub mod event_contract_series;
 pub mod execution_info;
+pub mod month_code;
+pub mod occ;
 pub mod options_series;
+pub mod options_strategy;
+pub mod parsed_product;
 pub mod product;
 pub mod product_catalog;
 pub mod protocol;
+pub mod roll_calendar;
+pub mod search;
+pub mod store;
 pub mod tradable_product;
+pub mod trading_calendar;
 pub mod venue;
 
 pub use event_contract_series::*;
 pub use execution_info::*;
 pub use options_series::*;
+pub use options_strategy::*;
+pub use parsed_product::*;
 pub use product::*;
 pub use product_catalog::*;
+pub use roll_calendar::*;
 pub use tradable_product::*;
+pub use trading_calendar::*;
 pub use venue::*;
@@ -0,0 +1,428 @@
+//! Conversion between canonical futures products ("ES 20241220 CME Future")
+//! and month-code tickers.
+//!
+//! A month-code ticker is a root, a contract month letter and the year:
+//!
+//! ```text
+//! ESZ4        Globex, one year digit
+//! ESZ24       two year digits
+//! F.US.EPZ24  CQG, using ProductCatalogInfo::cqg_contract_symbol as root
+//! ```
+//!
+//! Canonical names carry the full expiration date, so going from a ticker
+//! to a product needs the series' listed contracts.  A one digit year is
+//! ambiguous across decades; it resolves to the listed contract nearest to
+//! now that has not expired, or else the most recently expired one.
+//!
+//! The contract month is not always the expiration month, e.g. CL
+//! contracts expire in the month before delivery; per-root offsets are
+//! configured on the [`MonthCodeConverter`].
+
+use super::{
+    protocol::SymbologySnapshot, store::SymbologyStore, AliasKind, ParsedProduct,
+    Product, ProductCatalogInfo,
+};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use std::collections::BTreeMap;
+
+pub const MONTH_CODES: [char; 12] =
+    ['F', 'G', 'H', 'J', 'K', 'M', 'N', 'Q', 'U', 'V', 'X', 'Z'];
+
+/// Month code for a month in 1..=12.
+pub fn month_code(month: u32) -> Option<char> {
+    MONTH_CODES.get(month.checked_sub(1)? as usize).copied()
+}
+
+pub fn month_from_code(code: char) -> Option<u32> {
+    MONTH_CODES.iter().position(|c| *c == code).map(|i| i as u32 + 1)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonthCodeStyle {
+    /// One year digit, e.g. ESZ4
+    Globex,
+    /// Two year digits, e.g. ESZ24
+    TwoDigitYear,
+    /// CQG contract symbol and two year digits, e.g. F.US.EPZ24
+    Cqg,
+}
+
+impl MonthCodeStyle {
+    pub fn alias_kind(&self) -> Option<AliasKind> {
+        match self {
+            Self::Globex => Some(AliasKind::CmeGlobex),
+            Self::Cqg => Some(AliasKind::Cqg),
+            Self::TwoDigitYear => None,
+        }
+    }
+
+    fn year_digits(&self) -> usize {
+        match self {
+            Self::Globex => 1,
+            Self::TwoDigitYear | Self::Cqg => 2,
+        }
+    }
+}
+
+/// A month-code ticker split into its parts; the root is as written,
+/// e.g. the CQG symbol for CQG tickers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonthCodeTicker {
+    pub root: String,
+    pub month: u32,
+    /// The year digits as written
+    pub year: u32,
+    /// 1 or 2
+    pub year_digits: usize,
+}
+
+impl std::str::FromStr for MonthCodeTicker {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let digits = s.chars().rev().take_while(|c| c.is_ascii_digit()).count();
+        if !(1..=2).contains(&digits) {
+            bail!("expected one or two year digits: {s}");
+        }
+        let (rest, year) = s.split_at(s.len() - digits);
+        let mut chars = rest.chars();
+        let code = chars.next_back().ok_or_else(|| anyhow!("missing month code: {s}"))?;
+        let month = month_from_code(code)
+            .ok_or_else(|| anyhow!("invalid month code {code:?} in {s}"))?;
+        let root = chars.as_str();
+        if root.is_empty() {
+            bail!("missing root: {s}");
+        }
+        Ok(Self {
+            root: root.to_string(),
+            month,
+            year: year.parse()?,
+            year_digits: digits,
+        })
+    }
+}
+
+impl std::fmt::Display for MonthCodeTicker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = month_code(self.month).ok_or(std::fmt::Error)?;
+        write!(f, "{}{code}{:0width$}", self.root, self.year, width = self.year_digits)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MonthCodeConverter {
+    /// Contract month minus expiration month by root, e.g. 1 for CL
+    pub contract_month_offsets: BTreeMap<String, i32>,
+    /// CQG contract symbol by root, e.g. "F.US.EP" for "ES"
+    pub cqg_symbols: BTreeMap<String, String>,
+}
+
+impl MonthCodeConverter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_contract_month_offset(mut self, root: &str, months: i32) -> Self {
+        self.contract_month_offsets.insert(root.to_string(), months);
+        self
+    }
+
+    pub fn with_cqg_symbol(mut self, root: &str, cqg_symbol: &str) -> Self {
+        self.cqg_symbols.insert(root.to_string(), cqg_symbol.to_string());
+        self
+    }
+
+    /// Take CQG symbols from a venue's product catalog, keyed by exchange
+    /// product root.
+    pub fn with_product_catalog<'a>(
+        mut self,
+        catalog: impl IntoIterator<Item = &'a ProductCatalogInfo>,
+    ) -> Self {
+        for info in catalog {
+            if let Some(cqg) = &info.cqg_contract_symbol {
+                let root = info.product_root().unwrap_or(info.exchange_product.as_str());
+                self.cqg_symbols.insert(root.to_string(), cqg.clone());
+            }
+        }
+        self
+    }
+
+    /// (root, contract year, contract month) of a canonical futures product
+    pub fn contract_month(&self, product: &Product) -> Result<(String, i32, u32)> {
+        let ParsedProduct::Future { root, expiration, .. } = product.parse()? else {
+            bail!("not a future: {product}");
+        };
+        let offset = self.contract_month_offsets.get(&root).copied().unwrap_or(0);
+        let months = expiration.year() * 12 + expiration.month0() as i32 + offset;
+        Ok((root, months.div_euclid(12), months.rem_euclid(12) as u32 + 1))
+    }
+
+    pub fn to_month_code(
+        &self,
+        product: &Product,
+        style: MonthCodeStyle,
+    ) -> Result<String> {
+        let (root, year, month) = self.contract_month(product)?;
+        let root = match style {
+            MonthCodeStyle::Cqg => self
+                .cqg_symbols
+                .get(&root)
+                .ok_or_else(|| anyhow!("no CQG symbol for {root}"))?
+                .clone(),
+            MonthCodeStyle::Globex | MonthCodeStyle::TwoDigitYear => root,
+        };
+        let year_digits = style.year_digits();
+        let year = year.rem_euclid(10i32.pow(year_digits as u32)) as u32;
+        Ok(MonthCodeTicker { root, month, year, year_digits }.to_string())
+    }
+
+    /// The canonical root of a ticker, mapping CQG symbols back.
+    fn canonical_root(
+        &self,
+        ticker: &MonthCodeTicker,
+        style: MonthCodeStyle,
+    ) -> Result<String> {
+        match style {
+            MonthCodeStyle::Cqg => self
+                .cqg_symbols
+                .iter()
+                .find(|(_, cqg)| **cqg == ticker.root)
+                .map(|(root, _)| root.clone())
+                .ok_or_else(|| anyhow!("unknown CQG symbol {}", ticker.root)),
+            MonthCodeStyle::Globex | MonthCodeStyle::TwoDigitYear => {
+                Ok(ticker.root.clone())
+            }
+        }
+    }
+
+    /// Resolve a ticker against the listed contracts of its series.
+    pub fn to_product<'a>(
+        &self,
+        ticker: &str,
+        style: MonthCodeStyle,
+        listed: impl IntoIterator<Item = &'a Product>,
+        now: DateTime<Utc>,
+    ) -> Result<Product> {
+        let parsed: MonthCodeTicker = ticker.parse()?;
+        if parsed.year_digits != style.year_digits() {
+            bail!("expected {} year digits in {ticker}", style.year_digits());
+        }
+        let root = self.canonical_root(&parsed, style)?;
+        let modulus = 10i32.pow(parsed.year_digits as u32);
+        let mut candidates: Vec<(NaiveDate, &Product)> = vec![];
+        for product in listed {
+            let Ok((contract_root, year, month)) = self.contract_month(product) else {
+                continue;
+            };
+            if contract_root == root
+                && month == parsed.month
+                && year.rem_euclid(modulus) == parsed.year as i32
+            {
+                if let Some(expiration) = product.nominative_expiration() {
+                    candidates.push((expiration, product));
+                }
+            }
+        }
+        let today = now.date_naive();
+        // nearest unexpired first, then most recently expired
+        let best = candidates
+            .iter()
+            .filter(|(expiration, _)| *expiration >= today)
+            .min_by_key(|(expiration, _)| *expiration)
+            .or_else(|| candidates.iter().max_by_key(|(expiration, _)| *expiration));
+        best.map(|(_, product)| (*product).clone())
+            .ok_or_else(|| anyhow!("no listed contract for {ticker}"))
+    }
+
+    /// Resolve a ticker through the snapshot's aliases for the style, then
+    /// against the snapshot's listed futures.  One digit years that collide
+    /// across decades are never aliased by [`Self::insert_aliases`], so they
+    /// fall through to [`Self::to_product`] and resolve relative to `now`.
+    pub fn resolve(
+        &self,
+        snapshot: &SymbologySnapshot,
+        ticker: &str,
+        style: MonthCodeStyle,
+        now: DateTime<Utc>,
+    ) -> Result<Product> {
+        if let Some(product) = style
+            .alias_kind()
+            .and_then(|kind| snapshot.product_aliases.get(&kind)?.get(ticker))
+        {
+            return Ok(product.clone());
+        }
+        self.to_product(ticker, style, snapshot.products.keys(), now)
+    }
+
+    pub fn resolve_in_store(
+        &self,
+        store: &SymbologyStore,
+        ticker: &str,
+        style: MonthCodeStyle,
+        now: DateTime<Utc>,
+    ) -> Result<Product> {
+        self.resolve(store.snapshot(), ticker, style, now)
+    }
+
+    /// Month-code aliases for the snapshot's futures with the given venue
+    /// discriminant.  Tickers that would name more than one contract, e.g.
+    /// one digit years colliding across decades, are left out.
+    pub fn aliases(
+        &self,
+        snapshot: &SymbologySnapshot,
+        style: MonthCodeStyle,
+        venue_discriminant: Option<&str>,
+    ) -> BTreeMap<String, Product> {
+        let mut aliases: BTreeMap<String, Option<Product>> = BTreeMap::new();
+        for product in snapshot.products.keys() {
+            let Ok(parsed @ ParsedProduct::Future { .. }) = product.parse() else {
+                continue;
+            };
+            if parsed.venue_discriminant() != venue_discriminant {
+                continue;
+            }
+            if let Ok(ticker) = self.to_month_code(product, style) {
+                aliases
+                    .entry(ticker)
+                    .and_modify(|alias| *alias = None)
+                    .or_insert_with(|| Some(product.clone()));
+            }
+        }
+        aliases
+            .into_iter()
+            .filter_map(|(ticker, product)| Some((ticker, product?)))
+            .collect()
+    }
+
+    /// Add [`AliasKind::CmeGlobex`] and, if any CQG symbols are configured,
+    /// [`AliasKind::Cqg`] aliases for the futures on one venue, e.g. once
+    /// each for "CME", "CBOT", "NYMEX" and "COMEX".  Aliases already in
+    /// the snapshot, such as those supplied by the exchange, are kept.
+    pub fn insert_aliases(
+        &self,
+        snapshot: &mut SymbologySnapshot,
+        venue_discriminant: Option<&str>,
+    ) {
+        let mut styles = vec![MonthCodeStyle::Globex];
+        if !self.cqg_symbols.is_empty() {
+            styles.push(MonthCodeStyle::Cqg);
+        }
+        for style in styles {
+            let Some(kind) = style.alias_kind() else { continue };
+            let aliases = self.aliases(snapshot, style, venue_discriminant);
+            let existing = snapshot.product_aliases.entry(kind).or_default();
+            for (ticker, product) in aliases {
+                existing.entry(ticker).or_insert(product);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbology::{DerivativeKind, ProductInfo, ProductType};
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    fn future(root: &str, y: i32, m: u32, d: u32, venue: &str) -> Product {
+        Product::future(root, NaiveDate::from_ymd_opt(y, m, d).unwrap(), Some(venue))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_month_codes() -> Result<()> {
+        let converter = MonthCodeConverter::new()
+            .with_contract_month_offset("CL", 1)
+            .with_cqg_symbol("ES", "F.US.EP");
+        let es = future("ES", 2024, 12, 20, "CME");
+        assert_eq!(converter.to_month_code(&es, MonthCodeStyle::Globex)?, "ESZ4");
+        assert_eq!(converter.to_month_code(&es, MonthCodeStyle::TwoDigitYear)?, "ESZ24");
+        assert_eq!(converter.to_month_code(&es, MonthCodeStyle::Cqg)?, "F.US.EPZ24");
+        // CL Jan 2025 expires in December 2024
+        let cl = future("CL", 2024, 12, 19, "NYMEX");
+        assert_eq!(converter.to_month_code(&cl, MonthCodeStyle::Globex)?, "CLF5");
+        let ticker: MonthCodeTicker = "6EH25".parse()?;
+        assert_eq!(ticker.root, "6E");
+        assert_eq!((ticker.month, ticker.year, ticker.year_digits), (3, 25, 2));
+        assert!("ESA4".parse::<MonthCodeTicker>().is_err());
+        assert!("ESZ".parse::<MonthCodeTicker>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_decade() -> Result<()> {
+        let converter = MonthCodeConverter::new().with_cqg_symbol("ES", "F.US.EP");
+        let es_z14 = future("ES", 2014, 12, 19, "CME");
+        let es_z24 = future("ES", 2024, 12, 20, "CME");
+        let es_z34 = future("ES", 2034, 12, 15, "CME");
+        let listed = [es_z14.clone(), es_z24.clone(), es_z34.clone()];
+        let btc_cme = future("BTC", 2025, 3, 28, "CME");
+        let btc_cfe = future("BTC", 2025, 3, 28, "CFE");
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let resolve =
+            |ticker: &str, style, now| converter.to_product(ticker, style, &listed, now);
+        assert_eq!(resolve("ESZ4", MonthCodeStyle::Globex, now)?, es_z24);
+        assert_eq!(resolve("ESZ14", MonthCodeStyle::TwoDigitYear, now)?, es_z14);
+        assert_eq!(resolve("F.US.EPZ24", MonthCodeStyle::Cqg, now)?, es_z24);
+        let later = Utc.with_ymd_and_hms(2025, 1, 10, 0, 0, 0).unwrap();
+        assert_eq!(resolve("ESZ4", MonthCodeStyle::Globex, later)?, es_z34);
+        assert!(resolve("ESH4", MonthCodeStyle::Globex, now).is_err());
+        assert!(resolve("ESZ24", MonthCodeStyle::Globex, now).is_err());
+        // through aliases in the symbology store
+        let mut snapshot = SymbologySnapshot::default();
+        for product in listed.iter().chain([&btc_cme, &btc_cfe]) {
+            let expiration = product.nominative_expiration().unwrap();
+            snapshot.products.insert(
+                product.clone(),
+                ProductInfo {
+                    product_type: ProductType::Future {
+                        series: None,
+                        underlying: None,
+                        multiplier: dec!(50),
+                        expiration: expiration.and_hms_opt(13, 30, 0).unwrap().and_utc(),
+                        derivative_kind: DerivativeKind::Linear,
+                        first_notice_date: None,
+                    },
+                    primary_venue: None,
+                    price_display_format: None,
+                },
+            );
+        }
+        snapshot
+            .product_aliases
+            .entry(AliasKind::CmeGlobex)
+            .or_default()
+            .insert("ES DEC24".into(), es_z24.clone());
+        converter.insert_aliases(&mut snapshot, Some("CME"));
+        let globex = &snapshot.product_aliases[&AliasKind::CmeGlobex];
+        // ambiguous across decades, left to resolve against now
+        assert!(!globex.contains_key("ESZ4"));
+        // existing aliases are kept, other venues are not mixed in
+        assert_eq!(globex["ES DEC24"], es_z24);
+        assert_eq!(globex["BTCH5"], btc_cme);
+        assert_eq!(snapshot.product_aliases[&AliasKind::Cqg]["F.US.EPZ24"], es_z24);
+        assert_eq!(
+            converter.resolve(&snapshot, "ESZ4", MonthCodeStyle::Globex, now)?,
+            es_z24
+        );
+        assert_eq!(
+            converter.resolve(&snapshot, "ESZ4", MonthCodeStyle::Globex, later)?,
+            es_z34
+        );
+        converter.insert_aliases(&mut snapshot, Some("CFE"));
+        assert_eq!(snapshot.product_aliases[&AliasKind::CmeGlobex]["BTCH5"], btc_cme);
+        let store = SymbologyStore::from_snapshot(snapshot);
+        assert_eq!(
+            converter.resolve_in_store(
+                &store,
+                "ESZ24",
+                MonthCodeStyle::TwoDigitYear,
+                now
+            )?,
+            es_z24
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+//! Conversion between option products and OCC / OSI option symbols.
+//!
+//! An OCC symbol is 21 characters: the root left-justified and padded with
+//! spaces to six, the expiration as yymmdd, C or P, and the strike times
+//! 1000 in eight digits, e.g. "AAPL  241220C00200000".  Some vendors drop
+//! the padding ("AAPL241220C00200000"); both are accepted when parsing.
+//!
+//! The root is the first word of the options series stem, e.g. "AAPL" for
+//! "AAPL US Options".  Adjusted series trade under wrap roots such as
+//! "2TSLA"; [`OccSymbol::underlying_root`] maps them back to the underlying
+//! using [`OptionsWraps`].
+
+use super::{
+    protocol::SymbologySnapshot, AliasKind, OptionsSeriesInfo, OptionsSeriesInstance,
+    ParsedProduct, Product, ProductType, PutOrCall,
+};
+use crate::marketdata::options_marketdata::OptionsWraps;
+use anyhow::{anyhow, bail, Result};
+use chrono::{Datelike, NaiveDate};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use std::{collections::BTreeMap, fmt, str::FromStr, sync::LazyLock};
+
+pub const OCC_SYMBOL_LEN: usize = 21;
+pub const OCC_ROOT_LEN: usize = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OccSymbol {
+    pub root: String,
+    pub expiration: NaiveDate,
+    pub put_or_call: PutOrCall,
+    pub strike: Decimal,
+}
+
+impl OccSymbol {
+    pub fn new(
+        root: impl Into<String>,
+        expiration: NaiveDate,
+        put_or_call: PutOrCall,
+        strike: Decimal,
+    ) -> Result<Self> {
+        let root = root.into();
+        if root.is_empty() || root.len() > OCC_ROOT_LEN {
+            bail!("OCC root must be 1 to {OCC_ROOT_LEN} characters: {root:?}");
+        }
+        if !root.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+            bail!("OCC root must be upper case letters and digits: {root:?}");
+        }
+        if !(2000..2100).contains(&expiration.year()) {
+            bail!("OCC expiration year out of range: {expiration}");
+        }
+        strike_thousandths(strike)?;
+        Ok(Self { root, expiration, put_or_call, strike: strike.normalize() })
+    }
+
+    /// From a product symbol in either option style, see [`ParsedProduct`].
+    pub fn from_product(product: &Product) -> Result<Self> {
+        match product.parse()? {
+            ParsedProduct::Option { stem, expiration, strike, put_or_call, .. } => {
+                Self::new(root_of_stem(&stem)?, expiration, put_or_call, strike)
+            }
+            parsed => bail!("not an option: {} {product}", parsed.kind()),
+        }
+    }
+
+    /// The expiration date is taken in the series' expiration time zone.
+    pub fn from_instance(
+        series: &OptionsSeriesInfo,
+        instance: &OptionsSeriesInstance,
+    ) -> Result<Self> {
+        Self::new(
+            root_of_stem(series.stem()?)?,
+            instance.expiration.with_timezone(&series.expiration_time_zone).date_naive(),
+            instance.put_or_call,
+            instance.strike,
+        )
+    }
+
+    pub fn to_instance(
+        &self,
+        series: &OptionsSeriesInfo,
+    ) -> Result<OptionsSeriesInstance> {
+        let series_root = root_of_stem(series.stem()?)?;
+        if series_root != self.root {
+            bail!(
+                "OCC root {} does not match series {}",
+                self.root,
+                series.options_series
+            );
+        }
+        Ok(OptionsSeriesInstance {
+            expiration: series.expiration_time(self.expiration)?,
+            strike: self.strike,
+            put_or_call: self.put_or_call,
+        })
+    }
+
+    pub fn to_product(&self, series: &OptionsSeriesInfo) -> Result<Product> {
+        series.get_product(&self.to_instance(series)?)
+    }
+
+    /// The root without padding, e.g. "AAPL241220C00200000".
+    pub fn compact(&self) -> String {
+        format!("{}{}", self.root, self.suffix())
+    }
+
+    /// The underlying symbol for wrap roots like "2TSLA", or the root
+    /// itself if it isn't a known wrap.
+    pub fn underlying_root<'a>(
+        &'a self,
+        wraps: impl IntoIterator<Item = &'a OptionsWraps>,
+    ) -> &'a str {
+        wraps
+            .into_iter()
+            .find(|w| w.wraps.iter().any(|wrap| wrap == &self.root))
+            .map(|w| w.underlying.as_str())
+            .unwrap_or(&self.root)
+    }
+
+    /// Look up the product through the snapshot's OCC aliases.
+    pub fn lookup<'a>(&self, snapshot: &'a SymbologySnapshot) -> Option<&'a Product> {
+        snapshot.product_aliases.get(&AliasKind::Occ)?.get(&self.to_string())
+    }
+
+    fn suffix(&self) -> String {
+        // checked on construction
+        let thousandths = strike_thousandths(self.strike).unwrap_or_default();
+        format!(
+            "{:02}{:02}{:02}{}{:08}",
+            self.expiration.year() % 100,
+            self.expiration.month(),
+            self.expiration.day(),
+            self.put_or_call,
+            thousandths
+        )
+    }
+}
+
+/// The 21 character padded form.
+impl fmt::Display for OccSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<width$}{}", self.root, self.suffix(), width = OCC_ROOT_LEN)
+    }
+}
+
+impl FromStr for OccSymbol {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        static OCC_RE: LazyLock<regex::Regex> = LazyLock::new(|| {
+            regex::Regex::new(r"^([A-Z0-9]{1,6}) *(\d{2})(\d{2})(\d{2})([CP])(\d{8})$")
+                .unwrap()
+        });
+        let caps = OCC_RE
+            .captures(s)
+            .ok_or_else(|| anyhow!("not an OCC option symbol: {s:?}"))?;
+        // padded symbols must be exactly 21 characters
+        if s.contains(' ') && s.len() != OCC_SYMBOL_LEN {
+            bail!("padded OCC symbol must be {OCC_SYMBOL_LEN} characters: {s:?}");
+        }
+        let expiration = NaiveDate::from_ymd_opt(
+            2000 + caps[2].parse::<i32>()?,
+            caps[3].parse()?,
+            caps[4].parse()?,
+        )
+        .ok_or_else(|| anyhow!("invalid OCC expiration: {s:?}"))?;
+        let strike = Decimal::from(caps[6].parse::<u64>()?) / Decimal::ONE_THOUSAND;
+        Self::new(&caps[1], expiration, caps[5].parse()?, strike)
+    }
+}
+
+fn strike_thousandths(strike: Decimal) -> Result<u64> {
+    let thousandths = strike * Decimal::ONE_THOUSAND;
+    if strike.is_sign_negative() || !thousandths.fract().is_zero() {
+        bail!("OCC strike must be non-negative with at most 3 decimals: {strike}");
+    }
+    thousandths
+        .to_u64()
+        .filter(|t| *t < 100_000_000)
+        .ok_or_else(|| anyhow!("OCC strike too large: {strike}"))
+}
+
+/// The first word of an options stem, e.g. "AAPL" for "AAPL US".
+fn root_of_stem(stem: &str) -> Result<&str> {
+    stem.split_whitespace().next().ok_or_else(|| anyhow!("empty options stem"))
+}
+
+/// OCC aliases for every option product in the snapshot, keyed by the
+/// padded OCC symbol.  Products that can't be expressed in OCC symbology
+/// are skipped.
+pub fn occ_aliases(snapshot: &SymbologySnapshot) -> BTreeMap<String, Product> {
+    let mut aliases = BTreeMap::new();
+    for (product, info) in &snapshot.products {
+        let ProductType::Option { series, instance } = &info.product_type else {
+            continue;
+        };
+        let occ = match snapshot.options_series.get(series) {
+            Some(series) => OccSymbol::from_instance(series, instance),
+            None => OccSymbol::from_product(product),
+        };
+        if let Ok(occ) = occ {
+            aliases.insert(occ.to_string(), product.clone());
+        }
+    }
+    aliases
+}
+
+/// Replace the snapshot's [`AliasKind::Occ`] aliases with [`occ_aliases`].
+pub fn insert_occ_aliases(snapshot: &mut SymbologySnapshot) {
+    let aliases = occ_aliases(snapshot);
+    snapshot.product_aliases.insert(AliasKind::Occ, aliases);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbology::{DerivativeKind, OptionsExerciseType, ProductInfo};
+    use chrono::NaiveTime;
+    use rust_decimal_macros::dec;
+    use std::collections::BTreeSet;
+
+    fn series(name: &str) -> OptionsSeriesInfo {
+        OptionsSeriesInfo {
+            options_series: name.parse().unwrap(),
+            venue_discriminant: None,
+            quote_symbol: "USD".parse().unwrap(),
+            underlying: "TSLA US Equity".parse().unwrap(),
+            multiplier: dec!(100),
+            expiration_time_of_day: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+            expiration_time_zone: chrono_tz::America::New_York,
+            strikes_by_expiration: BTreeMap::from_iter([(
+                NaiveDate::from_ymd_opt(2024, 12, 20).unwrap(),
+                BTreeSet::from_iter([dec!(200), dec!(202.5)]),
+            )]),
+            derivative_kind: DerivativeKind::Linear,
+            exercise_type: OptionsExerciseType::American,
+            is_cash_settled: false,
+        }
+    }
+
+    #[test]
+    fn test_occ_symbol() -> Result<()> {
+        let occ: OccSymbol = "AAPL  241220C00200000".parse()?;
+        assert_eq!(occ.root, "AAPL");
+        assert_eq!(occ.expiration, NaiveDate::from_ymd_opt(2024, 12, 20).unwrap());
+        assert_eq!(occ.put_or_call, PutOrCall::Call);
+        assert_eq!(occ.strike, dec!(200));
+        assert_eq!(occ.compact(), "AAPL241220C00200000");
+        assert_eq!(occ.compact().parse::<OccSymbol>()?, occ);
+        let occ: OccSymbol = "SPX   250117P04512500".parse()?;
+        assert_eq!(occ.strike, dec!(4512.5));
+        assert_eq!(occ.to_string(), "SPX   250117P04512500");
+        assert!("AAPL 241220C00200000".parse::<OccSymbol>().is_err());
+        assert!(
+            OccSymbol::new("AAPL", occ.expiration, PutOrCall::Put, dec!(1.2345)).is_err()
+        );
+        // product symbols in either style
+        let product: Product = "AAPL US 20241220 200 C Option".parse()?;
+        assert_eq!(
+            OccSymbol::from_product(&product)?.to_string(),
+            "AAPL  241220C00200000"
+        );
+        let product = Product::option(
+            "AAPL",
+            occ.expiration,
+            dec!(0.5),
+            PutOrCall::Put,
+            Some("CBOE"),
+        )?;
+        assert_eq!(
+            OccSymbol::from_product(&product)?.to_string(),
+            "AAPL  250117P00000500"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_occ_wraps_and_aliases() -> Result<()> {
+        let wraps =
+            OptionsWraps { underlying: "TSLA".into(), wraps: vec!["2TSLA".into()] };
+        let wrapped = series("2TSLA US Options");
+        let occ: OccSymbol = "2TSLA 241220C00202500".parse()?;
+        assert_eq!(occ.underlying_root([&wraps]), "TSLA");
+        let product = occ.to_product(&wrapped)?;
+        assert_eq!(product.as_str(), "2TSLA US241220C00202500 Option");
+        assert!(occ.to_product(&series("TSLA US Options")).is_err());
+        let instance = wrapped.parse_instance(&product)?;
+        assert_eq!(OccSymbol::from_instance(&wrapped, &instance)?, occ);
+        let mut snapshot = SymbologySnapshot::default();
+        snapshot.options_series.insert(wrapped.options_series.clone(), wrapped.clone());
+        snapshot.products.insert(
+            product.clone(),
+            ProductInfo {
+                product_type: ProductType::Option {
+                    series: wrapped.options_series.clone(),
+                    instance,
+                },
+                primary_venue: None,
+                price_display_format: None,
+            },
+        );
+        insert_occ_aliases(&mut snapshot);
+        assert_eq!(occ.lookup(&snapshot), Some(&product));
+        Ok(())
+    }
+}
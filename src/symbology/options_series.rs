@@ -1,4 +1,4 @@
-use super::{DerivativeKind, Product, TradableProduct};
+use super::{DerivativeKind, ParsedProduct, Product, TradableProduct};
 use anyhow::{anyhow, bail, Result};
 use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use derive_more::{AsRef, Display};
@@ -9,7 +9,6 @@ use std::{
     collections::{BTreeMap, BTreeSet},
     fmt,
     str::{self, FromStr},
-    sync::LazyLock,
 };
 use strum_macros::{EnumString, IntoStaticStr};
 
@@ -67,26 +66,31 @@ pub struct OptionsSeriesInfo {
 impl OptionsSeriesInfo {
     pub fn get_product(&self, instance: &OptionsSeriesInstance) -> Result<Product> {
         let OptionsSeriesInstance { expiration, strike, put_or_call } = instance;
+        Product::option(
+            self.stem()?,
+            expiration.date_naive(),
+            *strike,
+            *put_or_call,
+            self.venue_discriminant.as_deref(),
+        )
+    }
+
+    /// The series name without " Options" and the venue discriminant,
+    /// e.g. "AAPL US" for "AAPL US Options".
+    pub fn stem(&self) -> Result<&str> {
         let stem_and_venue_discriminant = self
             .options_series
             .0
             .strip_suffix(" Options")
             .ok_or_else(|| anyhow!("invalid options series name"))?;
-        let stem = if let Some(venue_discriminant) = &self.venue_discriminant {
+        Ok(if let Some(venue_discriminant) = &self.venue_discriminant {
             stem_and_venue_discriminant
                 .strip_suffix(venue_discriminant.as_str())
                 .ok_or_else(|| anyhow!("invalid options series name"))?
                 .trim_end()
         } else {
             stem_and_venue_discriminant
-        };
-        Product::option(
-            stem,
-            expiration.date_naive(),
-            *strike,
-            *put_or_call,
-            self.venue_discriminant.as_deref(),
-        )
+        })
     }
 
     pub fn get_tradable_product(
@@ -97,50 +101,56 @@ impl OptionsSeriesInfo {
         TradableProduct::new(&base, Some(&self.quote_symbol))
     }
 
+    /// Expiration time of options expiring on `date`.
+    pub fn expiration_time(&self, date: NaiveDate) -> Result<DateTime<Utc>> {
+        Ok(date
+            .and_time(self.expiration_time_of_day)
+            .and_local_timezone(self.expiration_time_zone)
+            .single()
+            .ok_or_else(|| anyhow!("expiration time ambiguous with given time zone"))?
+            .to_utc())
+    }
+
+    /// Parse an option symbol in either OSI or long style, see
+    /// [`ParsedProduct`].  The stem, venue discriminant and expiration date
+    /// must belong to this series.  Tradable product symbols are accepted
+    /// too; their "/quote" suffix is ignored.
     pub fn parse_instance(
         &self,
         symbol: impl AsRef<str>,
     ) -> Result<OptionsSeriesInstance> {
-        static OPTION_SYMBOL_RE: LazyLock<regex::Regex> = LazyLock::new(|| {
-            regex::Regex::new(r"^([\w\s]+) (\d{8}) ([\d\.]+) ([PC])( \w*)? Option/?.*$")
-                .unwrap()
-        });
-
-        // CR alee: check stem
         let symbol = symbol.as_ref();
-        let caps = OPTION_SYMBOL_RE
-            .captures(symbol)
-            .ok_or_else(|| anyhow!("symbol does not match expected format"))?;
-
-        let expiration_str = &caps[2];
-        let expiration_date = NaiveDate::parse_from_str(expiration_str, "%Y%m%d")?;
-        // CR alee: check expiration date
-        let expiration = expiration_date
-            .and_time(self.expiration_time_of_day)
-            .and_local_timezone(self.expiration_time_zone)
-            .single()
-            .ok_or_else(|| anyhow!("expiration time ambiguous with given time zone"))?
-            .to_utc();
-
-        let strike = caps[3].parse::<Decimal>()?;
-        let put_or_call = caps[4].parse::<PutOrCall>()?;
-
-        // If we expect a venue discriminant, it must exist in the symbol and match
-        if let Some(expected_venue) = &self.venue_discriminant {
-            let venue_match =
-                caps.get(5).ok_or_else(|| anyhow!("missing venue discriminant"))?;
-            let venue = venue_match.as_str().trim();
-            if venue.is_empty() || venue != expected_venue {
-                bail!("venue discriminant mismatch");
-            }
+        let base = symbol.split_once('/').map_or(symbol, |(base, _)| base);
+        let parsed: ParsedProduct = base.parse()?;
+        let ParsedProduct::Option {
+            stem,
+            expiration,
+            strike,
+            put_or_call,
+            venue_discriminant,
+            ..
+        } = parsed
+        else {
+            bail!("not an option symbol");
+        };
+        if stem.trim_end() != self.stem()? {
+            bail!("stem mismatch: expected {}, got {stem}", self.stem()?);
         }
-
+        if venue_discriminant != self.venue_discriminant {
+            bail!("venue discriminant mismatch");
+        }
+        if !self.strikes_by_expiration.is_empty()
+            && !self.strikes_by_expiration.contains_key(&expiration)
+        {
+            bail!("no expiration on {expiration} in series {}", self.options_series);
+        }
+        let expiration = self.expiration_time(expiration)?;
         Ok(OptionsSeriesInstance { expiration, strike, put_or_call })
     }
 }
 
 /// A specific option from a series.
-#[derive(Debug, Clone, Copy, Deserialize, Serialize, JsonSchema)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
 pub struct OptionsSeriesInstance {
     pub expiration: DateTime<Utc>,
     pub strike: Decimal,
@@ -208,3 +218,43 @@ impl FromStr for PutOrCall {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_parse_instance() -> Result<()> {
+        let expiration = NaiveDate::from_ymd_opt(2024, 12, 20).unwrap();
+        let series = OptionsSeriesInfo {
+            options_series: "AAPL US Options".parse()?,
+            venue_discriminant: None,
+            quote_symbol: "USD".parse()?,
+            underlying: "AAPL US Equity".parse()?,
+            multiplier: dec!(100),
+            expiration_time_of_day: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+            expiration_time_zone: chrono_tz::America::New_York,
+            strikes_by_expiration: BTreeMap::from_iter([(
+                expiration,
+                BTreeSet::from_iter([dec!(200)]),
+            )]),
+            derivative_kind: DerivativeKind::Linear,
+            exercise_type: OptionsExerciseType::American,
+            is_cash_settled: false,
+        };
+        let instance = OptionsSeriesInstance {
+            expiration: series.expiration_time(expiration)?,
+            strike: dec!(200),
+            put_or_call: PutOrCall::Call,
+        };
+        let product = series.get_product(&instance)?;
+        assert_eq!(series.parse_instance(&product)?, instance);
+        assert_eq!(series.parse_instance("AAPL US 20241220 200 C Option")?, instance);
+        let tradable = series.get_tradable_product(&instance)?;
+        assert_eq!(series.parse_instance(&tradable)?, instance);
+        assert_eq!(series.parse_instance("AAPL US 20241220 200 C Option/USD")?, instance);
+        assert!(series.parse_instance("MSFT US 20241220 200 C Option/USD").is_err());
+        Ok(())
+    }
+}
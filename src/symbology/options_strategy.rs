@@ -0,0 +1,408 @@
+//! Multi-leg options strategies, e.g. verticals, straddles and condors.
+//!
+//! A strategy is defined from the buyer's point of view; buying one unit
+//! of the strategy executes every leg in its own direction, selling one
+//! unit executes every leg in the opposite direction.  Net prices follow
+//! the same convention: positive for a debit, negative for a credit.
+
+use super::{OptionsSeries, OptionsSeriesInfo, OptionsSeriesInstance, PutOrCall};
+use crate::{marketdata::options_marketdata::OptionsGreeks, Dir};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct OptionsStrategyLeg {
+    #[serde(rename = "i")]
+    #[schemars(title = "instance")]
+    pub instance: OptionsSeriesInstance,
+    #[serde(rename = "d")]
+    #[schemars(title = "dir")]
+    pub dir: Dir,
+    /// Number of contracts of this leg per unit of the strategy
+    #[serde(rename = "r")]
+    #[schemars(title = "ratio")]
+    pub ratio: u32,
+}
+
+impl OptionsStrategyLeg {
+    pub fn new(instance: OptionsSeriesInstance, dir: Dir, ratio: u32) -> Self {
+        Self { instance, dir, ratio }
+    }
+
+    /// Signed number of contracts per unit of the strategy
+    pub fn signed_ratio(&self) -> Decimal {
+        self.dir.position_sign() * Decimal::from(self.ratio)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct OptionsStrategy {
+    #[serde(rename = "os")]
+    #[schemars(title = "options_series")]
+    pub options_series: OptionsSeries,
+    #[serde(rename = "l")]
+    #[schemars(title = "legs")]
+    pub legs: Vec<OptionsStrategyLeg>,
+}
+
+impl OptionsStrategy {
+    pub fn new(
+        options_series: OptionsSeries,
+        legs: Vec<OptionsStrategyLeg>,
+    ) -> Result<Self> {
+        let strategy = Self { options_series, legs };
+        strategy.validate()?;
+        Ok(strategy)
+    }
+
+    /// Buy the lower/higher strike and sell the other, depending on
+    /// which strike is passed as `long_strike`.
+    pub fn vertical(
+        options_series: OptionsSeries,
+        expiration: DateTime<Utc>,
+        put_or_call: PutOrCall,
+        long_strike: Decimal,
+        short_strike: Decimal,
+    ) -> Result<Self> {
+        let leg = |strike, dir| {
+            OptionsStrategyLeg::new(
+                OptionsSeriesInstance { expiration, strike, put_or_call },
+                dir,
+                1,
+            )
+        };
+        Self::new(
+            options_series,
+            vec![leg(long_strike, Dir::Buy), leg(short_strike, Dir::Sell)],
+        )
+    }
+
+    /// Sell the near expiration and buy the far expiration at the same strike.
+    pub fn calendar(
+        options_series: OptionsSeries,
+        near_expiration: DateTime<Utc>,
+        far_expiration: DateTime<Utc>,
+        strike: Decimal,
+        put_or_call: PutOrCall,
+    ) -> Result<Self> {
+        if near_expiration >= far_expiration {
+            bail!("near expiration must be before far expiration");
+        }
+        let leg = |expiration, dir| {
+            OptionsStrategyLeg::new(
+                OptionsSeriesInstance { expiration, strike, put_or_call },
+                dir,
+                1,
+            )
+        };
+        Self::new(
+            options_series,
+            vec![leg(near_expiration, Dir::Sell), leg(far_expiration, Dir::Buy)],
+        )
+    }
+
+    /// Buy a put and a call at the same strike.
+    pub fn straddle(
+        options_series: OptionsSeries,
+        expiration: DateTime<Utc>,
+        strike: Decimal,
+    ) -> Result<Self> {
+        Self::strangle(options_series, expiration, strike, strike)
+    }
+
+    /// Buy a put at `put_strike` and a call at `call_strike`.
+    pub fn strangle(
+        options_series: OptionsSeries,
+        expiration: DateTime<Utc>,
+        put_strike: Decimal,
+        call_strike: Decimal,
+    ) -> Result<Self> {
+        if put_strike > call_strike {
+            bail!("put strike must not be above call strike");
+        }
+        let leg = |strike, put_or_call| {
+            OptionsStrategyLeg::new(
+                OptionsSeriesInstance { expiration, strike, put_or_call },
+                Dir::Buy,
+                1,
+            )
+        };
+        Self::new(
+            options_series,
+            vec![leg(put_strike, PutOrCall::Put), leg(call_strike, PutOrCall::Call)],
+        )
+    }
+
+    /// Buy one lower wing, sell two bodies, buy one upper wing.
+    pub fn butterfly(
+        options_series: OptionsSeries,
+        expiration: DateTime<Utc>,
+        put_or_call: PutOrCall,
+        lower_strike: Decimal,
+        middle_strike: Decimal,
+        upper_strike: Decimal,
+    ) -> Result<Self> {
+        if !(lower_strike < middle_strike && middle_strike < upper_strike) {
+            bail!("butterfly strikes must be strictly increasing");
+        }
+        let leg = |strike, dir, ratio| {
+            OptionsStrategyLeg::new(
+                OptionsSeriesInstance { expiration, strike, put_or_call },
+                dir,
+                ratio,
+            )
+        };
+        Self::new(
+            options_series,
+            vec![
+                leg(lower_strike, Dir::Buy, 1),
+                leg(middle_strike, Dir::Sell, 2),
+                leg(upper_strike, Dir::Buy, 1),
+            ],
+        )
+    }
+
+    /// Short put spread plus short call spread; buying the iron condor
+    /// collects a credit.
+    pub fn iron_condor(
+        options_series: OptionsSeries,
+        expiration: DateTime<Utc>,
+        long_put_strike: Decimal,
+        short_put_strike: Decimal,
+        short_call_strike: Decimal,
+        long_call_strike: Decimal,
+    ) -> Result<Self> {
+        if !(long_put_strike < short_put_strike
+            && short_put_strike <= short_call_strike
+            && short_call_strike < long_call_strike)
+        {
+            bail!("iron condor strikes must be increasing");
+        }
+        let leg = |strike, put_or_call, dir| {
+            OptionsStrategyLeg::new(
+                OptionsSeriesInstance { expiration, strike, put_or_call },
+                dir,
+                1,
+            )
+        };
+        Self::new(
+            options_series,
+            vec![
+                leg(long_put_strike, PutOrCall::Put, Dir::Buy),
+                leg(short_put_strike, PutOrCall::Put, Dir::Sell),
+                leg(short_call_strike, PutOrCall::Call, Dir::Sell),
+                leg(long_call_strike, PutOrCall::Call, Dir::Buy),
+            ],
+        )
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.legs.is_empty() {
+            bail!("options strategy must have at least one leg");
+        }
+        for (i, leg) in self.legs.iter().enumerate() {
+            if leg.ratio == 0 {
+                bail!("leg {i} has a zero ratio");
+            }
+            if self.legs[..i].iter().any(|other| other.instance == leg.instance) {
+                bail!("leg {i} duplicates an earlier leg");
+            }
+        }
+        Ok(())
+    }
+
+    /// Legs as executed for one unit of the strategy in direction `dir`.
+    pub fn legs_for(&self, dir: Dir) -> impl Iterator<Item = OptionsStrategyLeg> + '_ {
+        self.legs.iter().map(move |leg| OptionsStrategyLeg { dir: leg.dir * dir, ..*leg })
+    }
+
+    /// Net price of one unit given per-leg prices, in leg order.
+    pub fn net_price(&self, leg_prices: &[Decimal]) -> Result<Decimal> {
+        if leg_prices.len() != self.legs.len() {
+            bail!("expected {} leg prices, got {}", self.legs.len(), leg_prices.len());
+        }
+        Ok(self
+            .legs
+            .iter()
+            .zip(leg_prices)
+            .map(|(leg, price)| leg.signed_ratio() * price)
+            .sum())
+    }
+
+    /// Value of one unit at expiry, before the contract multiplier, if the
+    /// underlying settles at `underlying_price`.  Only defined for
+    /// strategies whose legs all expire together.
+    pub fn payoff_at_expiry(&self, underlying_price: Decimal) -> Result<Decimal> {
+        let Some(first) = self.legs.first() else {
+            bail!("options strategy must have at least one leg");
+        };
+        if self
+            .legs
+            .iter()
+            .any(|leg| leg.instance.expiration != first.instance.expiration)
+        {
+            bail!("payoff at expiry is undefined for legs with different expirations");
+        }
+        Ok(self
+            .legs
+            .iter()
+            .map(|leg| {
+                let intrinsic = match leg.instance.put_or_call {
+                    PutOrCall::Call => underlying_price - leg.instance.strike,
+                    PutOrCall::Put => leg.instance.strike - underlying_price,
+                };
+                leg.signed_ratio() * intrinsic.max(Decimal::ZERO)
+            })
+            .sum())
+    }
+
+    /// Sum per-leg greeks (in leg order) into the greeks of one unit of
+    /// the strategy, before the contract multiplier.
+    pub fn aggregate_greeks(
+        &self,
+        leg_greeks: &[OptionsGreeks],
+    ) -> Result<OptionsStrategyGreeks> {
+        if leg_greeks.len() != self.legs.len() {
+            bail!("expected {} leg greeks, got {}", self.legs.len(), leg_greeks.len());
+        }
+        let mut total = OptionsStrategyGreeks::default();
+        for (leg, greeks) in self.legs.iter().zip(leg_greeks) {
+            let ratio = leg.signed_ratio();
+            total.delta += greeks.delta * ratio;
+            total.gamma += greeks.gamma * ratio;
+            total.theta += greeks.theta * ratio;
+            total.vega += greeks.vega * ratio;
+            total.rho += greeks.rho * ratio;
+        }
+        Ok(total)
+    }
+}
+
+impl OptionsSeriesInfo {
+    /// Greeks of a position of `quantity` strategy units, including the
+    /// contract multiplier.  `leg_greeks` are per-contract, in leg order.
+    pub fn strategy_position_greeks(
+        &self,
+        strategy: &OptionsStrategy,
+        leg_greeks: &[OptionsGreeks],
+        quantity: Decimal,
+    ) -> Result<OptionsStrategyGreeks> {
+        if strategy.options_series != self.options_series {
+            bail!("strategy is not on options series {}", self.options_series);
+        }
+        let unit = strategy.aggregate_greeks(leg_greeks)?;
+        let scale = quantity * self.multiplier;
+        Ok(OptionsStrategyGreeks {
+            delta: unit.delta * scale,
+            gamma: unit.gamma * scale,
+            theta: unit.theta * scale,
+            vega: unit.vega * scale,
+            rho: unit.rho * scale,
+        })
+    }
+}
+
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema,
+)]
+pub struct OptionsStrategyGreeks {
+    pub delta: Decimal,
+    pub gamma: Decimal,
+    pub theta: Decimal,
+    pub vega: Decimal,
+    pub rho: Decimal,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    fn series() -> OptionsSeries {
+        OptionsSeries::new_unchecked("AAPL US Options")
+    }
+
+    fn expiration() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 16, 21, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_payoff_at_expiry() -> Result<()> {
+        let condor = OptionsStrategy::iron_condor(
+            series(),
+            expiration(),
+            dec!(90),
+            dec!(95),
+            dec!(105),
+            dec!(110),
+        )?;
+        assert_eq!(condor.payoff_at_expiry(dec!(100))?, dec!(0));
+        assert_eq!(condor.payoff_at_expiry(dec!(93))?, dec!(-2));
+        assert_eq!(condor.payoff_at_expiry(dec!(80))?, dec!(-5));
+        assert_eq!(condor.payoff_at_expiry(dec!(120))?, dec!(-5));
+        let fly = OptionsStrategy::butterfly(
+            series(),
+            expiration(),
+            PutOrCall::Call,
+            dec!(95),
+            dec!(100),
+            dec!(105),
+        )?;
+        assert_eq!(fly.payoff_at_expiry(dec!(100))?, dec!(5));
+        assert_eq!(fly.payoff_at_expiry(dec!(103))?, dec!(2));
+        assert_eq!(fly.payoff_at_expiry(dec!(110))?, dec!(0));
+        assert_eq!(fly.net_price(&[dec!(7), dec!(3.5), dec!(1.25)])?, dec!(1.25));
+        let calendar = OptionsStrategy::calendar(
+            series(),
+            expiration(),
+            expiration() + chrono::Duration::days(28),
+            dec!(100),
+            PutOrCall::Put,
+        )?;
+        assert!(calendar.payoff_at_expiry(dec!(100)).is_err());
+        assert!(OptionsStrategy::vertical(
+            series(),
+            expiration(),
+            PutOrCall::Call,
+            dec!(100),
+            dec!(100)
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_greeks_and_serde() -> Result<()> {
+        let straddle = OptionsStrategy::straddle(series(), expiration(), dec!(100))?;
+        let greeks = |put_or_call, delta| OptionsGreeks {
+            symbol: String::new(),
+            underlying: "AAPL US Equity".into(),
+            strike: dec!(100),
+            expiration: expiration().date_naive(),
+            put_or_call,
+            delta,
+            gamma: dec!(0.02),
+            theta: dec!(-0.05),
+            vega: dec!(0.2),
+            rho: dec!(0.01),
+            implied_volatility: dec!(0.3),
+        };
+        let unit = straddle.aggregate_greeks(&[
+            greeks(PutOrCall::Put, dec!(-0.45)),
+            greeks(PutOrCall::Call, dec!(0.55)),
+        ])?;
+        assert_eq!(unit.delta, dec!(0.10));
+        assert_eq!(unit.gamma, dec!(0.04));
+        assert_eq!(unit.vega, dec!(0.4));
+        let sold: Vec<_> = straddle.legs_for(Dir::Sell).map(|leg| leg.dir).collect();
+        assert_eq!(sold, vec![Dir::Sell, Dir::Sell]);
+        let json = serde_json::to_string(&straddle)?;
+        let parsed: OptionsStrategy = serde_json::from_str(&json)?;
+        assert_eq!(parsed, straddle);
+        Ok(())
+    }
+}
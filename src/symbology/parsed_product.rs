@@ -0,0 +1,525 @@
+//! Typed components of [`Product`] symbols.
+//!
+//! [`Product::parse`] splits a symbol into a [`ParsedProduct`], and the
+//! `Display` impl of [`ParsedProduct`] reproduces the symbol exactly as the
+//! corresponding [`Product`] constructor would build it:
+//!
+//! ```text
+//! USD                                     Fiat
+//! XAU Commodity                           Commodity
+//! BTC Crypto                              Crypto
+//! SPX [VENUE] Index                       Index
+//! AAPL US Equity                          Equity
+//! ES 20241220 [VENUE] Future              Future
+//! ES-NQ [1:-2] 20241220 [VENUE] Futures Spread
+//! BTC-USD [VENUE] Perpetual               Perpetual
+//! AAPL  241220C00200000 [VENUE] Option    Option, OSI style
+//! AAPL US 20241220 200 C [VENUE] Option   Option, long style
+//! <series stem> <outcome> Event Contract  EventContract
+//! ```
+//!
+//! Index and perpetual symbols are a single word; a second word is read as
+//! the venue discriminant.  Venue discriminants are always upper case.
+
+use super::{Product, PutOrCall};
+use anyhow::{anyhow, bail, Result};
+use chrono::{Datelike, NaiveDate};
+use rust_decimal::Decimal;
+use std::{fmt, sync::LazyLock};
+
+/// How the option part of an option symbol is spelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OptionSymbolStyle {
+    /// Stem padded to six characters, then yymmdd, put or call and the
+    /// strike times 1000 in eight digits, as built by [`Product::option`].
+    #[default]
+    Osi,
+    /// Stem, yyyymmdd, strike and put or call separated by spaces.
+    Long,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParsedProduct {
+    Fiat {
+        symbol: String,
+    },
+    Commodity {
+        symbol: String,
+    },
+    Crypto {
+        symbol: String,
+    },
+    Index {
+        symbol: String,
+        venue_discriminant: Option<String>,
+    },
+    Equity {
+        symbol: String,
+        country: String,
+    },
+    Future {
+        root: String,
+        expiration: NaiveDate,
+        venue_discriminant: Option<String>,
+    },
+    FuturesSpread {
+        legs: Vec<String>,
+        ratios: Option<Vec<Decimal>>,
+        expiration: NaiveDate,
+        venue_discriminant: Option<String>,
+    },
+    Perpetual {
+        symbol: String,
+        venue_discriminant: Option<String>,
+    },
+    Option {
+        stem: String,
+        expiration: NaiveDate,
+        strike: Decimal,
+        put_or_call: PutOrCall,
+        venue_discriminant: Option<String>,
+        style: OptionSymbolStyle,
+    },
+    /// The series stem and outcome can only be told apart with the
+    /// series info, see [`EventContractSeriesInfo::parse_instance`].
+    ///
+    /// [`EventContractSeriesInfo::parse_instance`]: super::EventContractSeriesInfo::parse_instance
+    EventContract {
+        symbol: String,
+    },
+}
+
+impl ParsedProduct {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Fiat { .. } => "Fiat",
+            Self::Commodity { .. } => "Commodity",
+            Self::Crypto { .. } => "Crypto",
+            Self::Index { .. } => "Index",
+            Self::Equity { .. } => "Equity",
+            Self::Future { .. } => "Future",
+            Self::FuturesSpread { .. } => "Futures Spread",
+            Self::Perpetual { .. } => "Perpetual",
+            Self::Option { .. } => "Option",
+            Self::EventContract { .. } => "Event Contract",
+        }
+    }
+
+    pub fn venue_discriminant(&self) -> Option<&str> {
+        match self {
+            Self::Index { venue_discriminant, .. }
+            | Self::Future { venue_discriminant, .. }
+            | Self::FuturesSpread { venue_discriminant, .. }
+            | Self::Perpetual { venue_discriminant, .. }
+            | Self::Option { venue_discriminant, .. } => venue_discriminant.as_deref(),
+            Self::Fiat { .. }
+            | Self::Commodity { .. }
+            | Self::Crypto { .. }
+            | Self::Equity { .. }
+            | Self::EventContract { .. } => None,
+        }
+    }
+
+    pub fn expiration(&self) -> Option<NaiveDate> {
+        match self {
+            Self::Future { expiration, .. }
+            | Self::FuturesSpread { expiration, .. }
+            | Self::Option { expiration, .. } => Some(*expiration),
+            _ => None,
+        }
+    }
+
+    pub fn to_product(&self) -> Result<Product> {
+        self.to_string().parse()
+    }
+}
+
+impl fmt::Display for ParsedProduct {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fiat { symbol } => return write!(f, "{symbol}"),
+            Self::Commodity { symbol } | Self::Crypto { symbol } => {
+                write!(f, "{symbol}")?
+            }
+            Self::Index { symbol, .. } | Self::Perpetual { symbol, .. } => {
+                write!(f, "{symbol}")?
+            }
+            Self::Equity { symbol, country } => write!(f, "{symbol} {country}")?,
+            Self::Future { root, expiration, .. } => {
+                write!(f, "{root} {}", expiration.format("%Y%m%d"))?
+            }
+            Self::FuturesSpread { legs, ratios, expiration, .. } => {
+                write!(f, "{}", legs.join("-"))?;
+                if let Some(ratios) = ratios {
+                    let ratios: Vec<_> = ratios.iter().map(|r| r.to_string()).collect();
+                    write!(f, " {}", ratios.join(":"))?;
+                }
+                write!(f, " {}", expiration.format("%Y%m%d"))?
+            }
+            Self::Option { stem, expiration, strike, put_or_call, style, .. } => {
+                match style {
+                    OptionSymbolStyle::Osi => {
+                        let strike_str = strike.to_string();
+                        let (dollar_part, decimal_part) =
+                            strike_str.split_once('.').unwrap_or((&strike_str, "000"));
+                        write!(
+                            f,
+                            "{:<6}{:02}{:02}{:02}{}{:0>5}{:0<3}",
+                            stem,
+                            expiration.year() % 100,
+                            expiration.month(),
+                            expiration.day(),
+                            put_or_call,
+                            dollar_part,
+                            &decimal_part[..decimal_part.len().min(3)]
+                        )?
+                    }
+                    OptionSymbolStyle::Long => write!(
+                        f,
+                        "{stem} {} {strike} {put_or_call}",
+                        expiration.format("%Y%m%d")
+                    )?,
+                }
+            }
+            Self::EventContract { symbol } => write!(f, "{symbol}")?,
+        }
+        if let Some(venue_discriminant) = self.venue_discriminant() {
+            write!(f, " {venue_discriminant}")?;
+        }
+        write!(f, " {}", self.kind())
+    }
+}
+
+impl std::str::FromStr for ParsedProduct {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.contains('/') {
+            bail!("product symbol cannot contain the forward slash character '/'");
+        }
+        if !s.contains(' ') {
+            if s.is_empty() {
+                bail!("empty product symbol");
+            }
+            return Ok(Self::Fiat { symbol: s.to_string() });
+        }
+        const KINDS: [&str; 9] = [
+            "Futures Spread",
+            "Event Contract",
+            "Commodity",
+            "Perpetual",
+            "Crypto",
+            "Future",
+            "Option",
+            "Equity",
+            "Index",
+        ];
+        let (kind, body) = KINDS
+            .iter()
+            .find_map(|kind| {
+                let body = s.strip_suffix(kind)?.strip_suffix(' ')?;
+                Some((*kind, body))
+            })
+            .ok_or_else(|| anyhow!("unknown product kind: {s}"))?;
+        if body.is_empty() {
+            bail!("missing symbol in {s}");
+        }
+        let words: Vec<&str> = body.split(' ').collect();
+        if words.iter().any(|w| w.is_empty()) && kind != "Option" {
+            bail!("unexpected whitespace in {s}");
+        }
+        Ok(match kind {
+            "Commodity" => Self::Commodity { symbol: body.to_string() },
+            "Crypto" => Self::Crypto { symbol: body.to_string() },
+            "Event Contract" => Self::EventContract { symbol: body.to_string() },
+            "Index" | "Perpetual" => {
+                let (symbol, venue_discriminant) = match words[..] {
+                    [symbol] => (symbol, None),
+                    [symbol, venue] => (symbol, Some(parse_venue(venue)?)),
+                    _ => bail!("too many words in {s}"),
+                };
+                let symbol = symbol.to_string();
+                if kind == "Index" {
+                    Self::Index { symbol, venue_discriminant }
+                } else {
+                    Self::Perpetual { symbol, venue_discriminant }
+                }
+            }
+            "Equity" => {
+                let Some((symbol, country)) = body.rsplit_once(' ') else {
+                    bail!("missing country in {s}");
+                };
+                Self::Equity { symbol: symbol.to_string(), country: country.to_string() }
+            }
+            "Future" => {
+                let (root, date, venue) = match words[..] {
+                    [root, date] => (root, date, None),
+                    [root, date, venue] => (root, date, Some(venue)),
+                    _ => bail!("expected root, expiration and venue in {s}"),
+                };
+                Self::Future {
+                    root: root.to_string(),
+                    expiration: parse_date(date)?,
+                    venue_discriminant: venue.map(parse_venue).transpose()?,
+                }
+            }
+            "Futures Spread" => {
+                let date_index = words
+                    .iter()
+                    .position(|w| w.len() == 8 && parse_date(w).is_ok())
+                    .ok_or_else(|| anyhow!("missing expiration in {s}"))?;
+                let (legs, ratios) = match words[..date_index] {
+                    [legs] => (legs, None),
+                    [legs, ratios] => (legs, Some(ratios)),
+                    _ => bail!("expected legs and ratios before expiration in {s}"),
+                };
+                let venue = match words[date_index + 1..] {
+                    [] => None,
+                    [venue] => Some(parse_venue(venue)?),
+                    _ => bail!("too many words after expiration in {s}"),
+                };
+                let legs: Vec<String> = legs.split('-').map(|l| l.to_string()).collect();
+                let ratios = ratios
+                    .map(|ratios| {
+                        ratios
+                            .split(':')
+                            .map(|r| Ok(r.parse()?))
+                            .collect::<Result<Vec<_>>>()
+                    })
+                    .transpose()?;
+                if legs.iter().any(|l| l.is_empty()) {
+                    bail!("empty leg in {s}");
+                }
+                if ratios.as_ref().is_some_and(|r| r.len() != legs.len()) {
+                    bail!("number of ratios does not match number of legs in {s}");
+                }
+                Self::FuturesSpread {
+                    legs,
+                    ratios,
+                    expiration: parse_date(words[date_index])?,
+                    venue_discriminant: venue,
+                }
+            }
+            "Option" => parse_option(body)?,
+            _ => unreachable!(),
+        })
+    }
+}
+
+fn parse_date(s: &str) -> Result<NaiveDate> {
+    if s.len() != 8 {
+        bail!("expected yyyymmdd date: {s}");
+    }
+    Ok(NaiveDate::parse_from_str(s, "%Y%m%d")?)
+}
+
+fn parse_venue(s: &str) -> Result<String> {
+    if s != s.to_uppercase() {
+        bail!("venue discriminant must be upper case: {s}");
+    }
+    Ok(s.to_string())
+}
+
+/// OSI years are two digits; they are read as 20yy.
+fn parse_option(body: &str) -> Result<ParsedProduct> {
+    static OSI_RE: LazyLock<regex::Regex> = LazyLock::new(|| {
+        regex::Regex::new(
+            r"^(.+?) *(\d{2})(\d{2})(\d{2})([CP])(\d{5,})(\d{3})(?: (\S+))?$",
+        )
+        .unwrap()
+    });
+    static LONG_RE: LazyLock<regex::Regex> = LazyLock::new(|| {
+        regex::Regex::new(r"^(\S.*?) (\d{8}) (\d+(?:\.\d+)?) ([CP])(?: (\S+))?$").unwrap()
+    });
+    if let Some(caps) = OSI_RE.captures(body) {
+        let expiration = NaiveDate::from_ymd_opt(
+            2000 + caps[2].parse::<i32>()?,
+            caps[3].parse()?,
+            caps[4].parse()?,
+        )
+        .ok_or_else(|| anyhow!("invalid expiration in {body}"))?;
+        let dollars: Decimal = caps[6].parse()?;
+        let thousandths: Decimal = caps[7].parse()?;
+        let strike = (dollars + thousandths / Decimal::ONE_THOUSAND).normalize();
+        let parsed = ParsedProduct::Option {
+            stem: caps[1].to_string(),
+            expiration,
+            strike,
+            put_or_call: caps[5].parse()?,
+            venue_discriminant: caps
+                .get(8)
+                .map(|v| parse_venue(v.as_str()))
+                .transpose()?,
+            style: OptionSymbolStyle::Osi,
+        };
+        // e.g. a stem that ends in digits may match ambiguously
+        if parsed.to_string().strip_suffix(" Option") == Some(body) {
+            return Ok(parsed);
+        }
+    }
+    if let Some(caps) = LONG_RE.captures(body) {
+        return Ok(ParsedProduct::Option {
+            stem: caps[1].to_string(),
+            expiration: parse_date(&caps[2])?,
+            strike: caps[3].parse()?,
+            put_or_call: caps[4].parse()?,
+            venue_discriminant: caps
+                .get(5)
+                .map(|v| parse_venue(v.as_str()))
+                .transpose()?,
+            style: OptionSymbolStyle::Long,
+        });
+    }
+    bail!("option symbol does not match OSI or long format: {body}")
+}
+
+impl Product {
+    /// Split the symbol into its typed components.
+    pub fn parse(&self) -> Result<ParsedProduct> {
+        self.0.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, seq::IndexedRandom, Rng, SeedableRng};
+    use rust_decimal_macros::dec;
+
+    fn assert_roundtrip(product: &Product) -> ParsedProduct {
+        let parsed =
+            product.parse().unwrap_or_else(|e| panic!("failed to parse {product}: {e}"));
+        assert_eq!(parsed.to_string(), product.as_str(), "{parsed:?}");
+        assert_eq!(&parsed.to_product().unwrap(), product);
+        parsed
+    }
+
+    #[test]
+    fn test_parse_examples() -> Result<()> {
+        let date = NaiveDate::from_ymd_opt(2024, 12, 20).unwrap();
+        assert_eq!(
+            assert_roundtrip(&Product::future("ES", date, Some("CME"))?),
+            ParsedProduct::Future {
+                root: "ES".into(),
+                expiration: date,
+                venue_discriminant: Some("CME".into())
+            }
+        );
+        assert_eq!(
+            assert_roundtrip(&Product::option(
+                "AAPL",
+                date,
+                dec!(200),
+                PutOrCall::Call,
+                None
+            )?),
+            ParsedProduct::Option {
+                stem: "AAPL".into(),
+                expiration: date,
+                strike: dec!(200),
+                put_or_call: PutOrCall::Call,
+                venue_discriminant: None,
+                style: OptionSymbolStyle::Osi,
+            }
+        );
+        let long: Product = "AAPL US 20241220 200.5 C Option".parse()?;
+        let parsed = assert_roundtrip(&long);
+        assert!(matches!(
+            parsed,
+            ParsedProduct::Option { ref stem, strike, style: OptionSymbolStyle::Long, .. }
+                if stem == "AAPL US" && strike == dec!(200.5)
+        ));
+        assert_eq!(
+            assert_roundtrip(&Product::futures_spread(
+                ["ES", "NQ"],
+                Some([dec!(1), dec!(-2)]),
+                date,
+                Some("cme")
+            )?),
+            ParsedProduct::FuturesSpread {
+                legs: vec!["ES".into(), "NQ".into()],
+                ratios: Some(vec![dec!(1), dec!(-2)]),
+                expiration: date,
+                venue_discriminant: Some("CME".into()),
+            }
+        );
+        assert!("ES 2024122 CME Future".parse::<ParsedProduct>().is_err());
+        assert!("ES 20241220 CME Widget".parse::<ParsedProduct>().is_err());
+        assert!("ES/USD".parse::<ParsedProduct>().is_err());
+        Ok(())
+    }
+
+    /// Every constructor, with randomized components, round-trips through
+    /// parse and display.
+    #[test]
+    fn test_parse_roundtrip_property() -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let roots = ["ES", "NQ", "CL", "ZN", "6E", "BTC", "AAPL", "BRKB", "SPXW", "X"];
+        let venues = [None, Some("CME"), Some("CBOE"), Some("cfe"), Some("BINANCE")];
+        for _ in 0..2000 {
+            let root = *roots.choose(&mut rng).unwrap();
+            let venue = *venues.choose(&mut rng).unwrap();
+            let date = NaiveDate::from_ymd_opt(
+                rng.random_range(2000..2100),
+                rng.random_range(1..=12),
+                rng.random_range(1..=28),
+            )
+            .unwrap();
+            // strikes with up to three decimals, as OSI allows
+            let strike = Decimal::new(rng.random_range(1..100_000_000), 3).normalize();
+            let put_or_call =
+                if rng.random_bool(0.5) { PutOrCall::Put } else { PutOrCall::Call };
+            let n_legs = rng.random_range(1..4);
+            let legs: Vec<&str> =
+                (0..n_legs).map(|_| *roots.choose(&mut rng).unwrap()).collect();
+            let ratios: Vec<Decimal> =
+                (0..n_legs).map(|_| Decimal::from(rng.random_range(-3..=3))).collect();
+            let products = [
+                Product::fiat(root)?,
+                Product::commodity(root)?,
+                Product::crypto(root)?,
+                Product::index(root, venue)?,
+                Product::equity(root, "US")?,
+                Product::future(root, date, venue)?,
+                Product::perpetual(root, venue)?,
+                Product::option(root, date, strike, put_or_call, venue)?,
+                Product::futures_spread(
+                    legs.iter().copied(),
+                    Some(ratios.clone()),
+                    date,
+                    venue,
+                )?,
+                Product::futures_spread(
+                    legs.iter().copied(),
+                    None::<Vec<Decimal>>,
+                    date,
+                    venue,
+                )?,
+            ];
+            for product in &products {
+                let parsed = assert_roundtrip(product);
+                assert_eq!(
+                    parsed.venue_discriminant().map(str::to_string),
+                    match parsed {
+                        ParsedProduct::Fiat { .. }
+                        | ParsedProduct::Commodity { .. }
+                        | ParsedProduct::Crypto { .. }
+                        | ParsedProduct::Equity { .. } => None,
+                        _ => venue.map(str::to_uppercase),
+                    }
+                );
+            }
+            match assert_roundtrip(&products[7]) {
+                ParsedProduct::Option {
+                    stem, expiration, strike: parsed_strike, ..
+                } => {
+                    assert_eq!(stem, root);
+                    assert_eq!(expiration, date);
+                    assert_eq!(parsed_strike, strike);
+                }
+                parsed => panic!("expected option, got {parsed:?}"),
+            }
+        }
+        Ok(())
+    }
+}
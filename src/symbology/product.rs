@@ -152,6 +152,12 @@ impl Product {
         Self::new(&osi_symbol, venue_discriminant, "Option")
     }
 
+    /// Symbol is the series stem followed by the outcome, see
+    /// [`EventContractSeriesInfo::get_product`].
+    pub fn event_contract(symbol: &str) -> Result<Self> {
+        Self::new(symbol, None, "Event Contract")
+    }
+
     // pub fn is_series(&self) -> bool {
     //     self.0.ends_with("Option") || self.0.ends_with("Event Contract")
     // }
@@ -388,6 +394,10 @@ impl std::str::FromStr for SpreadLeg {
 pub enum AliasKind {
     CmeGlobex,
     Cfe,
+    /// OCC / OSI option symbols, see [`occ`](super::occ)
+    Occ,
+    /// CQG month-code futures symbols, see [`month_code`](super::month_code)
+    Cqg,
 }
 
 #[cfg(feature = "postgres")]
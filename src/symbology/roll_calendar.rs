@@ -0,0 +1,512 @@
+//! Futures roll calendars and continuous contract symbols.
+//!
+//! A [`RollCalendar`] orders the contracts of one futures series by
+//! expiration and decides which one is the front month at a given time
+//! according to a [`RollRule`].  Continuous symbols like `ES c1` (front
+//! month) or `ES c2` (next contract) are resolved to concrete products
+//! through [`ContinuousContracts`].  Roots listed on several venues are
+//! told apart by venue discriminant, e.g. `BTC CME c1`.
+
+use super::{ParsedProduct, Product, ProductInfo};
+use crate::marketdata::TickerValues;
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+/// When to roll from the front contract to the next one.  Day counts are
+/// calendar days.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RollRule {
+    /// Roll `days_before` days before expiration.
+    Expiration { days_before: u32 },
+    /// Roll `days_before` days before the first notice date, or before
+    /// expiration for contracts without one.
+    FirstNotice { days_before: u32 },
+    /// Roll once the next contract trades more session volume than the
+    /// front, and no later than `days_before` days before expiration.
+    VolumeCrossover { days_before: u32 },
+    /// Roll once the next contract has more open interest than the front,
+    /// and no later than `days_before` days before expiration.
+    OpenInterestCrossover { days_before: u32 },
+}
+
+impl Default for RollRule {
+    fn default() -> Self {
+        Self::Expiration { days_before: 0 }
+    }
+}
+
+impl RollRule {
+    /// Latest time at which `contract` may still be the front month.
+    pub fn roll_deadline(&self, contract: &FuturesContract) -> DateTime<Utc> {
+        match self {
+            Self::Expiration { days_before }
+            | Self::VolumeCrossover { days_before }
+            | Self::OpenInterestCrossover { days_before } => {
+                contract.expiration - Duration::days(*days_before as i64)
+            }
+            Self::FirstNotice { days_before } => {
+                let notice = contract
+                    .first_notice_date
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+                    .map(|dt| dt.and_utc())
+                    .map_or(contract.expiration, |dt| dt.min(contract.expiration));
+                notice - Duration::days(*days_before as i64)
+            }
+        }
+    }
+
+    fn crossover_metric(&self, ticker: &TickerValues) -> Option<Decimal> {
+        match self {
+            Self::Expiration { .. } | Self::FirstNotice { .. } => None,
+            Self::VolumeCrossover { .. } => ticker.session_volume.or(ticker.volume_24h),
+            Self::OpenInterestCrossover { .. } => ticker.open_interest,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct FuturesContract {
+    pub product: Product,
+    pub expiration: DateTime<Utc>,
+    pub first_notice_date: Option<NaiveDate>,
+}
+
+impl FuturesContract {
+    pub fn from_product_info(product: &Product, info: &ProductInfo) -> Option<Self> {
+        if !product.ends_with(" Future") {
+            return None;
+        }
+        Some(Self {
+            product: product.clone(),
+            expiration: info.expiration()?,
+            first_notice_date: info.first_notice_date(),
+        })
+    }
+}
+
+/// A roll from one contract to the next at `time`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct RollEvent {
+    pub time: DateTime<Utc>,
+    pub from: Product,
+    pub to: Product,
+}
+
+#[derive(Debug, Clone)]
+pub struct RollCalendar {
+    /// Sorted by expiration
+    contracts: Vec<FuturesContract>,
+    rule: RollRule,
+    /// Early roll taken on a crossover: the index rolled to and when.
+    /// Holds until the previous contract's roll deadline passes.
+    crossed: Option<(usize, DateTime<Utc>)>,
+}
+
+impl RollCalendar {
+    pub fn new(mut contracts: Vec<FuturesContract>, rule: RollRule) -> Self {
+        contracts.sort_by(|a, b| {
+            a.expiration.cmp(&b.expiration).then_with(|| a.product.cmp(&b.product))
+        });
+        contracts.dedup_by(|a, b| a.product == b.product);
+        Self { contracts, rule, crossed: None }
+    }
+
+    /// Calendar from the members of a futures series, e.g. from a
+    /// `FuturesSeriesResponse` joined with their product info.  Products
+    /// that aren't dated futures are skipped.
+    pub fn from_products<'a>(
+        products: impl IntoIterator<Item = (&'a Product, &'a ProductInfo)>,
+        rule: RollRule,
+    ) -> Self {
+        let contracts = products
+            .into_iter()
+            .filter_map(|(product, info)| {
+                FuturesContract::from_product_info(product, info)
+            })
+            .collect();
+        Self::new(contracts, rule)
+    }
+
+    pub fn rule(&self) -> RollRule {
+        self.rule
+    }
+
+    pub fn contracts(&self) -> &[FuturesContract] {
+        &self.contracts
+    }
+
+    /// Index of the front contract at `now`.  `ticker` supplies market
+    /// data for crossover rules; without it they roll at their deadline.
+    ///
+    /// A crossover roll is sticky: once the next contract overtakes the
+    /// front it stays the front month until the old front's deadline, even
+    /// if the metric flips back or market data goes missing.
+    fn front_index(
+        &mut self,
+        now: DateTime<Utc>,
+        ticker: impl Fn(&Product) -> Option<TickerValues>,
+    ) -> Option<usize> {
+        let i = self.contracts.iter().position(|c| self.rule.roll_deadline(c) > now)?;
+        match self.crossed {
+            Some((to, at)) if to == i + 1 && at <= now => return Some(to),
+            Some((to, _)) if to <= i => self.crossed = None,
+            _ => {}
+        }
+        if let Some(next) = self.contracts.get(i + 1) {
+            let metric = |c: &FuturesContract| {
+                ticker(&c.product).and_then(|t| self.rule.crossover_metric(&t))
+            };
+            if let (Some(front), Some(next)) = (metric(&self.contracts[i]), metric(next))
+            {
+                if next > front {
+                    self.crossed = Some((i + 1, now));
+                    return Some(i + 1);
+                }
+            }
+        }
+        Some(i)
+    }
+
+    /// The `n`th contract counting from the front month at `now`, with
+    /// `n = 1` being the front month itself.
+    pub fn nth(
+        &mut self,
+        n: u32,
+        now: DateTime<Utc>,
+        ticker: impl Fn(&Product) -> Option<TickerValues>,
+    ) -> Option<&FuturesContract> {
+        if n == 0 {
+            return None;
+        }
+        let front = self.front_index(now, ticker)?;
+        self.contracts.get(front + n as usize - 1)
+    }
+
+    pub fn front(
+        &mut self,
+        now: DateTime<Utc>,
+        ticker: impl Fn(&Product) -> Option<TickerValues>,
+    ) -> Option<&FuturesContract> {
+        self.nth(1, now, ticker)
+    }
+
+    pub fn next(
+        &mut self,
+        now: DateTime<Utc>,
+        ticker: impl Fn(&Product) -> Option<TickerValues>,
+    ) -> Option<&FuturesContract> {
+        self.nth(2, now, ticker)
+    }
+
+    /// Scheduled rolls between consecutive contracts, at each contract's
+    /// roll deadline.  Crossover rules may roll earlier in practice; this
+    /// schedule is what history is stitched on when no market data for
+    /// the crossover is available.
+    pub fn roll_schedule(&self) -> Vec<RollEvent> {
+        self.contracts
+            .windows(2)
+            .map(|pair| RollEvent {
+                time: self.rule.roll_deadline(&pair[0]),
+                from: pair[0].product.clone(),
+                to: pair[1].product.clone(),
+            })
+            .collect()
+    }
+}
+
+/// A continuous contract symbol, e.g. `ES c1` for the front month of ES
+/// and `ES c2` for the next contract.  A venue discriminant goes between
+/// the root and the position, as in `BTC CME c1`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, DeserializeFromStr, SerializeDisplay)]
+pub struct ContinuousSymbol {
+    pub root: String,
+    pub venue_discriminant: Option<String>,
+    /// 1-based position from the front month
+    pub n: u32,
+}
+
+crate::json_schema_is_string!(ContinuousSymbol);
+
+impl fmt::Display for ContinuousSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.venue_discriminant {
+            Some(venue) => write!(f, "{} {venue} c{}", self.root, self.n),
+            None => write!(f, "{} c{}", self.root, self.n),
+        }
+    }
+}
+
+impl FromStr for ContinuousSymbol {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (root, n) = s
+            .rsplit_once(' ')
+            .ok_or_else(|| anyhow!("invalid continuous symbol: {s}"))?;
+        let n: u32 = n
+            .strip_prefix('c')
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| anyhow!("invalid continuous symbol: {s}"))?;
+        let (root, venue_discriminant) = match root.split_once(' ') {
+            Some((root, venue)) => (root, Some(venue)),
+            None => (root, None),
+        };
+        if root.is_empty()
+            || venue_discriminant.is_some_and(|v| v.is_empty() || v.contains(' '))
+            || n == 0
+        {
+            bail!("invalid continuous symbol: {s}");
+        }
+        Ok(Self {
+            root: root.to_string(),
+            venue_discriminant: venue_discriminant.map(|v| v.to_uppercase()),
+            n,
+        })
+    }
+}
+
+/// Roll calendars by futures root and venue discriminant, for resolving
+/// continuous symbols.
+#[derive(Debug, Clone, Default)]
+pub struct ContinuousContracts {
+    calendars: BTreeMap<(String, Option<String>), RollCalendar>,
+}
+
+impl ContinuousContracts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Group all dated futures by root and venue discriminant, rolling
+    /// each with `rule`.
+    pub fn from_products<'a>(
+        products: impl IntoIterator<Item = (&'a Product, &'a ProductInfo)>,
+        rule: RollRule,
+    ) -> Self {
+        let mut by_key: BTreeMap<_, Vec<FuturesContract>> = BTreeMap::new();
+        for (product, info) in products {
+            let Ok(ParsedProduct::Future { root, venue_discriminant, .. }) =
+                product.parse()
+            else {
+                continue;
+            };
+            if let Some(contract) = FuturesContract::from_product_info(product, info) {
+                by_key.entry((root, venue_discriminant)).or_default().push(contract);
+            }
+        }
+        let calendars = by_key
+            .into_iter()
+            .map(|(key, contracts)| (key, RollCalendar::new(contracts, rule)))
+            .collect();
+        Self { calendars }
+    }
+
+    pub fn insert(
+        &mut self,
+        root: impl Into<String>,
+        venue_discriminant: Option<&str>,
+        calendar: RollCalendar,
+    ) {
+        let venue_discriminant = venue_discriminant.map(|v| v.to_uppercase());
+        self.calendars.insert((root.into(), venue_discriminant), calendar);
+    }
+
+    pub fn calendar(
+        &self,
+        root: &str,
+        venue_discriminant: Option<&str>,
+    ) -> Option<&RollCalendar> {
+        self.calendars.iter().find_map(|((r, v), calendar)| {
+            (r == root && v.as_deref() == venue_discriminant).then_some(calendar)
+        })
+    }
+
+    /// Calendar for `symbol`.  A symbol without a venue discriminant falls
+    /// back to the only venue listing its root, if there is just one.
+    fn calendar_mut(&mut self, symbol: &ContinuousSymbol) -> Result<&mut RollCalendar> {
+        let root = symbol.root.as_str();
+        let venue = symbol.venue_discriminant.as_deref();
+        let mut candidates = self
+            .calendars
+            .iter_mut()
+            .filter(|((r, _), _)| r == root)
+            .filter(|((_, v), _)| venue.is_none() || v.as_deref() == venue)
+            .collect::<Vec<_>>();
+        if let Some(i) = candidates.iter().position(|((_, v), _)| v.as_deref() == venue) {
+            return Ok(candidates.swap_remove(i).1);
+        }
+        match candidates.len() {
+            0 => bail!("no futures found for {symbol}"),
+            1 => Ok(candidates.pop().unwrap().1),
+            _ => bail!("{symbol} is listed on several venues, add a venue discriminant"),
+        }
+    }
+
+    /// Resolve a continuous symbol to the concrete contract at `now`.
+    /// Crossover rolls seen here stay in effect until the roll deadline.
+    pub fn resolve(
+        &mut self,
+        symbol: &ContinuousSymbol,
+        now: DateTime<Utc>,
+        ticker: impl Fn(&Product) -> Option<TickerValues>,
+    ) -> Result<&Product> {
+        let calendar = self.calendar_mut(symbol)?;
+        calendar
+            .nth(symbol.n, now, ticker)
+            .map(|contract| &contract.product)
+            .ok_or_else(|| anyhow!("{symbol} has no contract at {now}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbology::{DerivativeKind, ProductType};
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    fn contract(date: (i32, u32, u32), fnd: Option<(i32, u32, u32)>) -> FuturesContract {
+        let (y, m, d) = date;
+        FuturesContract {
+            product: Product::future(
+                "CL",
+                NaiveDate::from_ymd_opt(y, m, d).unwrap(),
+                None,
+            )
+            .unwrap(),
+            expiration: Utc.with_ymd_and_hms(y, m, d, 19, 30, 0).unwrap(),
+            first_notice_date: fnd.and_then(|(y, m, d)| NaiveDate::from_ymd_opt(y, m, d)),
+        }
+    }
+
+    fn contracts() -> Vec<FuturesContract> {
+        vec![
+            contract((2026, 2, 19), Some((2026, 2, 13))),
+            contract((2026, 1, 20), Some((2026, 1, 16))),
+            contract((2026, 3, 20), Some((2026, 3, 16))),
+        ]
+    }
+
+    #[test]
+    fn test_roll_rules() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 15, 0, 0, 0).unwrap();
+        let no_ticker = |_: &Product| None;
+        let mut calendar = RollCalendar::new(contracts(), RollRule::default());
+        assert_eq!(
+            calendar.front(now, no_ticker).unwrap().product,
+            contracts()[1].product
+        );
+        let mut calendar =
+            RollCalendar::new(contracts(), RollRule::FirstNotice { days_before: 7 });
+        assert_eq!(
+            calendar.front(now, no_ticker).unwrap().product,
+            contracts()[0].product
+        );
+        assert_eq!(
+            calendar.next(now, no_ticker).unwrap().product,
+            contracts()[2].product
+        );
+        let schedule = calendar.roll_schedule();
+        assert_eq!(schedule.len(), 2);
+        assert_eq!(schedule[0].time, Utc.with_ymd_and_hms(2026, 1, 9, 0, 0, 0).unwrap());
+        // volume crossover rolls early once the next contract is busier
+        let rule = RollRule::VolumeCrossover { days_before: 1 };
+        let feb = contracts()[0].product.clone();
+        let volume = |busy: &Product| {
+            let busy = busy.clone();
+            move |product: &Product| {
+                let volume = if *product == busy { dec!(500_000) } else { dec!(200_000) };
+                Some(TickerValues { session_volume: Some(volume), ..Default::default() })
+            }
+        };
+        let early = Utc.with_ymd_and_hms(2026, 1, 12, 0, 0, 0).unwrap();
+        let mut calendar = RollCalendar::new(contracts(), rule);
+        assert_eq!(
+            calendar.front(early, no_ticker).unwrap().product,
+            contracts()[1].product
+        );
+        assert_eq!(calendar.front(early, volume(&feb)).unwrap().product, feb);
+        // the roll sticks even when the volume flips back or goes missing
+        let later = early + Duration::days(1);
+        let jan = contracts()[1].product.clone();
+        assert_eq!(calendar.front(later, volume(&jan)).unwrap().product, feb);
+        assert_eq!(calendar.front(later, no_ticker).unwrap().product, feb);
+        assert_eq!(
+            calendar.next(later, no_ticker).unwrap().product,
+            contracts()[2].product
+        );
+        // but not before the time it was observed
+        let earlier = early - Duration::days(1);
+        assert_eq!(calendar.front(earlier, no_ticker).unwrap().product, jan);
+        // and it's dropped once the deadline passes anyway
+        let past_jan = Utc.with_ymd_and_hms(2026, 1, 20, 0, 0, 0).unwrap();
+        assert_eq!(calendar.front(past_jan, volume(&jan)).unwrap().product, feb);
+        assert_eq!(calendar.crossed, None);
+    }
+
+    #[test]
+    fn test_continuous_symbols() -> Result<()> {
+        let symbol: ContinuousSymbol = "CL c2".parse()?;
+        assert_eq!(
+            symbol,
+            ContinuousSymbol { root: "CL".into(), venue_discriminant: None, n: 2 }
+        );
+        assert_eq!(symbol.to_string(), "CL c2");
+        let on_venue: ContinuousSymbol = "BTC cme c1".parse()?;
+        assert_eq!(on_venue.venue_discriminant.as_deref(), Some("CME"));
+        assert_eq!(on_venue.to_string(), "BTC CME c1");
+        assert!("CL c0".parse::<ContinuousSymbol>().is_err());
+        assert!("CL".parse::<ContinuousSymbol>().is_err());
+        assert!("CL A B c1".parse::<ContinuousSymbol>().is_err());
+        let mut continuous = ContinuousContracts::new();
+        continuous.insert(
+            "CL",
+            None,
+            RollCalendar::new(contracts(), RollRule::default()),
+        );
+        let now = Utc.with_ymd_and_hms(2026, 1, 20, 20, 0, 0).unwrap();
+        assert_eq!(
+            continuous.resolve(&symbol, now, |_| None)?.as_str(),
+            "CL 20260320 Future"
+        );
+        assert!(continuous.resolve(&"ES c1".parse()?, now, |_| None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_continuous_contracts_by_venue() -> Result<()> {
+        let info = ProductInfo {
+            product_type: ProductType::Future {
+                series: None,
+                underlying: None,
+                multiplier: dec!(5),
+                expiration: Utc.with_ymd_and_hms(2026, 3, 27, 16, 0, 0).unwrap(),
+                derivative_kind: DerivativeKind::Linear,
+                first_notice_date: None,
+            },
+            primary_venue: None,
+            price_display_format: None,
+        };
+        let march = NaiveDate::from_ymd_opt(2026, 3, 27).unwrap();
+        let cme = Product::future("BTC", march, Some("CME"))?;
+        let cboe = Product::future("BTC", march, Some("CBOE"))?;
+        let mut continuous = ContinuousContracts::from_products(
+            [(&cme, &info), (&cboe, &info)],
+            RollRule::default(),
+        );
+        assert_eq!(continuous.calendar("BTC", Some("CME")).unwrap().contracts().len(), 1);
+        assert!(continuous.calendar("BTC", None).is_none());
+        let now = Utc.with_ymd_and_hms(2026, 3, 1, 0, 0, 0).unwrap();
+        assert_eq!(continuous.resolve(&"BTC CME c1".parse()?, now, |_| None)?, &cme);
+        assert_eq!(continuous.resolve(&"BTC CBOE c1".parse()?, now, |_| None)?, &cboe);
+        // ambiguous without a venue discriminant
+        assert!(continuous.resolve(&"BTC c1".parse()?, now, |_| None).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,419 @@
+//! In-memory search over a [`SymbologySnapshot`]: exact, prefix, substring
+//! and fuzzy matching on product symbols, aliases, exchange symbols and
+//! catalog descriptions, with filtering by product type, venue, expiry and
+//! underlying.
+
+use super::{
+    protocol::SymbologySnapshot, AliasKind, ExecutionVenue, Product, ProductInfo,
+    ProductType,
+};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Where a query matched, in decreasing order of relevance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchField {
+    Symbol,
+    Alias(AliasKind),
+    ExchangeSymbol(ExecutionVenue),
+    Description(ExecutionVenue),
+}
+
+impl SearchField {
+    fn weight(&self) -> u32 {
+        match self {
+            Self::Symbol => 100,
+            Self::Alias(_) => 95,
+            Self::ExchangeSymbol(_) => 90,
+            Self::Description(_) => 60,
+        }
+    }
+}
+
+/// How a query matched, in decreasing order of relevance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchKind {
+    Exact,
+    /// Matches a whole word, e.g. the root of a futures symbol
+    Word,
+    Prefix,
+    WordPrefix,
+    Substring,
+    /// Within the given edit distance of a word
+    Fuzzy(u32),
+}
+
+impl MatchKind {
+    fn score(&self) -> u32 {
+        match self {
+            Self::Exact => 100,
+            Self::Word => 90,
+            Self::Prefix => 80,
+            Self::WordPrefix => 60,
+            Self::Substring => 40,
+            Self::Fuzzy(distance) => 30u32.saturating_sub(10 * distance),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult<'a> {
+    pub product: &'a Product,
+    pub info: &'a ProductInfo,
+    pub field: SearchField,
+    pub kind: MatchKind,
+    /// The text that matched, in its original case
+    pub matched: &'a str,
+    /// Higher is better
+    pub score: u32,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SearchFilter {
+    /// Product type names, e.g. "Future" or "Option"; empty matches all
+    pub product_types: Vec<String>,
+    pub venue: Option<ExecutionVenue>,
+    pub expiring_after: Option<DateTime<Utc>>,
+    pub expiring_before: Option<DateTime<Utc>>,
+    pub underlying: Option<Product>,
+}
+
+impl SearchFilter {
+    pub fn product_type(mut self, product_type: impl Into<String>) -> Self {
+        self.product_types.push(product_type.into());
+        self
+    }
+
+    pub fn venue(mut self, venue: ExecutionVenue) -> Self {
+        self.venue = Some(venue);
+        self
+    }
+
+    pub fn expiring_between(
+        mut self,
+        after: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
+    ) -> Self {
+        self.expiring_after = after;
+        self.expiring_before = before;
+        self
+    }
+
+    pub fn underlying(mut self, underlying: Product) -> Self {
+        self.underlying = Some(underlying);
+        self
+    }
+}
+
+struct IndexedProduct<'a> {
+    product: &'a Product,
+    info: &'a ProductInfo,
+    venues: BTreeSet<&'a ExecutionVenue>,
+    underlying: Option<&'a Product>,
+}
+
+struct Entry<'a> {
+    product: usize,
+    field: SearchField,
+    text: &'a str,
+}
+
+pub struct SymbologyIndex<'a> {
+    products: Vec<IndexedProduct<'a>>,
+    entries: Vec<Entry<'a>>,
+    /// lowercased text -> entries, for exact and prefix lookups
+    keys: BTreeMap<String, Vec<usize>>,
+    /// lowercased word -> entries, for word prefix and fuzzy lookups
+    words: BTreeMap<String, Vec<usize>>,
+}
+
+impl<'a> SymbologyIndex<'a> {
+    pub fn new(snapshot: &'a SymbologySnapshot) -> Self {
+        let mut index = Self {
+            products: vec![],
+            entries: vec![],
+            keys: BTreeMap::new(),
+            words: BTreeMap::new(),
+        };
+        let mut positions = BTreeMap::new();
+        for (product, info) in &snapshot.products {
+            let underlying = match &info.product_type {
+                ProductType::Option { series, .. } => {
+                    snapshot.options_series.get(series).map(|s| &s.underlying)
+                }
+                _ => info.underlying(),
+            };
+            positions.insert(product, index.products.len());
+            index.products.push(IndexedProduct {
+                product,
+                info,
+                venues: BTreeSet::new(),
+                underlying,
+            });
+            index.add(positions[product], SearchField::Symbol, product.as_str());
+        }
+        for (kind, aliases) in &snapshot.product_aliases {
+            for (alias, product) in aliases {
+                if let Some(i) = positions.get(product) {
+                    index.add(*i, SearchField::Alias(*kind), alias);
+                }
+            }
+        }
+        for (tradable, infos) in &snapshot.execution_info {
+            let Some(i) = positions.get(&tradable.base()).copied() else { continue };
+            for (venue, info) in infos {
+                index.products[i].venues.insert(venue);
+                if let Some(exchange_symbol) = &info.exchange_symbol {
+                    index.add(
+                        i,
+                        SearchField::ExchangeSymbol(venue.clone()),
+                        exchange_symbol,
+                    );
+                }
+            }
+        }
+        // catalog entries describe a product root on a venue, e.g. "ES"
+        let mut by_root: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (i, p) in index.products.iter().enumerate() {
+            if let Some(root) = p.product.split(' ').next() {
+                by_root.entry(root).or_default().push(i);
+            }
+        }
+        for (venue, catalog) in &snapshot.product_catalog {
+            for (exchange_product, info) in catalog {
+                let root = info.product_root().unwrap_or(exchange_product);
+                for i in by_root.get(root).into_iter().flatten() {
+                    for text in [&info.short_description, &info.long_description]
+                        .into_iter()
+                        .flatten()
+                    {
+                        index.add(*i, SearchField::Description(venue.clone()), text);
+                    }
+                }
+            }
+        }
+        index
+    }
+
+    fn add(&mut self, product: usize, field: SearchField, text: &'a str) {
+        let entry = self.entries.len();
+        self.entries.push(Entry { product, field, text });
+        let key = text.to_lowercase();
+        for word in key.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+            self.words.entry(word.to_string()).or_default().push(entry);
+        }
+        self.keys.entry(key).or_default().push(entry);
+    }
+
+    /// Search for `query`, returning at most `limit` products with their
+    /// best match, best first.  Equally good matches are ordered by
+    /// expiration, nearest first, so "ES" lists the front contract first.
+    pub fn search(
+        &self,
+        query: &str,
+        filter: &SearchFilter,
+        limit: usize,
+    ) -> Vec<SearchResult<'a>> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return vec![];
+        }
+        let mut best: BTreeMap<usize, (u32, usize, MatchKind)> = BTreeMap::new();
+        let mut consider = |entry: usize, kind: MatchKind| {
+            let e = &self.entries[entry];
+            let score = e.field.weight() * kind.score();
+            let slot = best.entry(e.product).or_insert((0, entry, kind));
+            if score > slot.0 {
+                *slot = (score, entry, kind);
+            }
+        };
+        for (key, entries) in self.keys.range(query.clone()..) {
+            if !key.starts_with(&query) {
+                break;
+            }
+            let kind = if *key == query { MatchKind::Exact } else { MatchKind::Prefix };
+            entries.iter().for_each(|e| consider(*e, kind));
+        }
+        for (word, entries) in self.words.range(query.clone()..) {
+            if !word.starts_with(&query) {
+                break;
+            }
+            let kind =
+                if *word == query { MatchKind::Word } else { MatchKind::WordPrefix };
+            entries.iter().for_each(|e| consider(*e, kind));
+        }
+        if query.len() >= 3 {
+            for (key, entries) in &self.keys {
+                if key.contains(&query) {
+                    entries.iter().for_each(|e| consider(*e, MatchKind::Substring));
+                }
+            }
+        }
+        let max_distance = (query.chars().count() / 4).clamp(1, 2);
+        if query.chars().count() >= 3 {
+            for (word, entries) in &self.words {
+                let distance = edit_distance(&query, word);
+                if distance > 0 && distance <= max_distance {
+                    let kind = MatchKind::Fuzzy(distance as u32);
+                    entries.iter().for_each(|e| consider(*e, kind));
+                }
+            }
+        }
+        let mut results: Vec<_> = best
+            .into_iter()
+            .filter(|(i, _)| self.matches_filter(&self.products[*i], filter))
+            .map(|(i, (score, entry, kind))| {
+                let p = &self.products[i];
+                let e = &self.entries[entry];
+                SearchResult {
+                    product: p.product,
+                    info: p.info,
+                    field: e.field.clone(),
+                    kind,
+                    matched: e.text,
+                    score,
+                }
+            })
+            .collect();
+        results.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.info.expiration().cmp(&b.info.expiration()))
+                .then_with(|| a.product.cmp(b.product))
+        });
+        results.truncate(limit);
+        results
+    }
+
+    fn matches_filter(&self, p: &IndexedProduct, filter: &SearchFilter) -> bool {
+        if !filter.product_types.is_empty() {
+            let product_type: &'static str = (&p.info.product_type).into();
+            if !filter.product_types.iter().any(|t| t == product_type) {
+                return false;
+            }
+        }
+        if filter.venue.as_ref().is_some_and(|venue| !p.venues.contains(venue)) {
+            return false;
+        }
+        if filter.expiring_after.is_some() || filter.expiring_before.is_some() {
+            let Some(expiration) = p.info.expiration() else {
+                return false;
+            };
+            if filter.expiring_after.is_some_and(|after| expiration < after)
+                || filter.expiring_before.is_some_and(|before| expiration >= before)
+            {
+                return false;
+            }
+        }
+        if filter.underlying.is_some() && filter.underlying.as_ref() != p.underlying {
+            return false;
+        }
+        true
+    }
+}
+
+/// Levenshtein distance between two strings, by chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != *cb);
+            curr.push(substitute.min(prev[j + 1] + 1).min(curr[j] + 1));
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbology::{DerivativeKind, ProductCatalogInfo};
+    use chrono::{NaiveDate, TimeZone};
+    use rust_decimal_macros::dec;
+
+    fn future(root: &str, y: i32, m: u32, d: u32) -> (Product, ProductInfo) {
+        let product =
+            Product::future(root, NaiveDate::from_ymd_opt(y, m, d).unwrap(), Some("CME"))
+                .unwrap();
+        let info = ProductInfo {
+            product_type: ProductType::Future {
+                series: None,
+                underlying: None,
+                multiplier: dec!(50),
+                expiration: Utc.with_ymd_and_hms(y, m, d, 14, 30, 0).unwrap(),
+                derivative_kind: DerivativeKind::Linear,
+                first_notice_date: None,
+            },
+            primary_venue: Some("CME".into()),
+            price_display_format: None,
+        };
+        (product, info)
+    }
+
+    #[test]
+    fn test_search() -> anyhow::Result<()> {
+        let mut snapshot = SymbologySnapshot::default();
+        snapshot.products.extend([
+            future("ES", 2026, 3, 20),
+            future("ES", 2025, 12, 19),
+            future("ESTR", 2026, 3, 18),
+            future("NQ", 2025, 12, 19),
+        ]);
+        let aapl = Product::equity("AAPL", "US")?;
+        snapshot.products.insert(
+            aapl.clone(),
+            ProductInfo {
+                product_type: ProductType::Equity { easy_to_borrow: None },
+                primary_venue: None,
+                price_display_format: None,
+            },
+        );
+        let es_dec = future("ES", 2025, 12, 19).0;
+        snapshot
+            .product_aliases
+            .entry(AliasKind::CmeGlobex)
+            .or_default()
+            .insert("ESZ5".into(), es_dec.clone());
+        let catalog: ProductCatalogInfo = serde_json::from_value(serde_json::json!({
+            "exchange": "CME",
+            "exchange_product": "NQ",
+            "short_description": "E-mini Nasdaq-100",
+        }))?;
+        snapshot
+            .product_catalog
+            .entry("CME".parse()?)
+            .or_default()
+            .insert("NQ".into(), catalog);
+        let index = SymbologyIndex::new(&snapshot);
+        let filter = SearchFilter::default();
+        // "type ES, get contracts": ES contracts in expiration order first
+        let results = index.search("ES", &filter, 10);
+        let symbols: Vec<_> = results.iter().map(|r| r.product.as_str()).collect();
+        assert_eq!(
+            symbols[..3],
+            [
+                "ES 20251219 CME Future",
+                "ES 20260320 CME Future",
+                "ESTR 20260318 CME Future"
+            ]
+        );
+        let results = index.search("esz5", &filter, 10);
+        assert_eq!(results[0].product, &es_dec);
+        assert_eq!(results[0].kind, MatchKind::Exact);
+        // fuzzy match on catalog descriptions
+        let results = index.search("nasdq", &filter, 10);
+        assert_eq!(results[0].product.as_str(), "NQ 20251219 CME Future");
+        assert!(matches!(results[0].field, SearchField::Description(_)));
+        let filter = SearchFilter::default().product_type("Future").expiring_between(
+            Some(Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap()),
+            None,
+        );
+        let results = index.search("es", &filter, 10);
+        assert_eq!(results.len(), 2);
+        assert!(index.search("aapl", &filter, 10).is_empty());
+        Ok(())
+    }
+}
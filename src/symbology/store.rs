@@ -0,0 +1,582 @@
+//! A [`SymbologySnapshot`] kept current from a stream of [`SymbologyUpdate`]s.
+//!
+//! The store checks sequence continuity, notifies subscribers of added,
+//! removed and changed products, and retains a bounded log of applied
+//! updates so that the symbology can be reconstructed as of any sequence
+//! number still in the log.
+
+use super::{
+    diff::same_value,
+    protocol::{SnapshotOrUpdate, SymbologySnapshot, SymbologyUpdate},
+    Product, ProductInfo,
+};
+use crate::SequenceIdAndNumber;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+pub const DEFAULT_MAX_LOG_LEN: usize = 1000;
+
+/// Errors raised while applying updates to a [`SymbologyStore`].
+///
+/// On any error the store is left untouched; the client should resubscribe
+/// or re-request a full snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbologyStoreError {
+    /// A partial update was received before any full snapshot.
+    NoSnapshot,
+    /// The update skipped one or more sequence numbers.
+    SequenceGap { expected: SequenceIdAndNumber, received: SequenceIdAndNumber },
+    /// The update is at or behind the current sequence number.
+    StaleUpdate { current: SequenceIdAndNumber, received: SequenceIdAndNumber },
+    /// The sequence id changed without a full snapshot.
+    SequenceReset { current: SequenceIdAndNumber, received: SequenceIdAndNumber },
+    /// The requested sequence number is no longer (or not yet) in the log.
+    NotRetained { requested: u64, oldest: u64, latest: u64 },
+}
+
+impl std::fmt::Display for SymbologyStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoSnapshot => write!(f, "received partial update before snapshot"),
+            Self::SequenceGap { expected, received } => {
+                write!(f, "sequence gap: expected {expected}, received {received}")
+            }
+            Self::StaleUpdate { current, received } => {
+                write!(f, "stale update: current {current}, received {received}")
+            }
+            Self::SequenceReset { current, received } => {
+                write!(f, "sequence reset: current {current}, received {received}")
+            }
+            Self::NotRetained { requested, oldest, latest } => {
+                write!(
+                    f,
+                    "sequence number {requested} not retained, log covers {oldest}..={latest}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SymbologyStoreError {}
+
+#[derive(Debug, Clone)]
+pub enum ProductChange {
+    Added { product: Product, info: ProductInfo },
+    Removed { product: Product, info: ProductInfo },
+    Changed { product: Product, old: ProductInfo, new: ProductInfo },
+}
+
+impl ProductChange {
+    pub fn product(&self) -> &Product {
+        match self {
+            Self::Added { product, .. }
+            | Self::Removed { product, .. }
+            | Self::Changed { product, .. } => product,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChangeLogEntry {
+    pub sequence: SequenceIdAndNumber,
+    pub update: SymbologyUpdate,
+    pub changes: Vec<ProductChange>,
+}
+
+pub type SubscriptionId = u64;
+
+type Subscriber = Box<dyn FnMut(&ProductChange) + Send>;
+
+pub struct SymbologyStore {
+    snapshot: SymbologySnapshot,
+    initialized: bool,
+    /// The symbology just before the oldest update in the log
+    base: SymbologySnapshot,
+    log: VecDeque<ChangeLogEntry>,
+    max_log_len: usize,
+    subscribers: Vec<(SubscriptionId, Subscriber)>,
+    next_subscription_id: SubscriptionId,
+}
+
+impl Default for SymbologyStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for SymbologyStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SymbologyStore")
+            .field("sequence", &self.sequence())
+            .field("products", &self.snapshot.products.len())
+            .field("log", &self.log.len())
+            .field("subscribers", &self.subscribers.len())
+            .finish()
+    }
+}
+
+impl SymbologyStore {
+    pub fn new() -> Self {
+        Self::with_max_log_len(DEFAULT_MAX_LOG_LEN)
+    }
+
+    /// Retain at most `max_log_len` updates for point-in-time queries; at
+    /// least one update is always retained.
+    pub fn with_max_log_len(max_log_len: usize) -> Self {
+        Self {
+            snapshot: SymbologySnapshot::default(),
+            initialized: false,
+            base: SymbologySnapshot::default(),
+            log: VecDeque::new(),
+            max_log_len: max_log_len.max(1),
+            subscribers: vec![],
+            next_subscription_id: 0,
+        }
+    }
+
+    pub fn from_snapshot(snapshot: SymbologySnapshot) -> Self {
+        let mut store = Self::new();
+        store.apply_snapshot(snapshot);
+        store
+    }
+
+    pub fn snapshot(&self) -> &SymbologySnapshot {
+        &self.snapshot
+    }
+
+    /// None until the first full snapshot is applied
+    pub fn sequence(&self) -> Option<SequenceIdAndNumber> {
+        self.initialized.then_some(self.snapshot.sequence)
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
+    /// Applied updates, oldest first
+    pub fn log(&self) -> impl Iterator<Item = &ChangeLogEntry> {
+        self.log.iter()
+    }
+
+    /// Register a callback invoked for every product added, removed or
+    /// changed by subsequently applied updates.
+    pub fn subscribe(
+        &mut self,
+        f: impl FnMut(&ProductChange) + Send + 'static,
+    ) -> SubscriptionId {
+        let id = self.next_subscription_id;
+        self.next_subscription_id += 1;
+        self.subscribers.push((id, Box::new(f)));
+        id
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let len = self.subscribers.len();
+        self.subscribers.retain(|(sid, _)| *sid != id);
+        self.subscribers.len() != len
+    }
+
+    /// Replace the contents of the store with the snapshot, starting a new
+    /// change log.  Subscribers are notified of the difference from the
+    /// previous contents.
+    pub fn apply_snapshot(&mut self, snapshot: SymbologySnapshot) -> &[ProductChange] {
+        self.reset(snapshot_to_update(snapshot))
+    }
+
+    /// Apply an update if it is next in sequence, or if it is a full
+    /// snapshot (every section in snapshot form) starting a new sequence.
+    /// Returns the product changes it caused.
+    pub fn apply_update(
+        &mut self,
+        update: SymbologyUpdate,
+    ) -> Result<&[ProductChange], SymbologyStoreError> {
+        let received = update.sequence;
+        if !self.initialized {
+            return if is_full_snapshot(&update) {
+                Ok(self.reset(update))
+            } else {
+                Err(SymbologyStoreError::NoSnapshot)
+            };
+        }
+        let current = self.snapshot.sequence;
+        if received.sequence_id != current.sequence_id {
+            return if is_full_snapshot(&update) {
+                Ok(self.reset(update))
+            } else {
+                Err(SymbologyStoreError::SequenceReset { current, received })
+            };
+        }
+        if received.sequence_number <= current.sequence_number {
+            return Err(SymbologyStoreError::StaleUpdate { current, received });
+        }
+        if !received.is_next_in_sequence(&current) {
+            return Err(SymbologyStoreError::SequenceGap {
+                expected: current.next(),
+                received,
+            });
+        }
+        while self.log.len() >= self.max_log_len {
+            if let Some(oldest) = self.log.pop_front() {
+                apply_to(&mut self.base, oldest.update);
+            }
+        }
+        let changes = apply_to(&mut self.snapshot, update.clone());
+        Ok(self.record(update, changes))
+    }
+
+    fn reset(&mut self, update: SymbologyUpdate) -> &[ProductChange] {
+        let mut snapshot = SymbologySnapshot {
+            products: std::mem::take(&mut self.snapshot.products),
+            ..Default::default()
+        };
+        let changes = apply_to(&mut snapshot, update.clone());
+        self.snapshot = snapshot;
+        self.initialized = true;
+        // the log starts at the snapshot itself, so that it can be replayed
+        // onto an empty base
+        self.base = SymbologySnapshot::default();
+        self.log.clear();
+        self.record(update, changes)
+    }
+
+    fn record(
+        &mut self,
+        update: SymbologyUpdate,
+        changes: Vec<ProductChange>,
+    ) -> &[ProductChange] {
+        for change in &changes {
+            for (_, f) in &mut self.subscribers {
+                f(change);
+            }
+        }
+        self.log.push_back(ChangeLogEntry { sequence: update.sequence, update, changes });
+        &self.log.back().unwrap().changes
+    }
+
+    /// The symbology as of the given sequence number in the current
+    /// sequence, reconstructed from the change log.
+    pub fn as_of(
+        &self,
+        sequence_number: u64,
+    ) -> Result<SymbologySnapshot, SymbologyStoreError> {
+        let (Some(oldest), Some(latest)) = (self.log.front(), self.log.back()) else {
+            return Err(SymbologyStoreError::NoSnapshot);
+        };
+        let oldest = oldest.sequence.sequence_number;
+        let latest = latest.sequence.sequence_number;
+        if sequence_number < oldest || sequence_number > latest {
+            return Err(SymbologyStoreError::NotRetained {
+                requested: sequence_number,
+                oldest,
+                latest,
+            });
+        }
+        if sequence_number == latest {
+            return Ok(self.snapshot.clone());
+        }
+        let mut snapshot = self.base.clone();
+        for entry in &self.log {
+            if entry.sequence.sequence_number > sequence_number {
+                break;
+            }
+            apply_to(&mut snapshot, entry.update.clone());
+        }
+        Ok(snapshot)
+    }
+
+    /// Write the retained log (and the base it applies to) as JSON.
+    pub fn write_to(&self, writer: impl Write) -> Result<()> {
+        if !self.initialized {
+            bail!("symbology store has no snapshot");
+        }
+        let persisted = PersistedStoreRef {
+            base: &self.base,
+            updates: self.log.iter().map(|entry| &entry.update).collect(),
+        };
+        serde_json::to_writer(writer, &persisted)?;
+        Ok(())
+    }
+
+    /// Read a store written by [`Self::write_to`], replaying its log.
+    pub fn read_from(reader: impl Read) -> Result<Self> {
+        let persisted: PersistedStore = serde_json::from_reader(reader)?;
+        let mut store =
+            Self::with_max_log_len(persisted.updates.len().max(DEFAULT_MAX_LOG_LEN));
+        let mut updates = persisted.updates.into_iter();
+        let Some(first) = updates.next() else {
+            bail!("persisted symbology store has no updates");
+        };
+        store.base = persisted.base.clone();
+        store.snapshot = persisted.base;
+        store.initialized = true;
+        let changes = apply_to(&mut store.snapshot, first.clone());
+        store.record(first, changes);
+        for update in updates {
+            store.apply_update(update)?;
+        }
+        Ok(store)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        Ok(writer.flush()?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+#[derive(Serialize)]
+struct PersistedStoreRef<'a> {
+    base: &'a SymbologySnapshot,
+    updates: Vec<&'a SymbologyUpdate>,
+}
+
+#[derive(Deserialize)]
+struct PersistedStore {
+    base: SymbologySnapshot,
+    updates: Vec<SymbologyUpdate>,
+}
+
+fn snapshot_to_update(snapshot: SymbologySnapshot) -> SymbologyUpdate {
+    SymbologyUpdate {
+        sequence: snapshot.sequence,
+        products: Some(snapshot.products.into()),
+        product_aliases: Some(snapshot.product_aliases.into()),
+        product_catalog: Some(snapshot.product_catalog.into()),
+        options_series: Some(snapshot.options_series.into()),
+        execution_info: Some(snapshot.execution_info.into()),
+    }
+}
+
+/// Every section is present in snapshot form; aliases and the product
+/// catalog may be omitted, in which case they are treated as empty.
+fn is_full_snapshot(update: &SymbologyUpdate) -> bool {
+    fn is_snapshot<K: Eq + Ord, V>(u: &Option<SnapshotOrUpdate<K, V>>) -> bool {
+        matches!(u, Some(SnapshotOrUpdate::Snapshot { .. }))
+    }
+    is_snapshot(&update.products)
+        && is_snapshot(&update.options_series)
+        && is_snapshot(&update.execution_info)
+        && (update.product_aliases.is_none() || is_snapshot(&update.product_aliases))
+        && (update.product_catalog.is_none() || is_snapshot(&update.product_catalog))
+}
+
+fn apply_to(
+    snapshot: &mut SymbologySnapshot,
+    update: SymbologyUpdate,
+) -> Vec<ProductChange> {
+    snapshot.sequence = update.sequence;
+    let changes = match update.products {
+        Some(products) => apply_products(&mut snapshot.products, products),
+        None => vec![],
+    };
+    if let Some(aliases) = update.product_aliases {
+        aliases.apply2(&mut snapshot.product_aliases);
+    }
+    if let Some(catalog) = update.product_catalog {
+        catalog.apply2(&mut snapshot.product_catalog);
+    }
+    if let Some(options_series) = update.options_series {
+        options_series.apply(&mut snapshot.options_series);
+    }
+    if let Some(execution_info) = update.execution_info {
+        execution_info.apply2(&mut snapshot.execution_info);
+    }
+    changes
+}
+
+fn apply_products(
+    products: &mut BTreeMap<Product, ProductInfo>,
+    update: SnapshotOrUpdate<Product, ProductInfo>,
+) -> Vec<ProductChange> {
+    let mut changes = vec![];
+    match update {
+        SnapshotOrUpdate::Snapshot { snapshot } => {
+            let old = std::mem::replace(products, snapshot);
+            for (product, info) in &old {
+                match products.get(product) {
+                    None => changes.push(ProductChange::Removed {
+                        product: product.clone(),
+                        info: info.clone(),
+                    }),
+                    Some(new) if !same_value(info, new) => {
+                        changes.push(ProductChange::Changed {
+                            product: product.clone(),
+                            old: info.clone(),
+                            new: new.clone(),
+                        })
+                    }
+                    Some(_) => {}
+                }
+            }
+            for (product, info) in products.iter() {
+                if !old.contains_key(product) {
+                    changes.push(ProductChange::Added {
+                        product: product.clone(),
+                        info: info.clone(),
+                    });
+                }
+            }
+        }
+        SnapshotOrUpdate::Update { updates } => {
+            for (product, info) in updates {
+                let change = match info {
+                    Some(info) => match products.insert(product.clone(), info.clone()) {
+                        None => Some(ProductChange::Added { product, info }),
+                        Some(old) if !same_value(&old, &info) => {
+                            Some(ProductChange::Changed { product, old, new: info })
+                        }
+                        Some(_) => None,
+                    },
+                    None => products
+                        .remove(&product)
+                        .map(|info| ProductChange::Removed { product, info }),
+                };
+                changes.extend(change);
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbology::{DerivativeKind, ProductType};
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::sync::{Arc, Mutex};
+
+    fn future(root: &str, m: u32, multiplier: Decimal) -> (Product, ProductInfo) {
+        let product = Product::future(
+            root,
+            NaiveDate::from_ymd_opt(2026, m, 20).unwrap(),
+            Some("CME"),
+        )
+        .unwrap();
+        let info = ProductInfo {
+            product_type: ProductType::Future {
+                series: None,
+                underlying: None,
+                multiplier,
+                expiration: Utc.with_ymd_and_hms(2026, m, 20, 14, 30, 0).unwrap(),
+                derivative_kind: DerivativeKind::Linear,
+                first_notice_date: None,
+            },
+            primary_venue: Some("CME".into()),
+            price_display_format: None,
+        };
+        (product, info)
+    }
+
+    fn update(sn: u64, products: Vec<(Product, Option<ProductInfo>)>) -> SymbologyUpdate {
+        SymbologyUpdate {
+            sequence: SequenceIdAndNumber::new(7, sn),
+            products: Some(SnapshotOrUpdate::Update { updates: products }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_symbology_store() -> Result<()> {
+        let (es_h, es_h_info) = future("ES", 3, dec!(50));
+        let (es_m, es_m_info) = future("ES", 6, dec!(50));
+        let (nq_h, nq_h_info) = future("NQ", 3, dec!(20));
+        let mut store = SymbologyStore::new();
+        assert_eq!(
+            store.apply_update(update(1, vec![])).unwrap_err(),
+            SymbologyStoreError::NoSnapshot
+        );
+        let seen = Arc::new(Mutex::new(vec![]));
+        let seen_cb = seen.clone();
+        store.subscribe(move |change| {
+            let kind = match change {
+                ProductChange::Added { .. } => "added",
+                ProductChange::Removed { .. } => "removed",
+                ProductChange::Changed { .. } => "changed",
+            };
+            seen_cb.lock().unwrap().push(format!("{kind} {}", change.product()));
+        });
+        let mut snapshot = SymbologySnapshot {
+            sequence: SequenceIdAndNumber::new(7, 10),
+            ..Default::default()
+        };
+        snapshot.products.insert(es_h.clone(), es_h_info.clone());
+        store.apply_snapshot(snapshot);
+        store.apply_update(update(11, vec![(es_m.clone(), Some(es_m_info))]))?;
+        let (_, mut es_h_changed) = future("ES", 3, dec!(5));
+        es_h_changed.primary_venue = None;
+        store.apply_update(update(
+            12,
+            vec![(es_h.clone(), Some(es_h_changed)), (nq_h.clone(), Some(nq_h_info))],
+        ))?;
+        store.apply_update(update(13, vec![(es_m.clone(), None)]))?;
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                "added ES 20260320 CME Future",
+                "added ES 20260620 CME Future",
+                "changed ES 20260320 CME Future",
+                "added NQ 20260320 CME Future",
+                "removed ES 20260620 CME Future",
+            ]
+        );
+        // continuity
+        assert_eq!(
+            store.apply_update(update(15, vec![])).unwrap_err(),
+            SymbologyStoreError::SequenceGap {
+                expected: SequenceIdAndNumber::new(7, 14),
+                received: SequenceIdAndNumber::new(7, 15),
+            }
+        );
+        assert!(matches!(
+            store.apply_update(update(13, vec![])),
+            Err(SymbologyStoreError::StaleUpdate { .. })
+        ));
+        assert_eq!(store.sequence(), Some(SequenceIdAndNumber::new(7, 13)));
+        // point-in-time
+        let as_of_11 = store.as_of(11)?;
+        assert_eq!(as_of_11.sequence.sequence_number, 11);
+        assert!(as_of_11.products.contains_key(&es_m));
+        assert!(!as_of_11.products.contains_key(&nq_h));
+        assert_eq!(as_of_11.products[&es_h].multiplier(), Some(dec!(50)));
+        assert!(matches!(
+            store.as_of(9),
+            Err(SymbologyStoreError::NotRetained { oldest: 10, latest: 13, .. })
+        ));
+        // persistence
+        let mut buf = vec![];
+        store.write_to(&mut buf)?;
+        let loaded = SymbologyStore::read_from(buf.as_slice())?;
+        assert_eq!(loaded.sequence(), store.sequence());
+        assert_eq!(loaded.snapshot().products.keys().collect::<Vec<_>>(), [&es_h, &nq_h]);
+        assert!(loaded.as_of(12)?.products.contains_key(&es_m));
+        Ok(())
+    }
+
+    #[test]
+    fn test_symbology_store_log_trimming() -> Result<()> {
+        let (es_h, es_h_info) = future("ES", 3, dec!(50));
+        let mut store = SymbologyStore::with_max_log_len(2);
+        store.apply_snapshot(SymbologySnapshot {
+            sequence: SequenceIdAndNumber::new(7, 0),
+            ..Default::default()
+        });
+        store.apply_update(update(1, vec![(es_h.clone(), Some(es_h_info))]))?;
+        store.apply_update(update(2, vec![(es_h.clone(), None)]))?;
+        store.apply_update(update(3, vec![]))?;
+        assert!(store.as_of(1).is_err());
+        assert!(!store.as_of(2)?.products.contains_key(&es_h));
+        Ok(())
+    }
+}
@@ -0,0 +1,357 @@
+//! Exchange trading calendars: recurring sessions and breaks in the
+//! venue's local time zone, plus holidays, early closes and one-off
+//! maintenance windows.
+//!
+//! Sessions are identified by their trading date.  A session whose close
+//! is at or before its open, like CME Globex 17:00-16:00 Chicago time,
+//! opens on the calendar day before its trading date.
+
+use super::{ExecutionVenue, Product};
+use crate::{marketdata::candle_width::resolve_local, orderflow::TimeInForce};
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// How far ahead to search for the next session before giving up.
+const MAX_SEARCH_DAYS: u64 = 370;
+
+/// A recurring session, in the calendar's local time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct TradingSession {
+    /// Weekdays of the trading dates this session runs on
+    pub days: Vec<Weekday>,
+    pub open: NaiveTime,
+    /// If at or before `open`, the session opens the previous day
+    pub close: NaiveTime,
+    /// (start, end) of intraday halts, e.g. a daily maintenance break
+    #[serde(default)]
+    pub breaks: Vec<(NaiveTime, NaiveTime)>,
+}
+
+impl TradingSession {
+    pub fn weekdays(open: NaiveTime, close: NaiveTime) -> Self {
+        use Weekday::*;
+        Self { days: vec![Mon, Tue, Wed, Thu, Fri], open, close, breaks: vec![] }
+    }
+
+    pub fn with_break(mut self, start: NaiveTime, end: NaiveTime) -> Self {
+        self.breaks.push((start, end));
+        self
+    }
+
+    fn is_overnight(&self) -> bool {
+        self.close <= self.open
+    }
+}
+
+/// A one-off period during which the venue is closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct MaintenanceWindow {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct TradingCalendar {
+    #[schemars(with = "String")]
+    pub time_zone: Tz,
+    pub sessions: Vec<TradingSession>,
+    /// Trading dates without any session
+    #[serde(default)]
+    pub holidays: BTreeSet<NaiveDate>,
+    /// Trading dates closing early, at the given local time
+    #[serde(default)]
+    pub early_closes: BTreeMap<NaiveDate, NaiveTime>,
+    #[serde(default)]
+    pub maintenance_windows: Vec<MaintenanceWindow>,
+}
+
+impl TradingCalendar {
+    pub fn new(time_zone: Tz, sessions: Vec<TradingSession>) -> Self {
+        Self {
+            time_zone,
+            sessions,
+            holidays: BTreeSet::new(),
+            early_closes: BTreeMap::new(),
+            maintenance_windows: vec![],
+        }
+    }
+
+    pub fn with_holidays(
+        mut self,
+        holidays: impl IntoIterator<Item = NaiveDate>,
+    ) -> Self {
+        self.holidays.extend(holidays);
+        self
+    }
+
+    pub fn with_early_close(mut self, date: NaiveDate, close: NaiveTime) -> Self {
+        self.early_closes.insert(date, close);
+        self
+    }
+
+    pub fn with_maintenance_window(
+        mut self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Self {
+        self.maintenance_windows.push(MaintenanceWindow { start, end });
+        self
+    }
+
+    pub fn is_trading_date(&self, date: NaiveDate) -> bool {
+        !self.holidays.contains(&date)
+            && self.sessions.iter().any(|s| s.days.contains(&date.weekday()))
+    }
+
+    /// Open and close of the session(s) for a trading date, ignoring breaks.
+    pub fn session_bounds(
+        &self,
+        date: NaiveDate,
+    ) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        self.raw_sessions(date)
+            .into_iter()
+            .map(|(open, close, _)| (open, close))
+            .reduce(|(open, close), (o, c)| (open.min(o), close.max(c)))
+    }
+
+    pub fn session_open(&self, date: NaiveDate) -> Option<DateTime<Utc>> {
+        self.session_bounds(date).map(|(open, _)| open)
+    }
+
+    pub fn session_close(&self, date: NaiveDate) -> Option<DateTime<Utc>> {
+        self.session_bounds(date).map(|(_, close)| close)
+    }
+
+    /// Periods during which the market is actually open on a trading date,
+    /// i.e. the sessions minus breaks and maintenance windows.
+    pub fn open_intervals(&self, date: NaiveDate) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let mut intervals = vec![];
+        for (open, close, breaks) in self.raw_sessions(date) {
+            let mut session = vec![(open, close)];
+            let closed = breaks.into_iter().chain(
+                self.maintenance_windows.iter().map(|window| (window.start, window.end)),
+            );
+            for (start, end) in closed {
+                session = session
+                    .into_iter()
+                    .flat_map(|(a, b)| {
+                        [(a, b.min(start)), (a.max(end), b)]
+                            .into_iter()
+                            .filter(|(a, b)| a < b)
+                            .collect::<Vec<_>>()
+                    })
+                    .collect();
+            }
+            intervals.extend(session);
+        }
+        intervals.sort();
+        intervals
+    }
+
+    pub fn is_open(&self, t: DateTime<Utc>) -> bool {
+        self.candidate_dates(t)
+            .any(|date| self.open_intervals(date).iter().any(|(a, b)| *a <= t && t < *b))
+    }
+
+    /// Trading date of the session in progress at `t`, including during
+    /// breaks; outside of any session, the trading date of the next one.
+    pub fn session_date(&self, t: DateTime<Utc>) -> Option<NaiveDate> {
+        self.dates_from(t)
+            .find(|date| self.session_bounds(*date).is_some_and(|(_, close)| t < close))
+    }
+
+    /// Next time after `t` that the market opens, including reopening
+    /// after a break or maintenance window.
+    pub fn next_open(&self, t: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.dates_from(t).find_map(|date| {
+            self.open_intervals(date).into_iter().map(|(a, _)| a).find(|a| *a > t)
+        })
+    }
+
+    /// Next time after `t` that the market closes, including for a break
+    /// or maintenance window.
+    pub fn next_close(&self, t: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.dates_from(t).find_map(|date| {
+            self.open_intervals(date).into_iter().map(|(_, b)| b).find(|b| *b > t)
+        })
+    }
+
+    /// When an order placed at `placed_at` with the given time in force
+    /// expires; None for orders that don't expire or if no session is
+    /// found.  `AtTheOpen` and `AtTheClose` orders expire at the auction
+    /// they participate in.
+    pub fn time_in_force_expiry(
+        &self,
+        time_in_force: &TimeInForce,
+        placed_at: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        match time_in_force {
+            TimeInForce::GoodTilCancel => None,
+            TimeInForce::GoodTilDate(expiry) => Some(*expiry),
+            TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill => Some(placed_at),
+            TimeInForce::GoodTilDay | TimeInForce::AtTheClose => {
+                self.session_close(self.session_date(placed_at)?)
+            }
+            TimeInForce::AtTheOpen => self.dates_from(placed_at).find_map(|date| {
+                self.session_open(date).filter(|open| *open > placed_at)
+            }),
+        }
+    }
+
+    /// (open, close, breaks) in UTC for each session on a trading date
+    #[allow(clippy::type_complexity)]
+    fn raw_sessions(
+        &self,
+        date: NaiveDate,
+    ) -> Vec<(DateTime<Utc>, DateTime<Utc>, Vec<(DateTime<Utc>, DateTime<Utc>)>)> {
+        if self.holidays.contains(&date) {
+            return vec![];
+        }
+        let Some(prev) = date.pred_opt() else {
+            return vec![];
+        };
+        let local =
+            |date: NaiveDate, time| resolve_local(&self.time_zone, date.and_time(time));
+        let mut sessions = vec![];
+        for session in &self.sessions {
+            if !session.days.contains(&date.weekday()) {
+                continue;
+            }
+            let open_date = if session.is_overnight() { prev } else { date };
+            let close_time = match self.early_closes.get(&date) {
+                Some(early) => *early,
+                None => session.close,
+            };
+            let (Some(open), Some(close)) =
+                (local(open_date, session.open), local(date, close_time))
+            else {
+                continue;
+            };
+            if close <= open {
+                continue;
+            }
+            let breaks = session
+                .breaks
+                .iter()
+                .filter_map(|(start, end)| {
+                    let start_date = if session.is_overnight() && *start >= session.open {
+                        prev
+                    } else {
+                        date
+                    };
+                    let end_date =
+                        if end < start { start_date.succ_opt()? } else { start_date };
+                    Some((local(start_date, *start)?, local(end_date, *end)?))
+                })
+                .collect();
+            sessions.push((open, close, breaks));
+        }
+        sessions
+    }
+
+    /// Trading dates whose sessions could contain `t`.
+    fn candidate_dates(&self, t: DateTime<Utc>) -> impl Iterator<Item = NaiveDate> {
+        let local = t.with_timezone(&self.time_zone).date_naive();
+        [local.pred_opt(), Some(local), local.succ_opt()].into_iter().flatten()
+    }
+
+    /// Trading dates in order, starting with the earliest that could
+    /// contain `t`.
+    fn dates_from(&self, t: DateTime<Utc>) -> impl Iterator<Item = NaiveDate> {
+        let start = self.candidate_dates(t).next();
+        (0..MAX_SEARCH_DAYS).filter_map(move |i| start?.checked_add_days(Days::new(i)))
+    }
+}
+
+/// Trading calendars by venue, with per-product overrides.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TradingCalendars {
+    pub venues: BTreeMap<ExecutionVenue, TradingCalendar>,
+    pub products: BTreeMap<Product, TradingCalendar>,
+}
+
+impl TradingCalendars {
+    pub fn get(
+        &self,
+        venue: &ExecutionVenue,
+        product: &Product,
+    ) -> Option<&TradingCalendar> {
+        self.products.get(product).or_else(|| self.venues.get(venue))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn hm(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    fn date(m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, m, d).unwrap()
+    }
+
+    fn utc(m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn test_overnight_sessions() {
+        // CME Globex equity futures, Chicago time
+        let calendar = TradingCalendar::new(
+            chrono_tz::America::Chicago,
+            vec![TradingSession::weekdays(hm(17, 0), hm(16, 0))
+                .with_break(hm(15, 15), hm(15, 30))],
+        )
+        .with_holidays([date(12, 25)])
+        .with_early_close(date(11, 28), hm(12, 15));
+        // Monday's session opens Sunday 17:00 CST (23:00 UTC)
+        assert_eq!(calendar.session_open(date(12, 1)), Some(utc(11, 30, 23, 0)));
+        assert!(calendar.is_open(utc(12, 1, 1, 0)));
+        assert!(!calendar.is_open(utc(12, 1, 21, 20)));
+        assert!(!calendar.is_open(utc(12, 1, 22, 30)));
+        assert_eq!(calendar.session_date(utc(12, 1, 23, 30)), Some(date(12, 2)));
+        assert_eq!(calendar.next_open(utc(12, 1, 21, 20)), Some(utc(12, 1, 21, 30)));
+        // Friday close to Monday's session, which opens on Sunday
+        assert_eq!(calendar.session_date(utc(12, 6, 12, 0)), Some(date(12, 8)));
+        assert_eq!(calendar.next_open(utc(12, 5, 23, 0)), Some(utc(12, 7, 23, 0)));
+        // no session for the Christmas trading date
+        assert!(!calendar.is_open(utc(12, 25, 12, 0)));
+        assert_eq!(calendar.session_date(utc(12, 24, 23, 0)), Some(date(12, 26)));
+        // day-after-Thanksgiving early close
+        assert_eq!(calendar.session_close(date(11, 28)), Some(utc(11, 28, 18, 15)));
+        assert_eq!(
+            calendar.time_in_force_expiry(&TimeInForce::GoodTilDay, utc(11, 28, 15, 0)),
+            Some(utc(11, 28, 18, 15))
+        );
+        assert_eq!(
+            calendar.time_in_force_expiry(&TimeInForce::AtTheOpen, utc(11, 28, 15, 0)),
+            Some(utc(11, 30, 23, 0))
+        );
+    }
+
+    #[test]
+    fn test_day_sessions_and_maintenance() {
+        let calendar = TradingCalendar::new(
+            chrono_tz::America::New_York,
+            vec![TradingSession::weekdays(hm(9, 30), hm(16, 0))],
+        )
+        .with_maintenance_window(utc(3, 10, 15, 0), utc(3, 10, 15, 30));
+        // first trading day after the DST change, 9:30 EDT
+        assert_eq!(calendar.session_open(date(3, 10)), Some(utc(3, 10, 13, 30)));
+        assert!(calendar.is_open(utc(3, 10, 14, 0)));
+        assert!(!calendar.is_open(utc(3, 10, 15, 10)));
+        assert_eq!(calendar.next_close(utc(3, 10, 14, 0)), Some(utc(3, 10, 15, 0)));
+        assert_eq!(calendar.next_open(utc(3, 10, 15, 10)), Some(utc(3, 10, 15, 30)));
+        assert_eq!(calendar.session_date(utc(3, 10, 21, 0)), Some(date(3, 11)));
+        assert_eq!(
+            calendar.time_in_force_expiry(&TimeInForce::AtTheClose, utc(3, 10, 12, 0)),
+            Some(utc(3, 10, 20, 0))
+        );
+    }
+}
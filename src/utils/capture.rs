@@ -0,0 +1,560 @@
+//! Append-only binary capture files for recording API streams.
+//!
+//! A capture file is the magic bytes [`CAPTURE_MAGIC`] followed by framed
+//! records.  Each record is (little endian):
+//!
+//! ```text
+//! u32     frame length, not including this field
+//! u8      kind, see CaptureKind
+//! u8      flags; bit 0 set if the record has a sequence
+//! i64     timestamp
+//! u32     timestamp_ns
+//! u64     sequence_id
+//! u64     sequence_number
+//! u16     symbol length in bytes, zero if none
+//! [u8]    symbol, utf-8
+//! [u8]    payload, msgpack
+//! ```
+//!
+//! Payloads are encoded with `rmp_serde::encode::write`, the same encoding
+//! the msgpack gRPC codec uses, so they share the types' serde impls.
+
+use crate::{
+    marketdata::{L2BookUpdate, TickerUpdate},
+    orderflow::{Dropcopy, Orderflow},
+    SequenceIdAndNumber,
+};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+pub const CAPTURE_MAGIC: &[u8; 8] = b"ARCHCAP\x01";
+
+/// Size of the fixed part of a frame after the length field
+const FRAME_HEADER_LEN: usize = 1 + 1 + 8 + 4 + 8 + 8 + 2;
+const FLAG_HAS_SEQUENCE: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum CaptureKind {
+    Orderflow = 1,
+    Dropcopy = 2,
+    L2BookUpdate = 3,
+    TickerUpdate = 4,
+}
+
+impl TryFrom<u8> for CaptureKind {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        Ok(match value {
+            1 => Self::Orderflow,
+            2 => Self::Dropcopy,
+            3 => Self::L2BookUpdate,
+            4 => Self::TickerUpdate,
+            _ => bail!("unknown capture kind: {value}"),
+        })
+    }
+}
+
+/// A stream message type that can be captured.
+pub trait Capture: Serialize + DeserializeOwned {
+    const KIND: CaptureKind;
+
+    /// The message's own timestamp, if it carries one
+    fn capture_timestamp(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    fn capture_sequence(&self) -> Option<SequenceIdAndNumber> {
+        None
+    }
+
+    fn capture_symbol(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Capture for Orderflow {
+    const KIND: CaptureKind = CaptureKind::Orderflow;
+}
+
+impl Capture for Dropcopy {
+    const KIND: CaptureKind = CaptureKind::Dropcopy;
+
+    fn capture_timestamp(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Dropcopy::Order(order) => order.recv_time(),
+            Dropcopy::Fill(fill) => fill.trade_time(),
+            Dropcopy::AberrantFill(fill) => fill.trade_time(),
+        }
+    }
+
+    fn capture_symbol(&self) -> Option<&str> {
+        match self {
+            Dropcopy::Order(order) => Some(&order.symbol),
+            Dropcopy::Fill(fill) => Some(&fill.symbol),
+            Dropcopy::AberrantFill(fill) => fill.symbol.as_deref(),
+        }
+    }
+}
+
+impl Capture for L2BookUpdate {
+    const KIND: CaptureKind = CaptureKind::L2BookUpdate;
+
+    fn capture_timestamp(&self) -> Option<DateTime<Utc>> {
+        self.timestamp()
+    }
+
+    fn capture_sequence(&self) -> Option<SequenceIdAndNumber> {
+        Some(self.sequence())
+    }
+}
+
+impl Capture for TickerUpdate {
+    const KIND: CaptureKind = CaptureKind::TickerUpdate;
+
+    fn capture_timestamp(&self) -> Option<DateTime<Utc>> {
+        match self {
+            TickerUpdate::Snapshot(ticker) | TickerUpdate::Diff(ticker) => {
+                ticker.timestamp()
+            }
+        }
+    }
+
+    fn capture_symbol(&self) -> Option<&str> {
+        match self {
+            TickerUpdate::Snapshot(ticker) | TickerUpdate::Diff(ticker) => {
+                Some(&ticker.symbol)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    /// Byte offset of the record in the file
+    pub offset: u64,
+    pub kind: CaptureKind,
+    pub timestamp: i64,
+    pub timestamp_ns: u32,
+    pub sequence: Option<SequenceIdAndNumber>,
+    pub symbol: Option<String>,
+    pub payload: Vec<u8>,
+}
+
+impl CaptureRecord {
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(self.timestamp, self.timestamp_ns)
+    }
+
+    pub fn decode<T: Capture>(&self) -> Result<T> {
+        if self.kind != T::KIND {
+            bail!("expected {:?} record, found {:?}", T::KIND, self.kind);
+        }
+        Ok(rmp_serde::decode::from_slice(&self.payload)?)
+    }
+}
+
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+    /// Offset of the next record
+    offset: u64,
+    buf: Vec<u8>,
+}
+
+impl CaptureWriter<BufWriter<File>> {
+    /// Create a new capture file, truncating any existing file.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+
+    /// Open a capture file for appending, creating it if necessary.
+    ///
+    /// A partially written record at the end of the file, e.g. from a
+    /// writer that crashed, is truncated before appending.
+    pub fn append(path: impl AsRef<Path>) -> Result<Self> {
+        let mut file =
+            OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        let len = file.metadata()?.len();
+        if len == 0 {
+            return Self::new(BufWriter::new(file));
+        }
+        let mut magic = [0u8; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != CAPTURE_MAGIC {
+            bail!("not a capture file");
+        }
+        let end = complete_frames_end(&mut file, len)?;
+        if end < len {
+            file.set_len(end)?;
+        }
+        Ok(Self { writer: BufWriter::new(file), offset: end, buf: vec![] })
+    }
+}
+
+/// Offset just past the last complete record of a capture of `len` bytes.
+fn complete_frames_end(reader: &mut (impl Read + Seek), len: u64) -> Result<u64> {
+    let mut offset = CAPTURE_MAGIC.len() as u64;
+    let mut len_bytes = [0u8; 4];
+    while offset + 4 <= len {
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut len_bytes)?;
+        let frame_len = u32::from_le_bytes(len_bytes) as u64;
+        if frame_len < FRAME_HEADER_LEN as u64 {
+            bail!("corrupt capture record at offset {offset}");
+        }
+        if offset + 4 + frame_len > len {
+            break;
+        }
+        offset += 4 + frame_len;
+    }
+    Ok(offset)
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Start a new capture, writing the magic bytes.
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(CAPTURE_MAGIC)?;
+        Ok(Self { writer, offset: CAPTURE_MAGIC.len() as u64, buf: vec![] })
+    }
+
+    /// Append a message, returning the offset of its record.  The record
+    /// is timestamped with the message's own timestamp if it has one,
+    /// otherwise with `recv_time`.
+    pub fn write<T: Capture>(
+        &mut self,
+        item: &T,
+        recv_time: DateTime<Utc>,
+    ) -> Result<u64> {
+        let symbol = item.capture_symbol().map(str::to_string);
+        self.write_for_symbol(symbol.as_deref(), item, recv_time)
+    }
+
+    /// Like [`Self::write`] but with an explicit symbol, for messages that
+    /// don't carry their own, e.g. [`L2BookUpdate`].
+    pub fn write_for_symbol<T: Capture>(
+        &mut self,
+        symbol: Option<&str>,
+        item: &T,
+        recv_time: DateTime<Utc>,
+    ) -> Result<u64> {
+        let timestamp = item.capture_timestamp().unwrap_or(recv_time);
+        let sequence = item.capture_sequence();
+        let symbol = symbol.unwrap_or("").as_bytes();
+        if symbol.len() > u16::MAX as usize {
+            bail!("symbol too long");
+        }
+        self.buf.clear();
+        rmp_serde::encode::write(&mut self.buf, item)?;
+        let frame_len = FRAME_HEADER_LEN + symbol.len() + self.buf.len();
+        let Ok(frame_len) = u32::try_from(frame_len) else {
+            bail!("record too large: {frame_len} bytes");
+        };
+        let seq = sequence.unwrap_or_default();
+        let flags = if sequence.is_some() { FLAG_HAS_SEQUENCE } else { 0 };
+        let w = &mut self.writer;
+        w.write_all(&frame_len.to_le_bytes())?;
+        w.write_all(&[T::KIND as u8, flags])?;
+        w.write_all(&timestamp.timestamp().to_le_bytes())?;
+        w.write_all(&timestamp.timestamp_subsec_nanos().to_le_bytes())?;
+        w.write_all(&seq.sequence_id.to_le_bytes())?;
+        w.write_all(&seq.sequence_number.to_le_bytes())?;
+        w.write_all(&(symbol.len() as u16).to_le_bytes())?;
+        w.write_all(symbol)?;
+        w.write_all(&self.buf)?;
+        let offset = self.offset;
+        self.offset += 4 + frame_len as u64;
+        Ok(offset)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+pub struct CaptureReader<R: Read + Seek> {
+    reader: R,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> CaptureReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut magic)?;
+        if &magic != CAPTURE_MAGIC {
+            bail!("not a capture file");
+        }
+        Ok(Self { reader })
+    }
+
+    /// Seek to a record offset, e.g. one returned by the writer or index.
+    pub fn seek(&mut self, offset: u64) -> Result<()> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        Ok(())
+    }
+
+    pub fn rewind(&mut self) -> Result<()> {
+        self.seek(CAPTURE_MAGIC.len() as u64)
+    }
+
+    /// Read the next record, or None at the end of the file.
+    ///
+    /// A partially written record at the end of the file is treated as the
+    /// end; the reader stays positioned before it so that reading again
+    /// after the writer finishes picks it up.
+    pub fn read_record(&mut self) -> Result<Option<CaptureRecord>> {
+        let offset = self.reader.stream_position()?;
+        match self.read_record_at(offset) {
+            Err(e)
+                if e.downcast_ref::<std::io::Error>()
+                    .is_some_and(|e| e.kind() == ErrorKind::UnexpectedEof) =>
+            {
+                self.seek(offset)?;
+                Ok(None)
+            }
+            r => r.map(Some),
+        }
+    }
+
+    fn read_record_at(&mut self, offset: u64) -> Result<CaptureRecord> {
+        let mut len = [0u8; 4];
+        self.reader.read_exact(&mut len)?;
+        let frame_len = u32::from_le_bytes(len) as usize;
+        if frame_len < FRAME_HEADER_LEN {
+            bail!("corrupt capture record at offset {offset}");
+        }
+        // grow with the data actually present rather than trusting the
+        // length field with an up-front allocation
+        let mut frame = Vec::new();
+        (&mut self.reader).take(frame_len as u64).read_to_end(&mut frame)?;
+        if frame.len() < frame_len {
+            return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+        }
+        let (header, rest) = frame.split_at(FRAME_HEADER_LEN);
+        let kind = CaptureKind::try_from(header[0])?;
+        let flags = header[1];
+        let le_u64 = |i: usize| u64::from_le_bytes(header[i..i + 8].try_into().unwrap());
+        let timestamp = le_u64(2) as i64;
+        let timestamp_ns = u32::from_le_bytes(header[10..14].try_into().unwrap());
+        let sequence = (flags & FLAG_HAS_SEQUENCE != 0).then(|| SequenceIdAndNumber {
+            sequence_id: le_u64(14),
+            sequence_number: le_u64(22),
+        });
+        let symbol_len = u16::from_le_bytes(header[30..32].try_into().unwrap()) as usize;
+        if symbol_len > rest.len() {
+            bail!("corrupt capture record at offset {offset}");
+        }
+        let (symbol, payload) = rest.split_at(symbol_len);
+        let symbol = match symbol_len {
+            0 => None,
+            _ => Some(std::str::from_utf8(symbol)?.to_string()),
+        };
+        Ok(CaptureRecord {
+            offset,
+            kind,
+            timestamp,
+            timestamp_ns,
+            sequence,
+            symbol,
+            payload: payload.to_vec(),
+        })
+    }
+
+    /// Read all records with timestamps in `[start, end)`, using the index
+    /// to skip blocks outside the range.
+    pub fn read_range(
+        &mut self,
+        index: &CaptureIndex,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<CaptureRecord>> {
+        let start = (start.timestamp(), start.timestamp_subsec_nanos());
+        let end = (end.timestamp(), end.timestamp_subsec_nanos());
+        let mut records = vec![];
+        for block in index.blocks.iter().filter(|b| b.min < end && b.max >= start) {
+            self.seek(block.offset)?;
+            for _ in 0..block.count {
+                let Some(record) = self.read_record()? else { break };
+                let key = (record.timestamp, record.timestamp_ns);
+                if start <= key && key < end {
+                    records.push(record);
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+impl<R: Read + Seek> Iterator for CaptureReader<R> {
+    type Item = Result<CaptureRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Sparse index over a capture file: consecutive runs of records with
+/// their time bounds.  Records within a file need not be in time order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureIndex {
+    pub block_size: usize,
+    pub blocks: Vec<CaptureIndexBlock>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureIndexBlock {
+    pub offset: u64,
+    pub count: usize,
+    /// (timestamp, timestamp_ns)
+    pub min: (i64, u32),
+    pub max: (i64, u32),
+}
+
+impl CaptureIndex {
+    pub fn new(block_size: usize) -> Self {
+        Self { block_size: block_size.max(1), blocks: vec![] }
+    }
+
+    /// Scan a capture from the start, indexing every record.
+    pub fn build<R: Read + Seek>(
+        reader: &mut CaptureReader<R>,
+        block_size: usize,
+    ) -> Result<Self> {
+        let mut index = Self::new(block_size);
+        reader.rewind()?;
+        while let Some(record) = reader.read_record()? {
+            index.push(record.offset, (record.timestamp, record.timestamp_ns));
+        }
+        Ok(index)
+    }
+
+    /// Add a record; offsets must be pushed in file order.
+    pub fn push(&mut self, offset: u64, timestamp: (i64, u32)) {
+        match self.blocks.last_mut() {
+            Some(block) if block.count < self.block_size => {
+                block.count += 1;
+                block.min = block.min.min(timestamp);
+                block.max = block.max.max(timestamp);
+            }
+            _ => self.blocks.push(CaptureIndexBlock {
+                offset,
+                count: 1,
+                min: timestamp,
+                max: timestamp,
+            }),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        rmp_serde::encode::write(&mut writer, self)?;
+        Ok(writer.flush()?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(rmp_serde::decode::from_read(BufReader::new(File::open(path)?))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{orderflow::OrderAck, OrderId};
+    use std::io::Cursor;
+
+    fn l2(ts: i64, sn: u64) -> L2BookUpdate {
+        serde_json::from_str(&format!(
+            r#"{{"t": "d", "ts": {ts}, "tn": 0, "sid": 7, "sn": {sn},
+                "b": [["99.5", "1"]], "a": []}}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_capture_roundtrip() {
+        let recv_time = DateTime::from_timestamp(1729700000, 5).unwrap();
+        let orderflow = Orderflow::OrderAck(OrderAck {
+            order_id: OrderId::nil(5),
+            exchange_order_id: Some("x".to_string()),
+        });
+        let mut writer = CaptureWriter::new(Cursor::new(vec![])).unwrap();
+        let mut index = CaptureIndex::new(2);
+        for ts in 1..=5 {
+            let offset = writer
+                .write_for_symbol(Some("BTC Crypto/USD"), &l2(ts, ts as u64), recv_time)
+                .unwrap();
+            index.push(offset, (ts, 0));
+        }
+        let of_offset = writer.write(&orderflow, recv_time).unwrap();
+        index.push(of_offset, (recv_time.timestamp(), 5));
+        let mut bytes = writer.into_inner().into_inner();
+        // simulate a torn write at the end
+        bytes.extend_from_slice(&[40, 0, 0, 0, 1]);
+
+        let mut reader = CaptureReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(CaptureIndex::build(&mut reader, 2).unwrap(), index);
+        reader.rewind().unwrap();
+        let records: Vec<CaptureRecord> = reader.by_ref().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 6);
+        assert_eq!(records[0].symbol.as_deref(), Some("BTC Crypto/USD"));
+        assert_eq!(records[0].sequence, Some(SequenceIdAndNumber::new(7, 1)));
+        let update: L2BookUpdate = records[4].decode().unwrap();
+        assert_eq!(update.sequence(), SequenceIdAndNumber::new(7, 5));
+        assert!(records[4].decode::<Orderflow>().is_err());
+
+        reader.seek(of_offset).unwrap();
+        let record = reader.read_record().unwrap().unwrap();
+        assert_eq!(record.timestamp(), Some(recv_time));
+        assert_eq!(record.sequence, None);
+        assert_eq!(
+            record.decode::<Orderflow>().unwrap().order_id(),
+            orderflow.order_id()
+        );
+
+        let start = DateTime::from_timestamp(2, 0).unwrap();
+        let end = DateTime::from_timestamp(4, 0).unwrap();
+        let in_range = reader.read_range(&index, start, end).unwrap();
+        let timestamps: Vec<i64> = in_range.iter().map(|r| r.timestamp).collect();
+        assert_eq!(timestamps, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_append_after_torn_write() -> Result<()> {
+        let recv_time = DateTime::from_timestamp(1729700000, 0).unwrap();
+        let path = std::env::temp_dir().join(format!("{}.cap", uuid::Uuid::new_v4()));
+        let mut writer = CaptureWriter::create(&path)?;
+        writer.write_for_symbol(None, &l2(1, 1), recv_time)?;
+        writer.flush()?;
+        drop(writer);
+        // a torn frame claiming more bytes than were written
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        file.write_all(&[0, 0, 0, 0x7f, 1, 0])?;
+        drop(file);
+        let mut writer = CaptureWriter::append(&path)?;
+        writer.write_for_symbol(None, &l2(2, 2), recv_time)?;
+        writer.flush()?;
+        drop(writer);
+        let reader = CaptureReader::open(&path)?;
+        let timestamps: Vec<i64> =
+            reader.map(|r| r.map(|r| r.timestamp)).collect::<Result<_>>()?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(timestamps, vec![1, 2]);
+        Ok(())
+    }
+}